@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+
+/// OCF 容器文件 `META-INF/container.xml` 中 rootfile 的默认媒体类型
+pub const MEDIA_TYPE_OPF: &str = "application/oebps-package+xml";
+
+/// A struct representing the OCF container document (`META-INF/container.xml`).
+#[derive(Debug, Default)]
+pub struct Container {
+    rootfiles: Vec<RootFile>,
+}
+
+impl Container {
+    pub fn new() -> Self {
+        Container {
+            rootfiles: Vec::new(),
+        }
+    }
+    /// Add a rootfile to the container, the first one is the default rendition
+    pub fn add_rootfile(&mut self, rootfile: RootFile) -> &mut Self {
+        self.rootfiles.push(rootfile);
+        self
+    }
+    /// All rootfiles of the container
+    pub fn rootfiles(&self) -> &[RootFile] {
+        &self.rootfiles
+    }
+
+    /// Decode a container document
+    pub fn decode_xml(xml: &str) -> Result<Container, super::Error> {
+        let xml: ContainerXml = quick_xml::de::from_str(xml).map_err(|e| {
+            super::Error::DeserializeErr(format!("Invalid container document:{}", e))
+        })?;
+        Ok(Container {
+            rootfiles: xml
+                .rootfiles
+                .items
+                .into_iter()
+                .map(|r| RootFile::new(r.full_path, r.media_type))
+                .collect(),
+        })
+    }
+
+    /// Encode the container document
+    pub fn encode_xml(&self) -> Result<String, super::Error> {
+        let xml = ContainerXml {
+            version: String::from("1.0"),
+            xmlns: String::from("urn:oasis:names:tc:opendocument:xmlns:container"),
+            rootfiles: RootFilesXml {
+                items: self
+                    .rootfiles
+                    .iter()
+                    .map(|r| RootFileXml {
+                        full_path: r.full_path.clone(),
+                        media_type: r.media_type.clone(),
+                    })
+                    .collect(),
+            },
+        };
+        let ret = super::encode_xml(&xml)?;
+        Ok(format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n{}",
+            ret
+        ))
+    }
+}
+
+/// container rootfile
+#[derive(Debug, Clone, PartialEq)]
+pub struct RootFile {
+    /// 包文件相对于容器根目录的路径
+    pub full_path: String,
+    /// 包文件的媒体类型
+    pub media_type: String,
+}
+
+impl RootFile {
+    /// Create a new rootfile
+    pub fn new<S1: Into<String>, S2: Into<String>>(full_path: S1, media_type: S2) -> RootFile {
+        RootFile {
+            full_path: full_path.into(),
+            media_type: media_type.into(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename = "container", default)]
+struct ContainerXml {
+    #[serde(rename = "@version")]
+    version: String,
+    #[serde(rename = "@xmlns")]
+    xmlns: String,
+    #[serde(rename = "rootfiles")]
+    rootfiles: RootFilesXml,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RootFilesXml {
+    #[serde(rename = "rootfile", default)]
+    items: Vec<RootFileXml>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct RootFileXml {
+    #[serde(rename = "@full-path")]
+    full_path: String,
+    #[serde(rename = "@media-type")]
+    media_type: String,
+}
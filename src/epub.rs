@@ -1,11 +1,24 @@
+use crate::container::{Container, RootFile, MEDIA_TYPE_OPF};
 use crate::epub::EpubVersion::{V20, V30};
-use crate::mime::first;
-use crate::toc::{TocElement, TocNav};
-use crate::xhtml::{XHtmlLinkItem, XHtmlRoot};
-use crate::{write, Error};
+use crate::mime::{first, first_or_default};
+use crate::opf::{
+    format_uuid, replace_series, Collection, Creator, Date, GuideReference, Identifier,
+    ManifestItem, MetaItem, Metadata, Package, SpineItemRef, Title,
+};
+use crate::reader::{percent_decode, resolve_path, Book};
+use crate::toc::{Landmark, PageTarget, TocElement, TocNav};
+use crate::validate::{validate_book, Diagnostic, DiagnosticKind, Location, Report, Severity};
+use crate::write::{
+    DirectoryStorage, ObservedStorage, Progress, ProgressObserver, Storage, ZipStorage,
+};
+use crate::xhtml::{check_body, repair_body};
+use crate::xhtml::{
+    css_references, rewrite_css_urls, rewrite_references, XHtmlLinkItem, XHtmlRoot,
+};
+use crate::{markdown, markup, Error, MediaSource};
 use dashmap::{DashMap, DashSet};
 use std::cmp::PartialEq;
-use std::fs;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 use std::time::SystemTime;
@@ -43,6 +56,14 @@ pub(crate) const META_INF_FOLDER_NAME: &str = "META-INF";
 pub(crate) const CONTAINER_FILENAME: &str = "container.xml";
 #[allow(dead_code)]
 pub(crate) const PKG_FILENAME: &str = "content.opf";
+pub(crate) const MIMETYPE_FILENAME: &str = "mimetype";
+pub(crate) const BOOK_ID: &str = "BookId";
+/// zip 文件能表示的最早时间 1980-01-01T00:00:00Z
+const ZIP_EPOCH: i64 = 315_532_800;
+pub(crate) const NCX_FILENAME: &str = "toc.ncx";
+pub(crate) const NAV_FILENAME: &str = "nav.xhtml";
+/// 可复现构建使用的时间戳环境变量
+pub(crate) const SOURCE_DATE_EPOCH: &str = "SOURCE_DATE_EPOCH";
 
 #[allow(dead_code)]
 pub(crate) const COVER_CSS_CONTENT: &str = "\
@@ -64,45 +85,59 @@ pub(crate) const COVER_FILE_NAME: &str = "cover.xhtml";
 pub(crate) const COVER_CSS_FILE: &str = "cover.css";
 
 /// epub规范版本
-#[derive(Debug, PartialEq,Copy)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 #[allow(dead_code)]
 pub enum EpubVersion {
     V20,
     V30,
 }
 
-impl Clone for EpubVersion {
-    fn clone(&self) -> Self {
-        match self {
-            EpubVersion::V20 => EpubVersion::V20,
-            EpubVersion::V30 => EpubVersion::V30,
-        }
-    }
+/// How section bodies are checked when they are added, bodies are not checked by default
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum BodyCheck {
+    /// Bodies are written as given
+    #[default]
+    Off,
+    /// Bodies that are not well-formed XHTML are rejected with [`Error::MalformedXhtmlErr`]
+    Reject,
+    /// Common problems are repaired with [`repair_body`], remaining problems are rejected
+    Repair,
 }
+
 /// An epub file structure instance
 #[derive(Debug)]
 #[allow(dead_code)]
 pub struct EpubBuilder {
     /// Book title
     title: String,
-    /// Book creator
-    creator: Vec<String>,
-    /// Book subject
-    subject: Option<String>,
+    /// All titles with their types and alternate scripts
+    titles: Vec<Title>,
+    /// Book creators
+    creator: Vec<Creator>,
+    /// Book subjects
+    subject: Vec<String>,
     /// Book description
     description: Option<String>,
-    /// Book date
-    date: Option<SystemTime>,
+    /// Publication date
+    date: Option<Date>,
+    /// Creation date
+    created: Option<Date>,
+    /// Last modification date, `dcterms:modified`
+    modified: Option<SystemTime>,
     /// Book category
     category: Option<String>,
     /// Book Publishers
     publisher: Option<String>,
-    /// Book contributor
-    contributor: Option<String>,
+    /// Book contributors, e.g. translators and illustrators
+    contributor: Vec<Creator>,
+    /// Series and sets the book belongs to
+    collection: Vec<Collection>,
     /// Book format
     format: Option<String>,
-    /// Book identifier
-    identifier: Option<String>,
+    /// Book identifier, referenced by `unique-identifier`
+    identifier: Option<Identifier>,
+    /// Other identifiers such as an ISBN
+    other_identifiers: Vec<Identifier>,
     /// Book source
     source: Option<String>,
     /// Book language
@@ -119,17 +154,22 @@ pub struct EpubBuilder {
 
     /// Book other metadata
     metadata: Option<DashMap<String, String>>,
+    /// EPUB 3 `meta` elements with a `property` kept from an opened book, e.g. `rendition:layout`
+    properties: Vec<MetaItem>,
 
     /// Custom style sheet collection
-    stylesheet: DashMap<String, String>,
+    stylesheet: DashMap<String, MediaSource>,
     /// Custom font collection
-    fonts: DashMap<String, String>,
+    fonts: DashMap<String, MediaSource>,
     /// Books Picture Collection
-    images: DashMap<String, String>,
+    images: DashMap<String, MediaSource>,
     /// Books Video Collection
-    videos: DashMap<String, String>,
+    videos: DashMap<String, MediaSource>,
     /// Books Audio Collection
-    audios: DashMap<String, String>,
+    audios: DashMap<String, MediaSource>,
+    /// Other resources kept from an opened book, e.g. scripts and documents outside the spine,
+    /// keyed by their path inside the package folder
+    resources: DashMap<String, Resource>,
 
     /// Book section collection
     sections: Vec<Section>,
@@ -137,8 +177,47 @@ pub struct EpubBuilder {
     /// Internal file name collection
     filenames: DashSet<String>,
 
+    /// Extra rootfiles for multiple-rendition books
+    rootfiles: Vec<RootFile>,
+
     /// Epub version
     version: EpubVersion,
+
+    /// Reproducible build mode, honours `SOURCE_DATE_EPOCH`
+    reproducible: bool,
+
+    /// Progress observer notified while writing
+    progress: Option<ProgressObserver>,
+
+    /// Well-formedness check of section bodies
+    body_check: BodyCheck,
+
+    /// Deepest heading level added to the table of contents, 0 for none
+    toc_heading_depth: usize,
+
+    /// Table of contents supplied by the caller instead of the generated one
+    toc: Option<TocNav>,
+
+    /// Edits the table of contents before it is written
+    toc_editor: Option<TocEditor>,
+
+    /// Landmarks added to the navigation document
+    landmarks: Vec<Landmark>,
+
+    /// Titles of the automatic landmarks by kind, overriding the ones for the book language
+    landmark_titles: HashMap<String, String>,
+
+    /// Print pages added to the page list
+    pages: Vec<PageTarget>,
+}
+
+/// 写入前修改目录的回调
+struct TocEditor(Box<dyn Fn(&mut TocNav) + Send + Sync>);
+
+impl std::fmt::Debug for TocEditor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("TocEditor")
+    }
 }
 
 impl Default for EpubBuilder {
@@ -147,43 +226,385 @@ impl Default for EpubBuilder {
     }
 }
 
-
-
 impl EpubBuilder {
     #[allow(dead_code)]
     pub fn new<S: Into<String>>(title: S, ver: EpubVersion) -> EpubBuilder {
         EpubBuilder {
             title: title.into(),
+            titles: Vec::new(),
             creator: Vec::new(),
-            subject: None,
+            subject: Vec::new(),
             description: None,
             date: None,
+            created: None,
+            modified: None,
             category: None,
             publisher: None,
-            contributor: None,
+            contributor: Vec::new(),
+            collection: Vec::new(),
             format: None,
             identifier: None,
+            other_identifiers: Vec::new(),
             source: None,
             language: None,
             relation: None,
             coverage: None,
             rights: None,
             metadata: None,
+            properties: Vec::new(),
             cover: None,
             stylesheet: DashMap::new(),
             fonts: DashMap::new(),
             images: DashMap::new(),
             videos: DashMap::new(),
             audios: DashMap::new(),
+            resources: DashMap::new(),
             sections: Vec::new(),
             filenames: DashSet::new(),
+            rootfiles: Vec::new(),
             version: ver,
+            reproducible: false,
+            progress: None,
+            body_check: BodyCheck::default(),
+            toc_heading_depth: 0,
+            toc: None,
+            toc_editor: None,
+            landmarks: Vec::new(),
+            landmark_titles: HashMap::new(),
+            pages: Vec::new(),
+        }
+    }
+
+    /// Load an existing EPUB, either a `.epub` file or an exploded folder, for editing
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<EpubBuilder, Error> {
+        EpubBuilder::from_book(&Book::open(path)?)
+    }
+
+    /// Create a builder from an opened [`Book`]
+    ///
+    /// Spine documents become sections, nested and titled as in the table of contents.
+    /// Stylesheets, fonts, images, audio and video are copied into the media collections and
+    /// links between resources are rewritten to the builder layout; a resource whose content
+    /// needs no rewriting is kept byte for byte. Documents outside the spine, scripts and
+    /// resources of other media types keep their media type and path inside the package folder.
+    /// All metadata is kept, including EPUB 3 `meta` properties such as `rendition:layout`;
+    /// the table of contents and `dcterms:modified` are regenerated on output.
+    pub fn from_book(book: &Book) -> Result<EpubBuilder, Error> {
+        let mut builder = EpubBuilder::new(book.metadata().title.clone(), book.version());
+        builder.load_metadata(book.metadata());
+
+        let manifest = book.package().manifest();
+        let toc_paths: Vec<String> = manifest
+            .iter()
+            .filter(|m| m.media_type == MEDIA_TYPE_NCX || has_property(m, "nav"))
+            .map(|m| book.resolve_href(&m.href))
+            .collect();
+
+        // 原路径 -> 新路径，均为容器内的完整路径
+        let mut moved: HashMap<String, String> = HashMap::new();
+
+        let mut documents = Vec::new();
+        for item in book.spine_items() {
+            let full_path = book.resolve_href(&item.href);
+            if item.media_type != MEDIA_TYPE_XHTML
+                || toc_paths.contains(&full_path)
+                || moved.contains_key(&full_path)
+            {
+                continue;
+            }
+            let basename = full_path.rsplit('/').next().unwrap_or_default();
+            let stem = basename.rsplit_once('.').map_or(basename, |(stem, _)| stem);
+            let filename = unique_filename(&format!("{}.xhtml", stem), |name| {
+                builder.filenames.contains(name)
+            });
+            builder.filenames.insert(filename.clone());
+            moved.insert(
+                full_path.clone(),
+                format!("{}/{}/{}", CONTENT_FOLDER_NAME, XHTML_FOLDER_NAME, filename),
+            );
+            documents.push((full_path, filename));
+        }
+
+        // 其他资源保留在包文件目录中的相对路径，避开生成的文件
+        let package_folder = match book.package_path().rsplit_once('/') {
+            Some((folder, _)) => format!("{}/", folder),
+            None => String::new(),
+        };
+        let reserved = [PKG_FILENAME, NCX_FILENAME, NAV_FILENAME];
+        let mut resource_documents = Vec::new();
+        let mut stylesheets = Vec::new();
+        for item in manifest {
+            let full_path = book.resolve_href(&item.href);
+            if moved.contains_key(&full_path) || toc_paths.contains(&full_path) {
+                continue;
+            }
+            let Some((folder_name, media)) = media_folder(&item.media_type).and_then(|name| {
+                builder
+                    .media_collections()
+                    .into_iter()
+                    .find(|(folder_name, _)| *folder_name == name)
+            }) else {
+                let path = full_path
+                    .strip_prefix(&package_folder)
+                    .unwrap_or_else(|| full_path.rsplit('/').next().unwrap_or_default());
+                let path = unique_filename(path, |name| {
+                    let new_path = format!("{}/{}", CONTENT_FOLDER_NAME, name);
+                    reserved.contains(&name) || moved.values().any(|p| *p == new_path)
+                });
+                builder.resources.insert(
+                    path.clone(),
+                    Resource {
+                        media_type: item.media_type.clone(),
+                        properties: item.properties.clone(),
+                        source: MediaSource::Bytes(book.read_file(&full_path)?),
+                    },
+                );
+                if item.media_type == MEDIA_TYPE_XHTML {
+                    resource_documents.push((full_path.clone(), path.clone()));
+                }
+                moved.insert(full_path, format!("{}/{}", CONTENT_FOLDER_NAME, path));
+                continue;
+            };
+            let basename = full_path.rsplit('/').next().unwrap_or_default();
+            let key = unique_filename(basename, |name| media.contains_key(name));
+            media.insert(key.clone(), MediaSource::Bytes(book.read_file(&full_path)?));
+            let new_path = format!("{}/{}/{}", CONTENT_FOLDER_NAME, folder_name, key);
+            if folder_name == CSS_FOLDER_NAME {
+                stylesheets.push((full_path.clone(), new_path.clone(), key));
+            }
+            moved.insert(full_path, new_path);
+        }
+
+        // 样式表中的 url() 仅在目标位置改变时改写
+        for (old_path, new_path, key) in stylesheets {
+            let Some(mut entry) = builder.stylesheet.get_mut(&key) else {
+                continue;
+            };
+            let MediaSource::Bytes(bytes) = entry.value_mut() else {
+                continue;
+            };
+            let Ok(css) = std::str::from_utf8(bytes) else {
+                continue;
+            };
+            let relinked = rewrite_css_urls(css, |href| relink(href, &old_path, &new_path, &moved));
+            if relinked != css {
+                *bytes = relinked.into_bytes();
+            }
+        }
+        for (old_path, key) in resource_documents {
+            let Some(mut entry) = builder.resources.get_mut(&key) else {
+                continue;
+            };
+            let MediaSource::Bytes(bytes) = &mut entry.value_mut().source else {
+                continue;
+            };
+            let Ok(xhtml) = std::str::from_utf8(bytes) else {
+                continue;
+            };
+            let new_path = &moved[&old_path];
+            let relinked =
+                rewrite_references(xhtml, |href| relink(href, &old_path, new_path, &moved));
+            if relinked != xhtml {
+                *bytes = relinked.into_bytes();
+            }
+        }
+
+        // 目录中每个文件的标题及上级文件
+        let mut titles = HashMap::new();
+        let mut parents = HashMap::new();
+        if let (Some(item), Ok(toc)) = (book.toc_item(), book.toc()) {
+            let toc_path = book.resolve_href(&item.href);
+            collect_toc(toc.elements(), &toc_path, None, &mut titles, &mut parents);
+        }
+
+        let mut placed: HashMap<String, String> = HashMap::new();
+        let mut previous_parent: Option<String> = None;
+        for (full_path, filename) in documents {
+            let mut xhtml = XHtmlRoot::decode_xml(&book.read_file_to_string(&full_path)?)?;
+            let new_path = &moved[&full_path];
+            xhtml.relink(|href| relink(href, &full_path, new_path, &moved));
+
+            let title = titles
+                .get(&full_path)
+                .filter(|title: &&String| !title.is_empty())
+                .cloned()
+                .or_else(|| Some(xhtml.title().to_string()).filter(|title| !title.is_empty()))
+                .unwrap_or_else(|| filename.trim_end_matches(".xhtml").to_string());
+            if xhtml.title().is_empty() {
+                xhtml.set_title(title.clone());
+            }
+
+            // 不在目录中的文件与前一个文件同级
+            let parent = if titles.contains_key(&full_path) {
+                parents.get(&full_path).and_then(|p| placed.get(p)).cloned()
+            } else {
+                previous_parent.clone()
+            };
+            let section = Section {
+                filename: filename.clone(),
+                title,
+                xhtml,
+                childs: Vec::new(),
+            };
+            match parent
+                .as_deref()
+                .and_then(|p| find_section_mut(&mut builder.sections, p))
+            {
+                Some(parent_section) => parent_section.childs.push(section),
+                None => builder.sections.push(section),
+            }
+            placed.insert(full_path, filename);
+            previous_parent = parent;
+        }
+
+        // 关联到 manifest 的元数据先记为新的文件路径，输出时换成 id；标识符的 id 固定为 BookId
+        let unique_id = book.metadata().identifier.as_ref().map(|i| i.id.as_str());
+        let content_prefix = format!("{}/", CONTENT_FOLDER_NAME);
+        for meta in builder.properties.iter_mut() {
+            let Some(target) = meta.refines.strip_prefix('#').filter(|id| !id.is_empty()) else {
+                continue;
+            };
+            if unique_id == Some(target) {
+                meta.refines = format!("#{}", BOOK_ID);
+            } else if let Some(path) = book
+                .resource_by_id(target)
+                .and_then(|m| moved.get(&book.resolve_href(&m.href)))
+                .and_then(|path| path.strip_prefix(&content_prefix))
+            {
+                meta.refines = format!("#{}", path);
+            }
+        }
+
+        let cover_image = manifest
+            .iter()
+            .find(|m| has_property(m, "cover-image"))
+            .or_else(|| {
+                book.metadata()
+                    .cover
+                    .as_deref()
+                    .and_then(|id| book.resource_by_id(id))
+            })
+            .and_then(|m| moved.get(&book.resolve_href(&m.href)))
+            .and_then(|path| {
+                let prefix = format!("{}/{}/", CONTENT_FOLDER_NAME, IMAGE_FOLDER_NAME);
+                path.strip_prefix(&prefix).map(String::from)
+            });
+        if let Some(image_filename) = cover_image {
+            let xhtml_filename = book
+                .package()
+                .guide()
+                .iter()
+                .find(|g| g.ref_type() == "cover")
+                .and_then(|g| placed.get(&book.resolve_href(g.href())))
+                .cloned()
+                .unwrap_or_default();
+            builder.cover = Some(Arc::new(Mutex::new(Cover {
+                filename: image_filename.clone(),
+                css_filename: None,
+                image_filename,
+                xhtml_filename,
+            })));
+        }
+
+        Ok(builder)
+    }
+
+    /// 从包文件元数据读取书籍信息
+    fn load_metadata(&mut self, metadata: &Metadata) {
+        self.creator = metadata.creator.clone();
+        self.subject = metadata.subject.clone();
+        self.description = metadata.description.clone();
+        self.category = metadata.category.clone();
+        self.publisher = metadata.publisher.clone();
+        self.titles = metadata.titles.clone();
+        self.contributor = metadata.contributor.clone();
+        self.collection = metadata.collection.clone();
+        self.format = metadata.format.clone();
+        self.identifier = metadata.identifier.as_ref().map(|i| Identifier {
+            id: String::from(BOOK_ID),
+            ..i.clone()
+        });
+        self.other_identifiers = metadata.other_identifiers.clone();
+        self.source = metadata.source.clone();
+        self.language = Some(metadata.language.clone()).filter(|l| !l.is_empty());
+        self.relation = metadata.relation.clone();
+        self.coverage = metadata.coverage.clone();
+        self.rights = metadata.rights.clone();
+        self.date = metadata.date_published;
+        self.created = metadata.date_created;
+        for meta in metadata.meta() {
+            if !meta.name.is_empty() {
+                self.add_metadata(meta.name.clone(), meta.content.clone());
+            } else if !meta.property.is_empty() {
+                self.properties.push(meta.clone());
+            }
         }
     }
 
     /// Set the epub identifier
+    ///
+    /// A UUID is generated when the book is written without an identifier.
     pub fn set_id<S: Into<String>>(&mut self, id: S) -> &mut Self {
-        self.identifier = Some(id.into());
+        self.identifier = Some(Identifier::new(BOOK_ID, id));
+        self
+    }
+    /// Set the identifier referenced by `unique-identifier`, e.g. [`Identifier::isbn`]
+    ///
+    /// ISBN check digits are validated, the id of the identifier is replaced.
+    pub fn set_unique_identifier(&mut self, identifier: Identifier) -> Result<&mut Self, Error> {
+        identifier.check()?;
+        self.identifier = Some(Identifier {
+            id: String::from(BOOK_ID),
+            ..identifier
+        });
+        Ok(self)
+    }
+    /// Add another identifier such as an ISBN or DOI, ISBN check digits are validated
+    pub fn add_identifier(&mut self, identifier: Identifier) -> Result<&mut Self, Error> {
+        identifier.check()?;
+        self.other_identifiers.push(identifier);
+        Ok(self)
+    }
+    /// The identifier referenced by `unique-identifier`
+    ///
+    /// `None` until it is set or generated when the book is written. In reproducible mode a
+    /// generated identifier depends on the content, it is derived again on every output and
+    /// not kept.
+    pub fn unique_identifier(&self) -> Option<&Identifier> {
+        self.identifier.as_ref()
+    }
+    /// Add an author, see [`EpubBuilder::add_creator`]
+    pub fn add_author<S: Into<String>>(&mut self, name: S) -> &mut Self {
+        self.creator.push(Creator::new(name, "aut"));
+        self
+    }
+    /// Add a creator with a MARC relator role and sort name, written as `dc:creator`
+    pub fn add_creator(&mut self, creator: Creator) -> &mut Self {
+        self.creator.push(creator);
+        self
+    }
+    /// Add a contributor such as a translator (`trl`) or illustrator (`ill`), written as `dc:contributor`
+    pub fn add_contributor(&mut self, contributor: Creator) -> &mut Self {
+        self.contributor.push(contributor);
+        self
+    }
+    /// Set the series and the position of the book in it, e.g. `("梦回大明春", "2")`
+    ///
+    /// Replaces the series set before, other collections are kept.
+    pub fn set_series<S1: Into<String>, S2: Into<String>>(
+        &mut self,
+        name: S1,
+        position: S2,
+    ) -> &mut Self {
+        replace_series(
+            &mut self.collection,
+            Collection::new(name, "series", position),
+        );
+        self
+    }
+    /// Add a series or set the book belongs to
+    pub fn add_collection(&mut self, collection: Collection) -> &mut Self {
+        self.collection.push(collection);
         self
     }
     /// Set the epub format
@@ -201,9 +622,226 @@ impl EpubBuilder {
         self.category = Some(category.into());
         self
     }
-    /// Set the epub date
-    pub fn set_date(&mut self, date: SystemTime) -> &mut Self {
-        self.date = Some(date);
+    /// Set the publication date, a `SystemTime` or a partial date such as `Date::year(2010)?`
+    pub fn set_date<D: Into<Date>>(&mut self, date: D) -> &mut Self {
+        self.date = Some(date.into());
+        self
+    }
+    /// Set the creation date, written as `dcterms:created` or `opf:event="creation"`
+    pub fn set_created<D: Into<Date>>(&mut self, created: D) -> &mut Self {
+        self.created = Some(created.into());
+        self
+    }
+    /// Set the epub last modification date, written as `dcterms:modified`
+    ///
+    /// EPUB 3 books without a modification date use the time they are written, EPUB 2 books
+    /// use `opf:event="modification"` and only when it is set.
+    pub fn set_modified(&mut self, modified: SystemTime) -> &mut Self {
+        self.modified = Some(modified);
+        self
+    }
+    /// Enable the reproducible build mode
+    ///
+    /// Manifest entries and files are always written in a stable order. In reproducible mode
+    /// the `SOURCE_DATE_EPOCH` environment variable (seconds since the Unix epoch), when set,
+    /// overrides the modification date and is used as the timestamp of every zip entry. A
+    /// missing identifier is derived from the metadata and the content of every file, so two
+    /// different books never share it.
+    pub fn set_reproducible(&mut self, reproducible: bool) -> &mut Self {
+        self.reproducible = reproducible;
+        self
+    }
+    /// Set how section bodies are checked in [`EpubBuilder::add_sub_section`]
+    ///
+    /// Defaults to [`BodyCheck::Off`], bodies are written as given.
+    pub fn set_body_check(&mut self, body_check: BodyCheck) -> &mut Self {
+        self.body_check = body_check;
+        self
+    }
+    /// Add the headings inside sections to the table of contents
+    ///
+    /// Headings `h1` to `h{depth}` of every section body are nested below the entry of their
+    /// section with `file.xhtml#id` links, headings without an id get one generated from
+    /// their text when the book is written. A first heading repeating the section title is
+    /// left out. `0`, the default, lists sections only.
+    pub fn set_toc_heading_depth(&mut self, depth: usize) -> &mut Self {
+        self.toc_heading_depth = depth.min(6);
+        self
+    }
+    /// Replace the table of contents generated from the sections
+    ///
+    /// The elements of `toc` are written to the NCX and the EPUB 3 navigation document, so
+    /// entries may point to fragments such as `xhtml/chapter_1.xhtml#note`, group several
+    /// files under an entry without a url, or leave files out. Urls are relative to the
+    /// package document, e.g. `xhtml/chapter_1.xhtml`. The title, language and NCX metadata
+    /// still come from the builder, the title of `toc` is used when it is not empty.
+    pub fn set_toc(&mut self, toc: TocNav) -> &mut Self {
+        self.toc = Some(toc);
+        self
+    }
+
+    /// Edit the table of contents right before it is written
+    ///
+    /// `editor` receives the generated table of contents, or the one set with
+    /// [`EpubBuilder::set_toc`], and may add, remove, reorder or rename entries.
+    pub fn edit_toc<F>(&mut self, editor: F) -> &mut Self
+    where
+        F: Fn(&mut TocNav) + Send + Sync + 'static,
+    {
+        self.toc_editor = Some(TocEditor(Box::new(editor)));
+        self
+    }
+
+    /// Add a landmark to the EPUB 3 navigation document
+    ///
+    /// `kind` is the structural semantics of the target, e.g. `copyright-page`, `preface` or
+    /// `index`, the url is relative to the package document, e.g. `xhtml/copyright.xhtml`.
+    /// Landmarks for the cover, the table of contents and the first section of the body
+    /// (`bodymatter`) are added automatically, a landmark of the same kind replaces them.
+    /// Their titles follow the book language, see [`EpubBuilder::set_landmark_title`].
+    pub fn add_landmark<S1, S2, S3>(&mut self, kind: S1, url: S2, title: S3) -> &mut Self
+    where
+        S1: Into<String>,
+        S2: Into<String>,
+        S3: Into<String>,
+    {
+        self.landmarks.push(Landmark::new(kind, url, title));
+        self
+    }
+
+    /// Set the title of an automatic landmark, e.g. `("toc", "Inhalt")`
+    ///
+    /// The cover and table of contents landmarks are titled in the book language (Chinese,
+    /// Japanese or English), the `bodymatter` landmark uses the title of its section.
+    pub fn set_landmark_title<S1, S2>(&mut self, kind: S1, title: S2) -> &mut Self
+    where
+        S1: Into<String>,
+        S2: Into<String>,
+    {
+        self.landmark_titles.insert(kind.into(), title.into());
+        self
+    }
+
+    /// Add a print page to the page list
+    ///
+    /// Elements with `epub:type="pagebreak"` and an id are added automatically in reading
+    /// order, their page number comes from the `title` or `aria-label` attribute or the
+    /// element text. Use this for pages that are not marked up that way.
+    pub fn add_page<S1: Into<String>, S2: Into<String>>(&mut self, name: S1, url: S2) -> &mut Self {
+        self.pages.push(PageTarget::new(name, url));
+        self
+    }
+
+    /// The table of contents as it would be written now
+    ///
+    /// Ids for headings, see [`EpubBuilder::set_toc_heading_depth`], are only assigned when
+    /// the book is written, headings without an id are missing until then.
+    pub fn toc(&self) -> TocNav {
+        let mut toc = TocNav::new(self.title.clone(), self.language_or_default());
+        for creator in self.creator.iter() {
+            toc.add_author(creator.name.as_str());
+        }
+        match self.toc.as_ref() {
+            Some(custom) => {
+                if !custom.title().is_empty() {
+                    toc.set_title(custom.title());
+                }
+                *toc.elements_mut() = custom.elements().to_vec();
+            }
+            None => {
+                for item in self.sections.iter() {
+                    toc.add_element(Self::convert_section(item, 1, self.toc_heading_depth));
+                }
+            }
+        }
+        for landmark in self.default_landmarks() {
+            if !self
+                .landmarks
+                .iter()
+                .any(|custom| custom.kind == landmark.kind)
+            {
+                toc.add_landmark(landmark);
+            }
+        }
+        for landmark in self.landmarks.iter() {
+            toc.add_landmark(landmark.clone());
+        }
+        let mut reading_order = Vec::new();
+        for section in self.sections_in_order() {
+            let href = format!("{}/{}", XHTML_FOLDER_NAME, section.filename);
+            for (id, name) in markup::pagebreaks(section.xhtml.body()) {
+                toc.add_page(PageTarget::new(name, format!("{}#{}", href, id)));
+            }
+            let ids = markup::ordered_element_ids(section.xhtml.body());
+            let targets = ids.into_iter().map(|id| format!("{}#{}", href, id));
+            reading_order.push(href.clone());
+            reading_order.extend(targets);
+        }
+        toc.set_reading_order(reading_order);
+        for page in self.pages.iter() {
+            toc.add_page(page.clone());
+        }
+        if let Some(editor) = self.toc_editor.as_ref() {
+            (editor.0)(&mut toc);
+        }
+        toc
+    }
+
+    /// 封面、目录和正文开始位置的地标
+    fn default_landmarks(&self) -> Vec<Landmark> {
+        let mut landmarks = Vec::new();
+        let cover = self
+            .cover
+            .as_ref()
+            .map(|cover| cover.lock().unwrap().xhtml_filename.clone())
+            .filter(|filename| !filename.is_empty());
+        if let Some(filename) = cover.as_ref() {
+            let url = format!("{}/{}", XHTML_FOLDER_NAME, filename);
+            landmarks.push(Landmark::new("cover", url, self.landmark_title("cover")));
+        }
+        if self.version == V30 {
+            let url = format!("{}#toc", NAV_FILENAME);
+            landmarks.push(Landmark::new("toc", url, self.landmark_title("toc")));
+        }
+        let body = self
+            .sections_in_order()
+            .into_iter()
+            .find(|section| Some(&section.filename) != cover.as_ref());
+        if let Some(section) = body {
+            let url = format!("{}/{}", XHTML_FOLDER_NAME, section.filename);
+            let title = self
+                .landmark_titles
+                .get("bodymatter")
+                .cloned()
+                .unwrap_or_else(|| section.title.clone());
+            landmarks.push(Landmark::new("bodymatter", url, title));
+        }
+        landmarks
+    }
+
+    /// 自动添加的封面和目录地标的标题，未设置时按书籍语言选择
+    fn landmark_title(&self, kind: &str) -> String {
+        if let Some(title) = self.landmark_titles.get(kind) {
+            return title.clone();
+        }
+        let language = self.language_or_default().to_ascii_lowercase();
+        let traditional = ["zh-tw", "zh-hk", "zh-mo", "zh-hant"]
+            .iter()
+            .any(|tag| language.starts_with(tag));
+        let title = match (language.split(['-', '_']).next().unwrap_or_default(), kind) {
+            ("zh", "cover") => "封面",
+            ("zh", _) if traditional => "目錄",
+            ("zh", _) => "目录",
+            ("ja", "cover") => "表紙",
+            ("ja", _) => "目次",
+            (_, "cover") => "Cover",
+            _ => "Table of Contents",
+        };
+        title.to_string()
+    }
+    /// Set an observer notified of every section, media file and entry written on output
+    pub fn set_progress<P: Progress + 'static>(&mut self, progress: P) -> &mut Self {
+        self.progress = Some(ProgressObserver(Box::new(progress)));
         self
     }
     /// Set the epub description
@@ -211,9 +849,14 @@ impl EpubBuilder {
         self.description = Some(description.into());
         self
     }
-    /// Set the epub subject
-    pub fn set_subject<S: Into<String>>(&mut self, subtitle: S) -> &mut Self {
-        self.title = subtitle.into();
+    /// Set the epub subject, replacing the existing subjects
+    pub fn set_subject<S: Into<String>>(&mut self, subject: S) -> &mut Self {
+        self.subject = vec![subject.into()];
+        self
+    }
+    /// Add a subject
+    pub fn add_subject<S: Into<String>>(&mut self, subject: S) -> &mut Self {
+        self.subject.push(subject.into());
         self
     }
     /// Set the epub title
@@ -221,6 +864,14 @@ impl EpubBuilder {
         self.title = title.into();
         self
     }
+    /// Add a title such as a subtitle, a title of type `main` also sets the book title
+    pub fn add_title(&mut self, title: Title) -> &mut Self {
+        if title.title_type == "main" {
+            self.title = title.text.clone();
+        }
+        self.titles.push(title);
+        self
+    }
     /// Set the epub source
     pub fn set_source<S: Into<String>>(&mut self, source: S) -> &mut Self {
         self.source = Some(source.into());
@@ -255,8 +906,24 @@ impl EpubBuilder {
         self
     }
 
-    /// Add an image file to the epub
-    pub fn add_image<S1: Into<String>>(
+    /// Register an extra rootfile in `META-INF/container.xml`
+    ///
+    /// The package document written by this builder is always the first rootfile, extra
+    /// rootfiles are listed after it for multiple-rendition books.
+    pub fn add_rootfile<S1: Into<String>, S2: Into<String>>(
+        &mut self,
+        full_path: S1,
+        media_type: S2,
+    ) -> &mut Self {
+        self.rootfiles.push(RootFile::new(full_path, media_type));
+        self
+    }
+
+    /// Add an image to the epub
+    ///
+    /// The source is a file path, bytes, or any other [`MediaSource`]. The internal filename
+    /// defaults to the source file name and is required for sources without one.
+    pub fn add_image<S1: Into<MediaSource>>(
         &mut self,
         source: S1,
         internal_filename: Option<String>,
@@ -271,8 +938,8 @@ impl EpubBuilder {
         )
     }
 
-    /// Add a video file to the epub
-    pub fn add_video<S1: Into<String>>(
+    /// Add a video to the epub, see [`EpubBuilder::add_image`]
+    pub fn add_video<S1: Into<MediaSource>>(
         &mut self,
         source: S1,
         internal_filename: Option<String>,
@@ -287,8 +954,8 @@ impl EpubBuilder {
         )
     }
 
-    /// Add an audio file to the epub
-    pub fn add_audio<S1: Into<String>>(
+    /// Add an audio to the epub, see [`EpubBuilder::add_image`]
+    pub fn add_audio<S1: Into<MediaSource>>(
         &mut self,
         source: S1,
         internal_filename: Option<String>,
@@ -302,8 +969,8 @@ impl EpubBuilder {
             audios,
         )
     }
-    /// Add a stylesheet file to the epub
-    pub fn add_stylesheet<S1: Into<String>>(
+    /// Add a stylesheet to the epub, see [`EpubBuilder::add_image`]
+    pub fn add_stylesheet<S1: Into<MediaSource>>(
         &mut self,
         source: S1,
         internal_filename: Option<String>,
@@ -318,8 +985,8 @@ impl EpubBuilder {
         )
     }
 
-    /// Add a font file to the epub
-    pub fn add_font<S1: Into<String>>(
+    /// Add a font to the epub, see [`EpubBuilder::add_image`]
+    pub fn add_font<S1: Into<MediaSource>>(
         &mut self,
         source: S1,
         internal_filename: Option<String>,
@@ -334,28 +1001,44 @@ impl EpubBuilder {
         )
     }
 
-    /// Set the epub cover
-    pub fn set_cover<S1: Into<String>>(&mut self, internal_image_path: S1, internal_css_path: Option<String>)
-        -> Result<String, Error> {
-        let raw_image_path = internal_image_path.into();
-        let image_path = Path::new(&raw_image_path);
+    /// Set the epub cover from an image file
+    pub fn set_cover<S1: Into<MediaSource>>(
+        &mut self,
+        internal_image_path: S1,
+        internal_css_path: Option<String>,
+    ) -> Result<String, Error> {
+        self.set_cover_image(internal_image_path, None, internal_css_path)
+    }
 
-        if !image_path.exists() {
-            return Err(Error::FileNotFoundErr(format!(
-                "file not found:{}",
-                raw_image_path.clone()
-            )));
+    /// Set the epub cover from any [`MediaSource`]
+    ///
+    /// The internal filename defaults to the source file name and is required for sources
+    /// without one, it also determines the media type of the image.
+    pub fn set_cover_image<S1: Into<MediaSource>>(
+        &mut self,
+        source: S1,
+        internal_filename: Option<String>,
+        internal_css_path: Option<String>,
+    ) -> Result<String, Error> {
+        let source = source.into();
+        if let MediaSource::Path(ref path) = source {
+            if !Path::new(path).exists() {
+                return Err(Error::FileNotFoundErr(format!("file not found:{}", path)));
+            }
         }
+        let filename = internal_filename
+            .clone()
+            .or_else(|| source.file_name())
+            .ok_or_else(|| Error::MediaError(String::from("Cover image filename required")))?;
 
-        first(raw_image_path.clone()).ok_or_else(|| {
-            Error::MediaError(format!("file mime err:{}", raw_image_path.clone()))
-        })?;
+        first(filename.clone())
+            .ok_or_else(|| Error::MediaError(format!("file mime err:{}", filename.clone())))?;
 
         // 移除之前的封面
         self.remove_cover_resources()?;
 
         // 添加封面图片到资源列表中
-        let cover_image_filename = self.add_image(raw_image_path.clone(), None)?;
+        let cover_image_filename = self.add_image(source, internal_filename)?;
 
         let body = format!("<img src=\"{}\" alt=\"cover\"/>", cover_image_filename);
         let cover_xhtml_filename = self.add_section(
@@ -372,9 +1055,10 @@ impl EpubBuilder {
         let mut cover = cover.lock().unwrap();
 
         // 设置封面文件名
-        cover.filename = image_path
-            .file_name()
-            .and_then(|name| name.to_str())
+        cover.filename = filename;
+        cover.image_filename = cover_image_filename
+            .rsplit('/')
+            .next()
             .unwrap_or_default()
             .to_string();
         cover.xhtml_filename = cover_xhtml_filename.clone();
@@ -383,6 +1067,8 @@ impl EpubBuilder {
     }
 
     /// Add a section to the epub
+    ///
+    /// The body is checked as set with [`EpubBuilder::set_body_check`].
     pub fn add_section<S1: Into<String>, S2: Into<String>>(
         &mut self,
         body: S1,
@@ -399,14 +1085,16 @@ impl EpubBuilder {
         )
     }
     /// Add a section to the epub
+    ///
+    /// The body is checked as set with [`EpubBuilder::set_body_check`].
     pub fn add_sub_section<S1: Into<String>, S2: Into<String>>(
         &mut self,
         parent_filename: Option<String>,
         body: S1,
         section_title: S2,
         internal_filename: Option<String>,
-        internal_css_path: Option<String>, )
-        -> Result<String, Error> {
+        internal_css_path: Option<String>,
+    ) -> Result<String, Error> {
         let mut base_filename = String::new();
         if let Some(mut filename) = internal_filename {
             let ext = Path::new(&filename)
@@ -446,10 +1134,12 @@ impl EpubBuilder {
             }
         }
 
+        let body = self.prepare_body(&base_filename, body.into())?;
+
         let mut section = Section::new(base_filename.clone());
         let title = section_title.into();
         section.title = title.clone();
-        section.xhtml.set_body(body.into());
+        section.xhtml.set_body(body);
         section.xhtml.set_title(title.clone());
 
         if let Some(css_path) = internal_css_path {
@@ -484,7 +1174,169 @@ impl EpubBuilder {
 
         self.filenames.insert(base_filename.clone());
 
-        Ok(String::from(base_filename))
+        Ok(base_filename)
+    }
+
+    /// Add a section written in Markdown
+    ///
+    /// CommonMark with tables, footnotes, strikethrough and heading attributes is converted to
+    /// XHTML. Headings keep an explicit `{#id}`, other headings get an id generated from their
+    /// text. Local images are imported with [`EpubBuilder::add_image`], their paths are
+    /// relative to the current directory like every other path given to the builder.
+    pub fn add_markdown_section<S1: AsRef<str>, S2: Into<String>>(
+        &mut self,
+        parent_filename: Option<String>,
+        markdown: S1,
+        section_title: S2,
+        internal_filename: Option<String>,
+        internal_css_path: Option<String>,
+    ) -> Result<String, Error> {
+        let body = markdown::to_xhtml(markdown.as_ref(), |url| {
+            let path = percent_decode(url);
+            // 同一图片只导入一次
+            let imported = self.images.iter().find_map(|item| match item.value() {
+                MediaSource::Path(source) if *source == path => Some(item.key().clone()),
+                _ => None,
+            });
+            match imported {
+                Some(filename) => Ok(format!("../{}/{}", IMAGE_FOLDER_NAME, filename)),
+                None => self.add_image(path, None),
+            }
+        })?;
+        self.add_sub_section(
+            parent_filename,
+            body,
+            section_title,
+            internal_filename,
+            internal_css_path,
+        )
+    }
+
+    /// 按 [`BodyCheck`] 检查或修复章节正文
+    fn prepare_body(&self, filename: &str, body: String) -> Result<String, Error> {
+        let body = match self.body_check {
+            BodyCheck::Off => return Ok(body),
+            BodyCheck::Reject => body,
+            BodyCheck::Repair => repair_body(&body),
+        };
+        let diagnostics = check_body(&body);
+        if diagnostics.is_empty() {
+            return Ok(body);
+        }
+        let problems: Vec<String> = diagnostics
+            .into_iter()
+            .map(|mut diagnostic| {
+                diagnostic.location.file = filename.to_string();
+                diagnostic.to_string()
+            })
+            .collect();
+        Err(Error::MalformedXhtmlErr(problems.join("\n")))
+    }
+
+    /// Check the body of every section, including sections loaded from an existing book
+    ///
+    /// Locations name the section file, lines and columns are relative to the body.
+    pub fn check_sections(&self) -> Report {
+        let mut report = Report::new();
+        for section in self.sections_in_order() {
+            for mut diagnostic in check_body(section.xhtml.body()) {
+                diagnostic.location.file = format!(
+                    "{}/{}/{}",
+                    CONTENT_FOLDER_NAME, XHTML_FOLDER_NAME, section.filename
+                );
+                report.push(diagnostic);
+            }
+        }
+        report
+    }
+
+    /// Check the links and resource references of every section and stylesheet
+    ///
+    /// `href`, `src`, `xlink:href` and `poster` attributes of section bodies, stylesheet links
+    /// and `url()` references must resolve to a section or a registered media file, and a
+    /// `#fragment` must match an element id of the target section. Links with a scheme such
+    /// as `https:` are skipped. Media files nothing references are reported as warnings.
+    pub fn check_links(&self) -> Report {
+        let xhtml_folder = format!("{}/{}", CONTENT_FOLDER_NAME, XHTML_FOLDER_NAME);
+        let sections = self.sections_in_order();
+
+        // 书中所有文件，章节附带其元素 id
+        let mut targets: HashMap<String, Option<HashSet<String>>> = HashMap::new();
+        for section in sections.iter() {
+            let path = format!("{}/{}", xhtml_folder, section.filename);
+            targets.insert(path, Some(section.xhtml.element_ids()));
+        }
+        for (folder_name, media) in self.media_collections() {
+            for key in sorted_keys(media) {
+                targets.insert(
+                    format!("{}/{}/{}", CONTENT_FOLDER_NAME, folder_name, key),
+                    None,
+                );
+            }
+        }
+        for item in self.resources.iter() {
+            let ids = (item.value().media_type == MEDIA_TYPE_XHTML)
+                .then(|| item.value().source.read().ok())
+                .flatten()
+                .map(|xhtml| markup::element_ids(&String::from_utf8_lossy(&xhtml)));
+            targets.insert(format!("{}/{}", CONTENT_FOLDER_NAME, item.key()), ids);
+        }
+        targets.insert(format!("{}/{}", CONTENT_FOLDER_NAME, NCX_FILENAME), None);
+        if self.version == V30 {
+            targets.insert(format!("{}/{}", CONTENT_FOLDER_NAME, NAV_FILENAME), None);
+        }
+
+        let mut report = Report::new();
+        let mut referenced = HashSet::new();
+        for section in sections.iter() {
+            let path = format!("{}/{}", xhtml_folder, section.filename);
+            for (location, href) in section.xhtml.references(&path) {
+                check_link(
+                    &path,
+                    location,
+                    &href,
+                    &targets,
+                    &mut referenced,
+                    &mut report,
+                );
+            }
+        }
+        for key in sorted_keys(&self.stylesheet) {
+            let path = format!("{}/{}/{}", CONTENT_FOLDER_NAME, CSS_FOLDER_NAME, key);
+            let Some(css) = self
+                .stylesheet
+                .get(&key)
+                .and_then(|item| item.value().read().ok())
+            else {
+                continue;
+            };
+            for href in css_references(&String::from_utf8_lossy(&css)) {
+                let location = Location::file(path.as_str());
+                check_link(
+                    &path,
+                    location,
+                    &href,
+                    &targets,
+                    &mut referenced,
+                    &mut report,
+                );
+            }
+        }
+
+        for (folder_name, media) in self.media_collections() {
+            for key in sorted_keys(media) {
+                let path = format!("{}/{}/{}", CONTENT_FOLDER_NAME, folder_name, key);
+                if !referenced.contains(&path) {
+                    report.push(Diagnostic::new(
+                        Severity::Warning,
+                        DiagnosticKind::UnreferencedMedia,
+                        Location::file(path.as_str()),
+                        format!("{} is not referenced by any section or stylesheet", path),
+                    ));
+                }
+            }
+        }
+        report
     }
 
     /// 移除旧封面资源
@@ -528,41 +1380,152 @@ impl EpubBuilder {
         Ok(())
     }
 
+    /// Write the epub as an exploded folder tree
     pub fn output(&mut self, output_path: &Path) -> Result<(), Error> {
-        println!("Output: {}", output_path.display());
-        self.create_folder(output_path)?;
+        log::info!("Output: {}", output_path.display());
+        self.write_to(&mut DirectoryStorage::new(output_path))
+    }
 
-        if let Err(e) = self.encode_toc_xml(output_path) {
-            return Err(e);
+    /// Write the epub as a single `.epub` zip archive
+    pub fn output_epub(&mut self, output_file: &Path) -> Result<(), Error> {
+        log::info!("Output: {}", output_file.display());
+        let mut storage = ZipStorage::create(output_file)?;
+        if let Some(timestamp) = self.source_date_epoch()? {
+            storage.set_last_modified(timestamp);
         }
-        let xhtml_path=output_path.join(CONTENT_FOLDER_NAME).join(XHTML_FOLDER_NAME);
-        if let Err(e) =  self.write_all_sections(xhtml_path.as_ref()) {
-            return Err(e);
+        self.write_to(&mut storage)?;
+        storage.finish()?;
+        Ok(())
+    }
+
+    /// Build the `.epub` zip archive in memory and return its bytes
+    pub fn output_bytes(&mut self) -> Result<Vec<u8>, Error> {
+        let mut storage = ZipStorage::in_memory();
+        if let Some(timestamp) = self.source_date_epoch()? {
+            storage.set_last_modified(timestamp);
         }
-        write::write_media_file(output_path,CSS_FOLDER_NAME,&self.stylesheet)?;
-        write::write_media_file(output_path,FONT_FOLDER_NAME,&self.fonts)?;
-        write::write_media_file(output_path,IMAGE_FOLDER_NAME,&self.images)?;
-        write::write_media_file(output_path,VIDEO_FOLDER_NAME,&self.videos)?;
-        write::write_media_file(output_path,AUDIO_FOLDER_NAME,&self.audios)?;
+        self.write_to(&mut storage)?;
+        Ok(storage.finish()?.into_inner())
+    }
 
-         Ok(())
+    /// Validate the book as it would be written
+    ///
+    /// The epub is built in memory and checked with [`validate_book`], so media loaders are
+    /// called and the progress observer is not notified.
+    pub fn validate(&mut self) -> Result<Report, Error> {
+        let progress = self.progress.take();
+        let bytes = self.output_bytes();
+        self.progress = progress;
+        Ok(validate_book(&Book::from_bytes(bytes?)?))
     }
 
-    fn write_all_sections(&mut self, output_path: &Path) -> Result<(), Error> {
-        let mut sections = std::mem::take(&mut self.sections); // 临时取出 sections
-        for mut section in sections.iter_mut() {
-            self.write_sections(output_path, &mut section)?;
+    /// Write every file of the epub through a [`Storage`]
+    ///
+    /// The `mimetype` file comes first, followed by `META-INF`, the content folder with the
+    /// package document, the table of contents and sections, and finally the media files.
+    pub fn write_to<S: Storage + ?Sized>(&mut self, storage: &mut S) -> Result<(), Error> {
+        let mut progress = self.progress.take();
+        let observer = progress.as_mut().map(|p| p.0.as_mut() as &mut dyn Progress);
+        // 可复现模式下生成的标识符由内容计算，每次输出重新计算，不保留
+        let identifier = self.reproducible.then(|| self.identifier.clone());
+        let result = self.write_entries(&mut ObservedStorage::new(storage, observer));
+        if let Some(identifier) = identifier {
+            self.identifier = identifier;
+        }
+        self.progress = progress;
+        result
+    }
+
+    fn write_entries<S: Storage + ?Sized>(
+        &mut self,
+        storage: &mut ObservedStorage<S>,
+    ) -> Result<(), Error> {
+        if self.toc_heading_depth > 0 {
+            let depth = self.toc_heading_depth;
+            self.for_each_section(|section| {
+                let body = markup::assign_heading_ids(section.xhtml.body(), depth);
+                section.xhtml.set_body(body);
+                Ok(())
+            })?;
+        }
+        if self
+            .identifier
+            .as_ref()
+            .is_none_or(|i| i.text.trim().is_empty())
+        {
+            // 空的标识符不参与计算
+            self.identifier = None;
+            self.identifier = Some(self.generated_identifier()?);
+        }
+
+        storage.write_file(MIMETYPE_FILENAME, MEDIA_TYPE_EPUB.as_bytes())?;
+        storage.add_folder(META_INF_FOLDER_NAME)?;
+        let container_entry = format!("{}/{}", META_INF_FOLDER_NAME, CONTAINER_FILENAME);
+        storage.write_file(&container_entry, self.encode_container_xml()?.as_bytes())?;
+        storage.add_folder(CONTENT_FOLDER_NAME)?;
+
+        let package_entry = format!("{}/{}", CONTENT_FOLDER_NAME, PKG_FILENAME);
+        storage.write_file(&package_entry, self.encode_package_xml()?.as_bytes())?;
+        for (filename, toc_xml) in self.encode_toc_xml()? {
+            let entry_name = format!("{}/{}", CONTENT_FOLDER_NAME, filename);
+            storage.write_file(&entry_name, toc_xml.as_bytes())?;
+        }
+
+        let xhtml_folder = format!("{}/{}", CONTENT_FOLDER_NAME, XHTML_FOLDER_NAME);
+        if !self.filenames.is_empty() {
+            storage.add_folder(&xhtml_folder)?;
+        }
+        let total = self.sections_in_order().len();
+        let mut index = 0;
+        self.for_each_section(|section| {
+            index += 1;
+            storage.on_section(&section.filename, index, total);
+            let entry_name = format!("{}/{}", xhtml_folder, section.filename);
+            storage.write_file(&entry_name, section.xhtml.encode_xml()?.as_bytes())
+        })?;
+
+        let total = self
+            .media_collections()
+            .iter()
+            .map(|(_, media)| media.len())
+            .sum::<usize>()
+            + self.resources.len();
+        let mut index = 0;
+        for (folder_name, media) in self.media_collections() {
+            if media.is_empty() {
+                continue;
+            }
+            let folder = format!("{}/{}", CONTENT_FOLDER_NAME, folder_name);
+            storage.add_folder(&folder)?;
+            for key in sorted_keys(media) {
+                if let Some(item) = media.get(&key) {
+                    let entry_name = format!("{}/{}", folder, key);
+                    index += 1;
+                    storage.on_media(&entry_name, index, total);
+                    storage.write_file(&entry_name, &item.value().read()?)?;
+                }
+            }
+        }
+        for key in sorted_keys(&self.resources) {
+            if let Some(item) = self.resources.get(&key) {
+                let entry_name = format!("{}/{}", CONTENT_FOLDER_NAME, key);
+                index += 1;
+                storage.on_media(&entry_name, index, total);
+                storage.write_file(&entry_name, &item.value().source.read()?)?;
+            }
         }
-        self.sections = sections; // 将 sections 放回 self
         Ok(())
     }
-    fn write_sections(&mut self, root_path: &Path, item: &mut Section) -> Result<(), Error> {
-        let mut stack = vec![item];
+
+    /// 按文档顺序（深度优先）遍历所有章节
+    fn for_each_section<F>(&mut self, mut callback: F) -> Result<(), Error>
+    where
+        F: FnMut(&mut Section) -> Result<(), Error>,
+    {
+        let mut stack: Vec<&mut Section> = self.sections.iter_mut().rev().collect();
 
         while let Some(current_item) = stack.pop() {
-            let path = root_path.join(&current_item.filename);
-            println!("Writing: {}", path.display());
-            write::write_file(path.as_ref(), &current_item.xhtml.encode_xml()?)?;
+            callback(current_item)?;
 
             // 将子节点加入栈中，逆序以保持顺序一致
             stack.extend(current_item.childs.iter_mut().rev());
@@ -570,86 +1533,536 @@ impl EpubBuilder {
 
         Ok(())
     }
-    fn encode_toc_xml(&mut self,toc_path:&Path) -> Result<(), Error> {
-        let cover_filename :String;
-        if let Some(cover) = &self.cover {
-            cover_filename = cover.lock().unwrap().xhtml_filename.clone();
+
+    /// 按文档顺序（深度优先）列出所有章节
+    fn sections_in_order(&self) -> Vec<&Section> {
+        let mut ordered = Vec::new();
+        let mut stack: Vec<&Section> = self.sections.iter().rev().collect();
+        while let Some(current_item) = stack.pop() {
+            ordered.push(current_item);
+            stack.extend(current_item.childs.iter().rev());
         }
-        let lang = self.language.as_deref().unwrap_or("zh-CN");
+        ordered
+    }
 
-        let mut toc = TocNav::new(self.title.clone(), lang);
-        toc.add_metadata("dtb:uid", self.identifier.as_deref().unwrap_or_default());
-        toc.add_metadata("dtb:totalPageCount", "0");
-        toc.add_metadata("dtb:maxPageNumber", "0");
+    /// 生成 META-INF/container.xml 文件内容
+    fn encode_container_xml(&self) -> Result<String, Error> {
+        let mut container = Container::new();
+        container.add_rootfile(RootFile::new(
+            format!("{}/{}", CONTENT_FOLDER_NAME, PKG_FILENAME),
+            MEDIA_TYPE_OPF,
+        ));
+        self.rootfiles.iter().for_each(|rootfile| {
+            container.add_rootfile(rootfile.clone());
+        });
+        container.encode_xml()
+    }
+
+    /// 根据书籍信息生成 content.opf 文件内容
+    fn encode_package_xml(&self) -> Result<String, Error> {
+        let mut metadata = Metadata::default();
+        metadata.title = self.title.clone();
+        metadata.titles = self.titles.clone();
+        metadata.creator = self.creator.clone();
+        metadata.subject = self.subject.clone();
+        metadata.description = self.description.clone();
+        metadata.category = self.category.clone();
+        metadata.publisher = self.publisher.clone();
+        metadata.contributor = self.contributor.clone();
+        metadata.collection = self.collection.clone();
+        metadata.format = self.format.clone();
+        metadata.source = self.source.clone();
+        metadata.language = self.language_or_default().to_string();
+        metadata.relation = self.relation.clone();
+        metadata.coverage = self.coverage.clone();
+        metadata.rights = self.rights.clone();
+        metadata.date_published = self.date;
+        metadata.date_created = self.created;
+        metadata.date_modified = self.modified_date()?;
+        if let Some(ref identifier) = self.identifier {
+            metadata.set_identifier(identifier.clone());
+        }
+        metadata.other_identifiers = self.other_identifiers.clone();
+        if let Some(ref items) = self.metadata {
+            for key in sorted_keys(items) {
+                if let Some(item) = items.get(&key) {
+                    metadata.add_meta(MetaItem {
+                        name: key,
+                        content: item.value().clone(),
+                        ..MetaItem::default()
+                    });
+                }
+            }
+        }
 
+        let mut package = Package::new();
+        package.add_manifest(ManifestItem::new("ncx", NCX_FILENAME, MEDIA_TYPE_NCX));
+        if self.version == V30 {
+            let mut nav = ManifestItem::new("nav", NAV_FILENAME, MEDIA_TYPE_XHTML);
+            nav.properties = String::from("nav");
+            package.add_manifest(nav);
+        }
 
-        let mut depth = 0;
-        self.sections
-            .iter().
-            enumerate()
-            // .map(|item| Self::convert_section(item, index, 0, &mut depth))
-            .for_each(|(index,item)| {
-                let element = Self::convert_section(item, (index + 1) as i32, 0, &mut depth);
-                toc.add_element(element);
-            });
+        // 固定的 id 不能被文件使用
+        let mut ids: HashSet<String> = ["ncx", "nav", BOOK_ID]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        ids.extend(self.other_identifiers.iter().map(|i| i.id.clone()));
+        for section in self.sections_in_order() {
+            let href = format!("{}/{}", XHTML_FOLDER_NAME, section.filename);
+            let id = manifest_id(&href, &mut ids);
+            let mut manifest = ManifestItem::new(id.as_str(), href.as_str(), MEDIA_TYPE_XHTML);
+            if self.version == V30 && section.xhtml.is_scripted() {
+                manifest.properties = String::from("scripted");
+            }
+            package.add_manifest(manifest);
+            package.add_spine(SpineItemRef::new(id));
+        }
 
-        toc.add_metadata("dtb:depth", (depth + 1).to_string());
+        let cover_image = self
+            .cover
+            .as_ref()
+            .map(|cover| cover.lock().unwrap().image_filename.clone());
+        for (folder_name, media) in self.media_collections() {
+            for key in sorted_keys(media) {
+                let href = format!("{}/{}", folder_name, key);
+                let mut manifest = ManifestItem::new(
+                    manifest_id(&href, &mut ids),
+                    href.clone(),
+                    first_or_default(&href),
+                );
+                if folder_name == IMAGE_FOLDER_NAME && cover_image.as_deref() == Some(key.as_str())
+                {
+                    metadata.set_cover(manifest.id.clone());
+                    if self.version == V30 {
+                        manifest.properties = String::from("cover-image");
+                    }
+                }
+                package.add_manifest(manifest);
+            }
+        }
+        for key in sorted_keys(&self.resources) {
+            if let Some(item) = self.resources.get(&key) {
+                let id = manifest_id(&key, &mut ids);
+                let mut manifest = ManifestItem::new(id, key.clone(), item.media_type.clone());
+                if self.version == V30 {
+                    manifest.properties = item.properties.clone();
+                }
+                package.add_manifest(manifest);
+            }
+        }
 
-        let write_toc_file = |toc: &mut TocNav, ver :EpubVersion| -> Result<(), Error> {
-            let toc_path = match ver { V20 => toc_path.join("toc.ncx"), V30 => toc_path.join("nav.xhtml")  };
-            if let Ok(toc_xml) = toc.encode_file(ver){
-                return write::write_file(toc_path.as_ref(), &toc_xml);
+        if let Some(ref cover) = self.cover {
+            let xhtml_filename = cover.lock().unwrap().xhtml_filename.clone();
+            if !xhtml_filename.is_empty() {
+                let href = format!("{}/{}", XHTML_FOLDER_NAME, xhtml_filename);
+                package.add_guide(GuideReference::new("cover", "Cover", href));
             }
-            Ok(())
-        };
-        write_toc_file(&mut toc, V20)?;
+        }
 
+        // 关联到 manifest 的元数据在打开时记录为文件路径，这里换成 id
         if self.version == V30 {
-            write_toc_file(&mut toc, V30)?;
+            for meta in self.properties.iter() {
+                let mut meta = meta.clone();
+                let target = meta.refines.strip_prefix('#').unwrap_or_default();
+                if let Some(item) = package.manifest().iter().find(|item| item.href == target) {
+                    meta.refines = format!("#{}", item.id);
+                }
+                metadata.add_meta(meta);
+            }
         }
-        Ok(())
+
+        package.set_metadata(metadata);
+        package.encode_xml(self.version)
     }
-    fn write_cover(&mut self, cover_path: &Path) -> Result<(), Error> {
-        Ok(())
+
+    /// 没有标识符时生成 UUID，可复现模式下由包文件和所有文件的内容计算
+    fn generated_identifier(&mut self) -> Result<Identifier, Error> {
+        if !self.reproducible {
+            return Ok(Identifier::random_uuid(BOOK_ID));
+        }
+        let mut hashes = [0xcbf2_9ce4_8422_2325, 0x6c62_272e_07bb_0142];
+        // 每段内容前加上长度，避免不同的分段得到相同的结果
+        let mut feed = |bytes: &[u8]| {
+            for hash in hashes.iter_mut() {
+                *hash = fnv1a(&(bytes.len() as u64).to_be_bytes(), *hash);
+                *hash = fnv1a(bytes, *hash);
+            }
+        };
+        feed(self.encode_package_xml()?.as_bytes());
+        self.for_each_section(|section| {
+            feed(section.filename.as_bytes());
+            feed(section.xhtml.encode_xml()?.as_bytes());
+            Ok(())
+        })?;
+        for (folder_name, media) in self.media_collections() {
+            for key in sorted_keys(media) {
+                if let Some(item) = media.get(&key) {
+                    feed(format!("{}/{}", folder_name, key).as_bytes());
+                    feed(&item.value().read()?);
+                }
+            }
+        }
+        for key in sorted_keys(&self.resources) {
+            if let Some(item) = self.resources.get(&key) {
+                feed(key.as_bytes());
+                feed(&item.value().source.read()?);
+            }
+        }
+
+        let mut bytes = [0u8; 16];
+        bytes[..8].copy_from_slice(&hashes[0].to_be_bytes());
+        bytes[8..].copy_from_slice(&hashes[1].to_be_bytes());
+        // version 8，自定义生成的 UUID
+        bytes[6] = (bytes[6] & 0x0f) | 0x80;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+        Ok(Identifier::uuid(BOOK_ID, format_uuid(&bytes)))
+    }
+
+    /// `dcterms:modified` 的值，可复现模式下优先使用 `SOURCE_DATE_EPOCH`
+    ///
+    /// EPUB 3 必须有修改时间，未设置时使用当前时间；可复现模式下使用 zip 的最早时间
+    /// 1980-01-01，与文件的时间戳一致。
+    fn modified_date(&self) -> Result<Option<chrono::DateTime<chrono::Utc>>, Error> {
+        if let Some(timestamp) = self.source_date_epoch()? {
+            return Ok(Some(timestamp));
+        }
+        let modified = self.modified.map(chrono::DateTime::<chrono::Utc>::from);
+        if modified.is_some() || self.version != V30 {
+            return Ok(modified);
+        }
+        if self.reproducible {
+            return Ok(chrono::DateTime::from_timestamp(ZIP_EPOCH, 0));
+        }
+        Ok(chrono::DateTime::from_timestamp(
+            chrono::Utc::now().timestamp(),
+            0,
+        ))
+    }
+
+    /// 可复现模式下读取 `SOURCE_DATE_EPOCH` 环境变量
+    fn source_date_epoch(&self) -> Result<Option<chrono::DateTime<chrono::Utc>>, Error> {
+        if !self.reproducible {
+            return Ok(None);
+        }
+        let Ok(value) = std::env::var(SOURCE_DATE_EPOCH) else {
+            return Ok(None);
+        };
+        value
+            .trim()
+            .parse::<i64>()
+            .ok()
+            .and_then(|seconds| chrono::DateTime::from_timestamp(seconds, 0))
+            .map(Some)
+            .ok_or_else(|| {
+                Error::InvalidMetadataErr(format!("Invalid {}:{}", SOURCE_DATE_EPOCH, value))
+            })
+    }
+
+    /// 所有媒体资源集合及其所在目录
+    fn media_collections(&self) -> [(&'static str, &DashMap<String, MediaSource>); 5] {
+        [
+            (CSS_FOLDER_NAME, &self.stylesheet),
+            (FONT_FOLDER_NAME, &self.fonts),
+            (IMAGE_FOLDER_NAME, &self.images),
+            (VIDEO_FOLDER_NAME, &self.videos),
+            (AUDIO_FOLDER_NAME, &self.audios),
+        ]
     }
 
-    /// 递归将 Section 转换为 TocElement
-    fn convert_section(section: &Section, index: i32, depth: usize, max_depth: &mut usize)
-        -> TocElement {
-        if depth > *max_depth {
-            *max_depth = depth;
+    fn language_or_default(&self) -> &str {
+        self.language.as_deref().unwrap_or("zh-CN")
+    }
+
+    /// 生成目录文件，返回 (文件名, 文件内容) 列表
+    fn encode_toc_xml(&self) -> Result<Vec<(String, String)>, Error> {
+        let mut toc = self.toc();
+        let uid = self
+            .identifier
+            .as_ref()
+            .map(|i| i.value(self.version == V30));
+        toc.add_metadata("dtb:uid", uid.unwrap_or_default());
+        let max_page = toc
+            .pages()
+            .iter()
+            .filter_map(|page| page.name.parse::<u32>().ok())
+            .max();
+        toc.add_metadata("dtb:totalPageCount", toc.pages().len().to_string());
+        toc.add_metadata(
+            "dtb:maxPageNumber",
+            max_page.unwrap_or_default().to_string(),
+        );
+
+        let mut toc_files = vec![(String::from(NCX_FILENAME), toc.encode_file(V20)?)];
+        if self.version == V30 {
+            toc_files.push((String::from(NAV_FILENAME), toc.encode_file(V30)?));
         }
+        Ok(toc_files)
+    }
+
+    /// 递归将 Section 转换为 TocElement，`level` 为嵌套层级，顶层为 1
+    fn convert_section(section: &Section, level: i32, heading_depth: usize) -> TocElement {
+        // 正文中的标题排在子章节之前
+        let mut childs = heading_elements(section, heading_depth, level + 1);
+        childs.extend(section.childs.iter().map(|item| {
+            Self::convert_section(item, level + 1, heading_depth) // 递归转换子节点
+        }));
 
         TocElement {
-            level: index,
+            level,
             url: format!("{}/{}", XHTML_FOLDER_NAME, section.filename.clone()),
             title: section.title.clone(),
-            childs: section
-                .childs
-                .iter()
-                .map(|item| Self::convert_section(item, index + 1, depth + 1, max_depth)) // 递归转换子节点
-                .collect(),
+            childs,
         }
     }
+}
 
-    /// 创建文件夹
-    fn create_folder(&mut self,output_path: &Path) -> Result<(), Error> {
-        let folder_path = Path::new(output_path);
-        write::create_epub_folders(folder_path)?;
-        write::create_media_folder(folder_path,CSS_FOLDER_NAME,&mut self.stylesheet)?;
-        write::create_media_folder(folder_path,IMAGE_FOLDER_NAME,&mut self.images)?;
-        write::create_media_folder(folder_path,FONT_FOLDER_NAME,&mut self.fonts)?;
-        write::create_media_folder(folder_path,VIDEO_FOLDER_NAME,&mut self.videos)?;
-        write::create_media_folder(folder_path,AUDIO_FOLDER_NAME,&mut self.audios)?;
+/// 64 位 FNV-1a 哈希
+fn fnv1a(bytes: &[u8], offset: u64) -> u64 {
+    bytes.iter().fold(offset, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
 
-        if !self.filenames.is_empty() {
-            let media_folder_path = folder_path.join(CONTENT_FOLDER_NAME).join(XHTML_FOLDER_NAME);
-            if let Err(e) = fs::create_dir_all(&media_folder_path) {
-                return Err(Error::PathCreateErr(format!("Could not create media folder:{}", e)));
+/// 将文件路径转换为合法的 manifest id (XML NCName)，与 `used` 中的 id 重复时追加序号
+fn manifest_id(href: &str, used: &mut HashSet<String>) -> String {
+    let id: String = href
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
             }
+        })
+        .collect();
+    let id = match id.chars().next() {
+        Some(c) if c.is_alphabetic() || c == '_' => id,
+        _ => format!("id_{}", id),
+    };
+    let id = if used.contains(&id) {
+        (2..)
+            .map(|index| format!("{}_{}", id, index))
+            .find(|id| !used.contains(id))
+            .unwrap()
+    } else {
+        id
+    };
+    used.insert(id.clone());
+    id
+}
+
+/// 按名称排序的键，保证输出顺序稳定
+fn sorted_keys<V>(map: &DashMap<String, V>) -> Vec<String> {
+    let mut keys: Vec<String> = map.iter().map(|item| item.key().clone()).collect();
+    keys.sort();
+    keys
+}
+
+/// 是否包含指定的 manifest properties
+fn has_property(item: &ManifestItem, property: &str) -> bool {
+    item.properties.split_whitespace().any(|p| p == property)
+}
+
+/// 根据媒体类型确定资源所在目录，不支持的类型返回 `None`
+fn media_folder(media_type: &str) -> Option<&'static str> {
+    match media_type {
+        MEDIA_TYPE_CSS => Some(CSS_FOLDER_NAME),
+        "application/vnd.ms-opentype" => Some(FONT_FOLDER_NAME),
+        t if t.starts_with("font/")
+            || t.starts_with("application/font-")
+            || t.starts_with("application/x-font-") =>
+        {
+            Some(FONT_FOLDER_NAME)
         }
-        Ok(())
+        t if t.starts_with("image/") => Some(IMAGE_FOLDER_NAME),
+        t if t.starts_with("video/") => Some(VIDEO_FOLDER_NAME),
+        t if t.starts_with("audio/") => Some(AUDIO_FOLDER_NAME),
+        _ => None,
+    }
+}
+
+/// 文件名已被占用时追加序号，如 `image_1.png`
+fn unique_filename<F: Fn(&str) -> bool>(filename: &str, used: F) -> String {
+    if !used(filename) {
+        return filename.to_string();
     }
+    let (stem, ext) = match filename.rsplit_once('.') {
+        Some((stem, ext)) => (stem, format!(".{}", ext)),
+        None => (filename, String::new()),
+    };
+    (1..)
+        .map(|index| format!("{}_{}{}", stem, index, ext))
+        .find(|name| !used(name))
+        .unwrap()
+}
+
+/// 将 `old_base` 中的链接改写为相对于 `new_base` 的链接，目标未移动时返回 `None`
+fn relink(
+    href: &str,
+    old_base: &str,
+    new_base: &str,
+    moved: &HashMap<String, String>,
+) -> Option<String> {
+    // 跳过片段、外部链接和 data: 等带协议的地址
+    if href.is_empty() || href.starts_with('#') || href.contains(':') {
+        return None;
+    }
+    let (path, fragment) = match href.split_once('#') {
+        Some((path, fragment)) => (path, Some(fragment)),
+        None => (href, None),
+    };
+    let target = moved.get(&resolve_path(old_base, path))?;
+    let mut relinked = relative_path(new_base, target);
+    if let Some(fragment) = fragment {
+        relinked.push('#');
+        relinked.push_str(fragment);
+    }
+    (relinked != href).then_some(relinked)
+}
+
+/// 章节正文中 `h1` 到 `h{depth}` 标题组成的目录项，跳过重复章节标题的第一个标题
+fn heading_elements(section: &Section, depth: usize, level: i32) -> Vec<TocElement> {
+    if depth == 0 {
+        return Vec::new();
+    }
+    let url = format!("{}/{}", XHTML_FOLDER_NAME, section.filename);
+    let mut headings = markup::headings(section.xhtml.body());
+    if headings
+        .first()
+        .is_some_and(|heading| heading.text == section.title)
+    {
+        headings.remove(0);
+    }
+
+    let mut elements = Vec::new();
+    // (标题级别, 目录项)，级别递增
+    let mut stack: Vec<(usize, TocElement)> = Vec::new();
+    let attach = |stack: &mut Vec<(usize, TocElement)>, elements: &mut Vec<TocElement>| {
+        if let Some((_, element)) = stack.pop() {
+            match stack.last_mut() {
+                Some((_, parent)) => parent.childs.push(element),
+                None => elements.push(element),
+            }
+        }
+    };
+    for heading in headings {
+        let Some(id) = heading.id.filter(|_| heading.level <= depth) else {
+            continue;
+        };
+        while stack.last().is_some_and(|(open, _)| *open >= heading.level) {
+            attach(&mut stack, &mut elements);
+        }
+        let element = TocElement {
+            level: level + stack.len() as i32,
+            url: format!("{}#{}", url, id),
+            title: heading.text,
+            childs: Vec::new(),
+        };
+        stack.push((heading.level, element));
+    }
+    while !stack.is_empty() {
+        attach(&mut stack, &mut elements);
+    }
+    elements
+}
+
+/// 检查 `base_file` 中的一个链接，记录被引用的文件
+fn check_link(
+    base_file: &str,
+    location: Location,
+    href: &str,
+    targets: &HashMap<String, Option<HashSet<String>>>,
+    referenced: &mut HashSet<String>,
+    report: &mut Report,
+) {
+    // 跳过外部链接和 data: 等带协议的地址
+    let href = href.trim();
+    if href.is_empty() || href.contains(':') {
+        return;
+    }
+    let (path, fragment) = match href.split_once('#') {
+        Some((path, fragment)) => (path, Some(fragment)),
+        None => (href, None),
+    };
+    let target = if path.is_empty() {
+        base_file.to_string()
+    } else {
+        resolve_path(base_file, path)
+    };
+    let Some(ids) = targets.get(&target) else {
+        report.push(Diagnostic::new(
+            Severity::Error,
+            DiagnosticKind::BrokenLink,
+            location,
+            format!("{} does not resolve to a file of the book", href),
+        ));
+        return;
+    };
+    referenced.insert(target.clone());
+    if let (Some(ids), Some(fragment)) = (ids, fragment) {
+        let fragment = percent_decode(fragment);
+        if !fragment.is_empty() && !ids.contains(&fragment) {
+            report.push(Diagnostic::new(
+                Severity::Error,
+                DiagnosticKind::MissingFragment,
+                location,
+                format!("Fragment #{} not found in {}", fragment, target),
+            ));
+        }
+    }
+}
+
+/// 计算 `target` 相对于 `base_file` 所在目录的路径
+fn relative_path(base_file: &str, target: &str) -> String {
+    let base: Vec<&str> = base_file.split('/').collect();
+    let base = &base[..base.len() - 1];
+    let target: Vec<&str> = target.split('/').collect();
+    let common = base
+        .iter()
+        .zip(&target[..target.len() - 1])
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut parts = vec![".."; base.len() - common];
+    parts.extend(&target[common..]);
+    parts.join("/")
+}
+
+/// 收集目录中每个文件的标题和上级文件，只记录文件第一次出现的位置
+fn collect_toc(
+    elements: &[TocElement],
+    toc_path: &str,
+    parent: Option<&str>,
+    titles: &mut HashMap<String, String>,
+    parents: &mut HashMap<String, String>,
+) {
+    for element in elements {
+        let full_path = (!element.url.is_empty()).then(|| resolve_path(toc_path, &element.url));
+        if let Some(ref full_path) = full_path {
+            if !titles.contains_key(full_path) {
+                titles.insert(full_path.clone(), element.title.clone());
+                if let Some(parent) = parent.filter(|p| p != full_path) {
+                    parents.insert(full_path.clone(), parent.to_string());
+                }
+            }
+        }
+        let parent = full_path.as_deref().or(parent);
+        collect_toc(&element.childs, toc_path, parent, titles, parents);
+    }
+}
+
+/// 按文件名查找章节
+fn find_section_mut<'a>(sections: &'a mut [Section], filename: &str) -> Option<&'a mut Section> {
+    for section in sections {
+        if section.filename == filename {
+            return Some(section);
+        }
+        if let Some(found) = find_section_mut(&mut section.childs, filename) {
+            return Some(found);
+        }
+    }
+    None
 }
 
 #[derive(Debug)]
@@ -684,7 +2097,15 @@ impl Cover {
     }
 }
 
+/// 原样保留的资源及其媒体类型和 manifest 属性
 #[derive(Debug)]
+struct Resource {
+    media_type: String,
+    properties: String,
+    source: MediaSource,
+}
+
+#[derive(Debug, Default)]
 #[allow(dead_code)]
 struct Section {
     filename: String,
@@ -693,16 +2114,6 @@ struct Section {
     childs: Vec<Section>,
 }
 
-impl Default for Section {
-    fn default() -> Section {
-        Section {
-            filename: String::new(),
-            title: String::new(),
-            xhtml: XHtmlRoot::default(),
-            childs: Vec::new(),
-        }
-    }
-}
 impl Section {
     #[allow(dead_code)]
     pub fn new<S: Into<String>>(filename: S) -> Section {
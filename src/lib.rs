@@ -1,13 +1,23 @@
 use dashmap::DashMap;
 use quick_xml::se::Serializer;
 use serde::Serialize;
+use std::fmt;
+use std::fs;
+use std::io::Read;
 use std::path::{Path, PathBuf};
 
+pub mod container;
+mod entities;
 pub mod epub;
+mod markdown;
+mod markup;
 pub mod mime;
 pub mod opf;
+pub mod reader;
+pub mod text;
 pub mod toc;
-mod write;
+pub mod validate;
+pub mod write;
 pub mod xhtml;
 
 /// 错误信息枚举
@@ -53,6 +63,18 @@ pub enum Error {
 
     /// Serialize error
     SerializeErr(String),
+
+    /// Deserialize error
+    DeserializeErr(String),
+
+    /// Zip archive error
+    ArchiveErr(String),
+
+    /// Section body is not well-formed XHTML
+    MalformedXhtmlErr(String),
+
+    /// Invalid regular expression
+    InvalidPatternErr(String),
 }
 
 impl From<std::io::Error> for Error {
@@ -74,33 +96,145 @@ fn combine_dirs(dir_names: &[&str]) -> String {
     path.to_str().unwrap().to_string()
 }
 
+/// The content of a media resource.
+///
+/// Paths are checked when the resource is added and read on output, readers are read when
+/// the resource is added, and loader closures are only called on output.
+pub enum MediaSource {
+    /// A file on disk
+    Path(String),
+    /// Bytes held in memory
+    Bytes(Vec<u8>),
+    /// A closure producing the bytes when the epub is written
+    Loader(Box<dyn Fn() -> Result<Vec<u8>, Error> + Send + Sync>),
+}
+
+impl MediaSource {
+    /// Read a stream to the end
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<MediaSource, Error> {
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content)?;
+        Ok(MediaSource::Bytes(content))
+    }
+
+    /// Load the bytes lazily when the epub is written
+    pub fn lazy<F>(loader: F) -> MediaSource
+    where
+        F: Fn() -> Result<Vec<u8>, Error> + Send + Sync + 'static,
+    {
+        MediaSource::Loader(Box::new(loader))
+    }
+
+    /// 读取资源内容
+    pub(crate) fn read(&self) -> Result<Vec<u8>, Error> {
+        match self {
+            MediaSource::Path(path) => fs::read(path)
+                .map_err(|e| Error::PathCreateErr(format!("Could not read file:{}", e))),
+            MediaSource::Bytes(bytes) => Ok(bytes.clone()),
+            MediaSource::Loader(loader) => loader(),
+        }
+    }
+
+    /// 文件路径的文件名，其他来源返回 `None`
+    pub(crate) fn file_name(&self) -> Option<String> {
+        match self {
+            MediaSource::Path(path) => Path::new(path)
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map(String::from),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Debug for MediaSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MediaSource::Path(path) => f.debug_tuple("Path").field(path).finish(),
+            MediaSource::Bytes(bytes) => write!(f, "Bytes({} bytes)", bytes.len()),
+            MediaSource::Loader(_) => f.write_str("Loader"),
+        }
+    }
+}
+
+impl From<&str> for MediaSource {
+    fn from(path: &str) -> Self {
+        MediaSource::Path(path.to_string())
+    }
+}
+
+impl From<String> for MediaSource {
+    fn from(path: String) -> Self {
+        MediaSource::Path(path)
+    }
+}
+
+impl From<&String> for MediaSource {
+    fn from(path: &String) -> Self {
+        MediaSource::Path(path.clone())
+    }
+}
+
+impl From<&Path> for MediaSource {
+    fn from(path: &Path) -> Self {
+        MediaSource::Path(path.to_string_lossy().into_owned())
+    }
+}
+
+impl From<PathBuf> for MediaSource {
+    fn from(path: PathBuf) -> Self {
+        MediaSource::from(path.as_path())
+    }
+}
+
+impl From<Vec<u8>> for MediaSource {
+    fn from(bytes: Vec<u8>) -> Self {
+        MediaSource::Bytes(bytes)
+    }
+}
+
+impl From<&[u8]> for MediaSource {
+    fn from(bytes: &[u8]) -> Self {
+        MediaSource::Bytes(bytes.to_vec())
+    }
+}
+
 /// 添加媒体文件
-fn add_media<S1: Into<String>, S2: Into<String>>(
-    source: S1,
+///
+/// 未指定内部文件名时使用源文件名，内存数据和加载函数必须指定文件名。
+fn add_media<S: Into<String>>(
+    source: MediaSource,
     internal_filename: Option<String>,
     media_file_format: String,
-    media_folder_name: S2,
-    hashmap: &DashMap<String, String>,
+    media_folder_name: S,
+    hashmap: &DashMap<String, MediaSource>,
 ) -> Result<String, Error> {
-    let source_str = source.into();
     // Check if file exists
-    if !Path::new(&source_str).exists() {
-        return Err(Error::FileNotFoundErr(format!(
-            "File not found:{}",
-            &source_str
-        )));
+    if let MediaSource::Path(ref path) = source {
+        if !Path::new(path).exists() {
+            return Err(Error::FileNotFoundErr(format!("File not found:{}", path)));
+        }
     }
-    let filename = internal_filename.unwrap_or_else(|| {
-        let file_path = Path::new(&source_str);
-        let basename = file_path.file_name().unwrap().to_str().unwrap();
-
-        if basename.len() > 255 || hashmap.contains_key(basename) {
-            let ext = file_path.extension().and_then(|osstr| osstr.to_str()).unwrap_or("jpg");
-            format!("{}_{}.{}", media_file_format, hashmap.len() + 1, ext)
-        } else {
-            basename.to_string()
+    let filename = match internal_filename {
+        Some(filename) => filename,
+        None => {
+            let basename = source.file_name().ok_or_else(|| {
+                Error::MediaError(format!(
+                    "Internal filename required for {} data",
+                    media_file_format
+                ))
+            })?;
+            if basename.len() > 255 || hashmap.contains_key(&basename) {
+                let ext = Path::new(&basename)
+                    .extension()
+                    .and_then(|osstr| osstr.to_str())
+                    .unwrap_or("jpg");
+                format!("{}_{}.{}", media_file_format, hashmap.len() + 1, ext)
+            } else {
+                basename
+            }
         }
-    });
+    };
 
     if hashmap.contains_key(&filename) {
         return Err(Error::FilenameUsedErr(format!(
@@ -109,7 +243,7 @@ fn add_media<S1: Into<String>, S2: Into<String>>(
         )));
     }
 
-    hashmap.insert(filename.clone(), source_str);
+    hashmap.insert(filename.clone(), source);
 
     Ok(format!("../{}/{}", media_folder_name.into(), filename))
 }
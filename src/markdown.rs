@@ -0,0 +1,108 @@
+//! Markdown 转换为 XHTML 正文
+
+use crate::{markup, Error};
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag, TagEnd};
+use std::collections::{HashMap, HashSet};
+
+/// CommonMark 加表格、脚注、删除线和标题属性 `{#id}`
+fn options() -> Options {
+    Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_HEADING_ATTRIBUTES
+}
+
+/// 将 Markdown 转换为 XHTML 正文
+///
+/// 本地图片地址交给 `image` 处理并替换为其返回值，没有 `{#id}` 的标题按标题文字生成 id，
+/// 脚注输出为 EPUB 的 `noteref` 和 `footnote`。
+pub(crate) fn to_xhtml<F>(markdown: &str, mut image: F) -> Result<String, Error>
+where
+    F: FnMut(&str) -> Result<String, Error>,
+{
+    let mut events: Vec<Event> = Parser::new_ext(markdown, options()).collect();
+
+    let mut used: HashSet<String> = events
+        .iter()
+        .filter_map(|event| match event {
+            Event::Start(Tag::Heading { id: Some(id), .. }) => Some(id.to_string()),
+            _ => None,
+        })
+        .collect();
+    let mut footnotes: HashMap<String, usize> = HashMap::new();
+    for index in 0..events.len() {
+        if let Event::Start(Tag::Heading { id: None, .. }) = &events[index] {
+            let mut text = String::new();
+            for event in events[index + 1..].iter() {
+                match event {
+                    Event::End(TagEnd::Heading(_)) => break,
+                    Event::Text(value) | Event::Code(value) => text.push_str(value),
+                    _ => {}
+                }
+            }
+            if let Event::Start(Tag::Heading { id, .. }) = &mut events[index] {
+                *id = Some(CowStr::from(markup::unique_id(&text, &mut used)));
+            }
+            continue;
+        }
+        let html = match &mut events[index] {
+            Event::Start(Tag::Image { dest_url, .. }) => {
+                if is_local(dest_url) {
+                    *dest_url = CowStr::from(image(dest_url)?);
+                }
+                continue;
+            }
+            Event::FootnoteReference(label) => {
+                let number = footnote_number(label, &mut footnotes);
+                Event::InlineHtml(CowStr::from(format!(
+                    "<sup class=\"footnote-reference\"><a href=\"#{}\" epub:type=\"noteref\">{}</a></sup>",
+                    footnote_id(label),
+                    number
+                )))
+            }
+            Event::Start(Tag::FootnoteDefinition(label)) => {
+                let number = footnote_number(label, &mut footnotes);
+                Event::Html(CowStr::from(format!(
+                    "<aside class=\"footnote-definition\" id=\"{}\" epub:type=\"footnote\">\
+                     <sup class=\"footnote-definition-label\">{}</sup>\n",
+                    footnote_id(label),
+                    number
+                )))
+            }
+            Event::End(TagEnd::FootnoteDefinition) => Event::Html(CowStr::from("</aside>\n")),
+            _ => continue,
+        };
+        events[index] = html;
+    }
+
+    let mut output = String::with_capacity(markdown.len() * 3 / 2);
+    html::push_html(&mut output, events.into_iter());
+    // 内嵌的 HTML 可能不是格式正确的 XML
+    Ok(markup::repair(&output))
+}
+
+/// 本地文件地址，不含协议和片段
+fn is_local(url: &str) -> bool {
+    !url.is_empty() && !url.starts_with('#') && !url.contains(':')
+}
+
+/// 脚注按首次出现的顺序编号
+fn footnote_number(label: &str, footnotes: &mut HashMap<String, usize>) -> usize {
+    let next = footnotes.len() + 1;
+    *footnotes.entry(label.to_string()).or_insert(next)
+}
+
+/// 脚注标签对应的元素 id
+fn footnote_id(label: &str) -> String {
+    let label: String = label
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!("fn-{}", label)
+}
@@ -0,0 +1,1140 @@
+//! 简单的 XHTML 标记扫描器，用于检查和修复章节正文
+
+use crate::entities::html_entity;
+use crate::validate::{Diagnostic, DiagnosticKind, Location, Severity};
+use crate::xhtml::{Sanitizer, REFERENCE_ATTRIBUTES};
+use std::collections::HashSet;
+
+/// XML 预定义实体
+const XML_ENTITIES: [&str; 5] = ["amp", "lt", "gt", "quot", "apos"];
+
+/// HTML 空元素，修复时自动闭合
+const VOID_ELEMENTS: [&str; 16] = [
+    "area", "base", "basefont", "br", "col", "embed", "frame", "hr", "img", "input", "isindex",
+    "link", "meta", "param", "source", "track",
+];
+
+#[derive(Debug)]
+pub(crate) struct Attribute<'a> {
+    pub(crate) name: &'a str,
+    /// 属性值原文（不含引号），没有值时为 `None`
+    pub(crate) value: Option<&'a str>,
+    pub(crate) quoted: bool,
+    pub(crate) offset: usize,
+    pub(crate) value_offset: usize,
+}
+
+#[derive(Debug)]
+pub(crate) enum Token<'a> {
+    Text(&'a str),
+    /// 完整的实体引用，如 `&amp;`、`&#160;`
+    Reference(&'a str),
+    /// 不构成实体引用的 `&`
+    BareAmpersand,
+    /// 不构成标签的 `<`
+    BareLessThan,
+    StartTag {
+        name: &'a str,
+        attributes: Vec<Attribute<'a>>,
+        self_closing: bool,
+        terminated: bool,
+    },
+    EndTag {
+        name: &'a str,
+        terminated: bool,
+    },
+    /// 注释、CDATA、处理指令和 DOCTYPE
+    Other {
+        raw: &'a str,
+        terminator: &'static str,
+        terminated: bool,
+    },
+    Invalid(char),
+}
+
+/// 按顺序输出 (字节偏移, 标记)
+pub(crate) struct Tokenizer<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl<'a> Tokenizer<'a> {
+    pub(crate) fn new(input: &'a str) -> Self {
+        Tokenizer { input, position: 0 }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.position..]
+    }
+
+    /// 跳过 `script` 等元素的原始文本，停在第一个不区分大小写的 `</name` 处，返回跳过的文本
+    pub(crate) fn skip_raw_text(&mut self, name: &str) -> &'a str {
+        let start = self.position;
+        let rest = self.rest().to_ascii_lowercase();
+        let end_tag = format!("</{}", name.to_ascii_lowercase());
+        let mut offset = 0;
+        while let Some(index) = rest[offset..].find(&end_tag) {
+            let end = offset + index + end_tag.len();
+            // `</scripts` 不是 `</script` 的结束标签
+            if !rest[end..].starts_with(|c: char| c.is_ascii_alphanumeric() || c == '-') {
+                self.position += offset + index;
+                return &self.input[start..self.position];
+            }
+            offset = end;
+        }
+        self.position = self.input.len();
+        &self.input[start..]
+    }
+
+    /// 读取直到 `terminator`，返回是否找到结束符
+    fn read_until(&mut self, start: usize, terminator: &'static str) -> (&'a str, bool) {
+        match self.input[start..].find(terminator) {
+            Some(index) => {
+                self.position = start + index + terminator.len();
+                (
+                    &self.input[self.position - (index + terminator.len())..self.position],
+                    true,
+                )
+            }
+            None => {
+                self.position = self.input.len();
+                (&self.input[start..], false)
+            }
+        }
+    }
+
+    fn read_reference(&mut self) -> Token<'a> {
+        let rest = self.rest();
+        let body = &rest[1..];
+        let length = if let Some(numeric) = body.strip_prefix('#') {
+            let (digits, radix_length) = match numeric.strip_prefix(['x', 'X']) {
+                Some(hex) => (
+                    hex.len()
+                        - hex
+                            .trim_start_matches(|c: char| c.is_ascii_hexdigit())
+                            .len(),
+                    1,
+                ),
+                None => (
+                    numeric.len()
+                        - numeric
+                            .trim_start_matches(|c: char| c.is_ascii_digit())
+                            .len(),
+                    0,
+                ),
+            };
+            (digits > 0).then_some(1 + radix_length + digits)
+        } else if body.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            Some(
+                body.len()
+                    - body
+                        .trim_start_matches(|c: char| c.is_ascii_alphanumeric())
+                        .len(),
+            )
+        } else {
+            None
+        };
+        match length {
+            Some(length) if body[length..].starts_with(';') => {
+                let raw = &rest[..length + 2];
+                self.position += raw.len();
+                Token::Reference(raw)
+            }
+            _ => {
+                self.position += 1;
+                Token::BareAmpersand
+            }
+        }
+    }
+
+    fn read_tag(&mut self) -> Token<'a> {
+        let start = self.position;
+        let rest = self.rest();
+        if rest.starts_with("<!--") {
+            let (raw, terminated) = self.read_until(start + 4, "-->");
+            let raw = &self.input[start..start + 4 + raw.len()];
+            return Token::Other {
+                raw,
+                terminator: "-->",
+                terminated,
+            };
+        }
+        if rest.starts_with("<![CDATA[") {
+            let (raw, terminated) = self.read_until(start + 9, "]]>");
+            let raw = &self.input[start..start + 9 + raw.len()];
+            return Token::Other {
+                raw,
+                terminator: "]]>",
+                terminated,
+            };
+        }
+        if rest.starts_with("<?") {
+            let (raw, terminated) = self.read_until(start + 2, "?>");
+            let raw = &self.input[start..start + 2 + raw.len()];
+            return Token::Other {
+                raw,
+                terminator: "?>",
+                terminated,
+            };
+        }
+        if rest.starts_with("<!") {
+            let (raw, terminated) = self.read_until(start + 2, ">");
+            let raw = &self.input[start..start + 2 + raw.len()];
+            return Token::Other {
+                raw,
+                terminator: ">",
+                terminated,
+            };
+        }
+        let is_end = rest.starts_with("</");
+        let name_start = start + if is_end { 2 } else { 1 };
+        let name_length = self.input[name_start..]
+            .find(|c: char| c.is_whitespace() || c == '/' || c == '>' || c == '<')
+            .unwrap_or(self.input.len() - name_start);
+        let name = &self.input[name_start..name_start + name_length];
+        if !name.starts_with(|c: char| c.is_alphabetic() || c == '_' || c == ':') {
+            self.position += 1;
+            return Token::BareLessThan;
+        }
+        self.position = name_start + name_length;
+        if is_end {
+            let rest = self.rest();
+            let terminated = rest.trim_start().starts_with('>');
+            self.position += if terminated {
+                rest.find('>').unwrap() + 1
+            } else {
+                rest.len() - rest.trim_start().len()
+            };
+            return Token::EndTag { name, terminated };
+        }
+
+        let mut attributes = Vec::new();
+        loop {
+            let rest = self.rest();
+            self.position += rest.len() - rest.trim_start().len();
+            let rest = self.rest();
+            if rest.is_empty() || rest.starts_with('<') {
+                return Token::StartTag {
+                    name,
+                    attributes,
+                    self_closing: false,
+                    terminated: false,
+                };
+            }
+            if let Some(after) = rest.strip_prefix("/>") {
+                self.position = self.input.len() - after.len();
+                return Token::StartTag {
+                    name,
+                    attributes,
+                    self_closing: true,
+                    terminated: true,
+                };
+            }
+            if rest.starts_with('>') {
+                self.position += 1;
+                return Token::StartTag {
+                    name,
+                    attributes,
+                    self_closing: false,
+                    terminated: true,
+                };
+            }
+            if rest.starts_with('/') {
+                self.position += 1;
+                continue;
+            }
+            let offset = self.position;
+            let name_length = rest
+                .find(|c: char| c.is_whitespace() || c == '=' || c == '>' || c == '/' || c == '<')
+                .unwrap_or(rest.len())
+                .max(1);
+            let attribute_name = &rest[..name_length];
+            self.position += name_length;
+            let rest = self.rest();
+            let after_space = rest.trim_start();
+            let Some(value_part) = after_space.strip_prefix('=') else {
+                attributes.push(Attribute {
+                    name: attribute_name,
+                    value: None,
+                    quoted: false,
+                    offset,
+                    value_offset: offset,
+                });
+                continue;
+            };
+            let value_part = value_part.trim_start();
+            self.position = self.input.len() - value_part.len();
+            match value_part.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let value_start = self.position + 1;
+                    match self.input[value_start..].find(quote) {
+                        Some(length) => {
+                            let value = &self.input[value_start..value_start + length];
+                            self.position = value_start + length + 1;
+                            attributes.push(Attribute {
+                                name: attribute_name,
+                                value: Some(value),
+                                quoted: true,
+                                offset,
+                                value_offset: value_start,
+                            });
+                        }
+                        None => {
+                            let value = &self.input[value_start..];
+                            self.position = self.input.len();
+                            attributes.push(Attribute {
+                                name: attribute_name,
+                                value: Some(value),
+                                quoted: true,
+                                offset,
+                                value_offset: value_start,
+                            });
+                            return Token::StartTag {
+                                name,
+                                attributes,
+                                self_closing: false,
+                                terminated: false,
+                            };
+                        }
+                    }
+                }
+                _ => {
+                    let length = value_part
+                        .find(|c: char| c.is_whitespace() || c == '>' || c == '<')
+                        .unwrap_or(value_part.len());
+                    // `<a href=x/>` 中最后的 `/` 属于自闭合标记
+                    let length = if value_part[length..].starts_with('>')
+                        && value_part[..length].ends_with('/')
+                    {
+                        length - 1
+                    } else {
+                        length
+                    };
+                    let value = &value_part[..length];
+                    let value_offset = self.position;
+                    self.position += length;
+                    attributes.push(Attribute {
+                        name: attribute_name,
+                        value: Some(value),
+                        quoted: false,
+                        offset,
+                        value_offset,
+                    });
+                }
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for Tokenizer<'a> {
+    type Item = (usize, Token<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.position;
+        let rest = self.rest();
+        let c = rest.chars().next()?;
+        let token = match c {
+            '&' => self.read_reference(),
+            '<' => self.read_tag(),
+            c if !is_xml_char(c) => {
+                self.position += c.len_utf8();
+                Token::Invalid(c)
+            }
+            _ => {
+                let length = rest
+                    .find(|c: char| c == '&' || c == '<' || !is_xml_char(c))
+                    .unwrap_or(rest.len());
+                self.position += length;
+                Token::Text(&rest[..length])
+            }
+        };
+        Some((start, token))
+    }
+}
+
+/// XML 1.0 允许的字符
+pub(crate) fn is_xml_char(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\r' | '\u{20}'..='\u{D7FF}' | '\u{E000}'..='\u{FFFD}' | '\u{10000}'..)
+}
+
+/// 数字字符引用对应的字符
+fn numeric_reference(reference: &str) -> Option<char> {
+    let body = reference.strip_prefix("&#")?.strip_suffix(';')?;
+    let code = match body.strip_prefix(['x', 'X']) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+        None => body.parse().ok()?,
+    };
+    char::from_u32(code)
+}
+
+fn is_void(name: &str) -> bool {
+    VOID_ELEMENTS.contains(&name.to_ascii_lowercase().as_str())
+}
+
+/// 字节偏移转换为行列号
+struct LineIndex {
+    starts: Vec<usize>,
+}
+
+impl LineIndex {
+    fn new(input: &str) -> Self {
+        let mut starts = vec![0];
+        starts.extend(input.match_indices('\n').map(|(index, _)| index + 1));
+        LineIndex { starts }
+    }
+
+    fn location(&self, input: &str, offset: usize) -> Location {
+        let line = self.starts.partition_point(|start| *start <= offset);
+        let column = input[self.starts[line - 1]..offset].chars().count() + 1;
+        Location {
+            file: String::new(),
+            line: Some(line),
+            column: Some(column),
+        }
+    }
+}
+
+/// 标记中引用其他资源的属性值（已解码实体），返回 (位置, 值)
+pub(crate) fn references(input: &str) -> Vec<(Location, String)> {
+    let index = LineIndex::new(input);
+    let mut references = Vec::new();
+    for (_, token) in Tokenizer::new(input) {
+        let Token::StartTag { attributes, .. } = token else {
+            continue;
+        };
+        for attribute in attributes {
+            let Some(value) = attribute.value else {
+                continue;
+            };
+            let name = attribute.name.to_ascii_lowercase();
+            if REFERENCE_ATTRIBUTES.contains(&name.as_str()) {
+                let value = quick_xml::escape::unescape(value)
+                    .map_or_else(|_| value.to_string(), |v| v.into_owned());
+                references.push((index.location(input, attribute.value_offset), value));
+            }
+        }
+    }
+    references
+}
+
+/// 标记中所有元素的 `id`
+pub(crate) fn element_ids(input: &str) -> HashSet<String> {
+    ordered_element_ids(input).into_iter().collect()
+}
+
+/// 标记中所有元素的 `id`，按文档顺序排列
+pub(crate) fn ordered_element_ids(input: &str) -> Vec<String> {
+    let mut ids = Vec::new();
+    for (_, token) in Tokenizer::new(input) {
+        let Token::StartTag { attributes, .. } = token else {
+            continue;
+        };
+        for attribute in attributes {
+            if let (true, Some(value)) = (attribute.name == "id", attribute.value) {
+                ids.push(value.to_string());
+            }
+        }
+    }
+    ids
+}
+
+/// 正文中的 `h1`–`h6` 标题
+#[derive(Debug)]
+pub(crate) struct Heading {
+    pub(crate) level: usize,
+    pub(crate) id: Option<String>,
+    /// 标题文字，已解码实体并合并空白
+    pub(crate) text: String,
+    /// 插入 `id` 属性的位置，即标签名之后
+    insert: usize,
+}
+
+/// 标签名对应的标题级别
+fn heading_level(name: &str) -> Option<usize> {
+    match name.to_ascii_lowercase().as_str() {
+        "h1" => Some(1),
+        "h2" => Some(2),
+        "h3" => Some(3),
+        "h4" => Some(4),
+        "h5" => Some(5),
+        "h6" => Some(6),
+        _ => None,
+    }
+}
+
+/// 按文档顺序列出标记中的标题
+pub(crate) fn headings(input: &str) -> Vec<Heading> {
+    let mut headings = Vec::new();
+    let mut current: Option<(&str, Heading)> = None;
+    for (offset, token) in Tokenizer::new(input) {
+        match (current.as_mut(), token) {
+            (
+                None,
+                Token::StartTag {
+                    name,
+                    attributes,
+                    self_closing: false,
+                    ..
+                },
+            ) => {
+                if let Some(level) = heading_level(name) {
+                    let id = attributes
+                        .iter()
+                        .find(|attribute| attribute.name == "id")
+                        .and_then(|attribute| attribute.value)
+                        .map(unescape_reference);
+                    let heading = Heading {
+                        level,
+                        id,
+                        text: String::new(),
+                        insert: offset + 1 + name.len(),
+                    };
+                    current = Some((name, heading));
+                }
+            }
+            (Some((_, heading)), Token::Text(text)) => heading.text.push_str(text),
+            (Some((_, heading)), Token::Reference(reference)) => {
+                heading.text.push_str(&unescape_reference(reference))
+            }
+            (Some((open, _)), Token::EndTag { name, .. }) if name.eq_ignore_ascii_case(open) => {
+                if let Some((_, mut heading)) = current.take() {
+                    heading.text = heading
+                        .text
+                        .split_whitespace()
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    headings.push(heading);
+                }
+            }
+            _ => {}
+        }
+    }
+    headings
+}
+
+/// `epub:type="pagebreak"` 元素的 (id, 页码)，页码取自 `title`、`aria-label` 或元素文字
+pub(crate) fn pagebreaks(input: &str) -> Vec<(String, String)> {
+    let mut pages = Vec::new();
+    // 正在读取文字的分页元素：(标签名, id)
+    let mut current: Option<(&str, String, String)> = None;
+    for (_, token) in Tokenizer::new(input) {
+        match (current.as_mut(), token) {
+            (
+                None,
+                Token::StartTag {
+                    name,
+                    attributes,
+                    self_closing,
+                    ..
+                },
+            ) => {
+                let value = |key: &str| {
+                    attributes
+                        .iter()
+                        .find(|attribute| attribute.name == key)
+                        .and_then(|attribute| attribute.value)
+                        .map(unescape_reference)
+                };
+                let is_pagebreak = value("epub:type")
+                    .is_some_and(|t| t.split_whitespace().any(|t| t == "pagebreak"));
+                let (true, Some(id)) = (is_pagebreak, value("id")) else {
+                    continue;
+                };
+                match value("title").or_else(|| value("aria-label")) {
+                    Some(label) => pages.push((id, label.trim().to_string())),
+                    None if !self_closing && !is_void(name) => {
+                        current = Some((name, id, String::new()))
+                    }
+                    None => pages.push((id.clone(), id)),
+                }
+            }
+            (Some((_, _, text)), Token::Text(value)) => text.push_str(value),
+            (Some((_, _, text)), Token::Reference(reference)) => {
+                text.push_str(&unescape_reference(reference))
+            }
+            (Some((open, _, _)), Token::EndTag { name, .. }) if name.eq_ignore_ascii_case(open) => {
+                if let Some((_, id, text)) = current.take() {
+                    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                    pages.push((id, text));
+                }
+            }
+            _ => {}
+        }
+    }
+    pages
+}
+
+/// 为 `max_level` 及以上级别缺少 `id` 的标题生成 id
+pub(crate) fn assign_heading_ids(input: &str, max_level: usize) -> String {
+    let mut used = element_ids(input);
+    let mut output = String::with_capacity(input.len());
+    let mut position = 0;
+    for heading in headings(input) {
+        if heading.level > max_level || heading.id.is_some() {
+            continue;
+        }
+        let id = unique_id(&heading.text, &mut used);
+        output.push_str(&input[position..heading.insert]);
+        output.push_str(&format!(
+            " id=\"{}\"",
+            id.replace('&', "&amp;").replace('"', "&quot;")
+        ));
+        position = heading.insert;
+    }
+    output.push_str(&input[position..]);
+    output
+}
+
+/// 解码实体引用，未知实体保留原文
+fn unescape_reference(reference: &str) -> String {
+    if let Ok(text) = quick_xml::escape::unescape(reference) {
+        return text.into_owned();
+    }
+    match html_entity(reference.trim_start_matches('&').trim_end_matches(';')) {
+        Some(c) => c.to_string(),
+        None => reference.to_string(),
+    }
+}
+
+/// 按标题文字生成唯一的 id
+pub(crate) fn unique_id(text: &str, used: &mut HashSet<String>) -> String {
+    let mut slug = String::new();
+    for c in text.trim().chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() || c == '_' || c == '-' {
+            slug.push(c);
+        } else if c.is_whitespace() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_matches('-');
+    // id 不能以数字开头
+    let base = match slug.chars().next() {
+        None => String::from("section"),
+        Some(c) if c.is_ascii_digit() || c == '-' => format!("h-{}", slug),
+        Some(_) => slug.to_string(),
+    };
+    let mut candidate = base.clone();
+    let mut counter = 1;
+    while used.contains(&candidate) {
+        candidate = format!("{}-{}", base, counter);
+        counter += 1;
+    }
+    used.insert(candidate.clone());
+    candidate
+}
+
+/// 检查标记是否为格式正确的 XML 片段
+///
+/// `html_entities` 为 `true` 时允许 HTML 命名实体（文档声明了 XHTML 1.x DTD）。
+pub(crate) fn check(input: &str, html_entities: bool) -> Vec<Diagnostic> {
+    let index = LineIndex::new(input);
+    let mut diagnostics = Vec::new();
+    let mut report = |offset: usize, kind: DiagnosticKind, message: String| {
+        diagnostics.push(Diagnostic::new(
+            Severity::Error,
+            kind,
+            index.location(input, offset),
+            message,
+        ));
+    };
+    let mut open: Vec<(usize, &str)> = Vec::new();
+
+    for (offset, token) in Tokenizer::new(input) {
+        match token {
+            Token::Text(_) => {}
+            Token::Reference(reference) => {
+                if let Some((kind, message)) = check_reference(reference, html_entities) {
+                    report(offset, kind, message);
+                }
+            }
+            Token::BareAmpersand => report(
+                offset,
+                DiagnosticKind::MalformedMarkup,
+                String::from("Unescaped '&', use &amp;"),
+            ),
+            Token::BareLessThan => report(
+                offset,
+                DiagnosticKind::MalformedMarkup,
+                String::from("Unescaped '<', use &lt;"),
+            ),
+            Token::Invalid(c) => report(
+                offset,
+                DiagnosticKind::InvalidCharacter,
+                format!("Invalid character U+{:04X}", c as u32),
+            ),
+            Token::Other {
+                raw, terminated, ..
+            } => {
+                if !terminated {
+                    let what = raw.chars().take(9).collect::<String>();
+                    report(
+                        offset,
+                        DiagnosticKind::MalformedMarkup,
+                        format!("Unterminated {}", what),
+                    );
+                }
+            }
+            Token::StartTag {
+                name,
+                attributes,
+                self_closing,
+                terminated,
+            } => {
+                if !terminated {
+                    report(
+                        offset,
+                        DiagnosticKind::MalformedMarkup,
+                        format!("Unterminated tag <{}>", name),
+                    );
+                }
+                let mut names = Vec::new();
+                for attribute in &attributes {
+                    if !is_attribute_name(attribute.name) {
+                        report(
+                            attribute.offset,
+                            DiagnosticKind::MalformedMarkup,
+                            format!("Invalid attribute name {}", attribute.name),
+                        );
+                    }
+                    if !input[..attribute.offset].ends_with(char::is_whitespace) {
+                        report(
+                            attribute.offset,
+                            DiagnosticKind::MalformedMarkup,
+                            format!("Missing whitespace before attribute {}", attribute.name),
+                        );
+                    }
+                    if names.contains(&attribute.name) {
+                        report(
+                            attribute.offset,
+                            DiagnosticKind::MalformedMarkup,
+                            format!("Duplicate attribute {}", attribute.name),
+                        );
+                    }
+                    names.push(attribute.name);
+                    match attribute.value {
+                        None => report(
+                            attribute.offset,
+                            DiagnosticKind::MalformedMarkup,
+                            format!("Attribute {} has no value", attribute.name),
+                        ),
+                        Some(_) if !attribute.quoted => report(
+                            attribute.offset,
+                            DiagnosticKind::MalformedMarkup,
+                            format!("Value of attribute {} is not quoted", attribute.name),
+                        ),
+                        Some(value) => {
+                            for (index, kind, message) in check_text(value, html_entities) {
+                                report(attribute.value_offset + index, kind, message);
+                            }
+                        }
+                    }
+                }
+                if !self_closing {
+                    open.push((offset, name));
+                }
+            }
+            Token::EndTag { name, terminated } => {
+                if !terminated {
+                    report(
+                        offset,
+                        DiagnosticKind::MalformedMarkup,
+                        format!("Unterminated end tag </{}>", name),
+                    );
+                }
+                match open.iter().rposition(|(_, open_name)| *open_name == name) {
+                    Some(position) => {
+                        for (open_offset, open_name) in
+                            open.drain(position..).skip(1).collect::<Vec<_>>()
+                        {
+                            report(
+                                open_offset,
+                                DiagnosticKind::MalformedMarkup,
+                                format!("Unclosed tag <{}>, closed by </{}>", open_name, name),
+                            );
+                        }
+                        open.truncate(position);
+                    }
+                    None => report(
+                        offset,
+                        DiagnosticKind::MalformedMarkup,
+                        format!("End tag </{}> has no matching start tag", name),
+                    ),
+                }
+            }
+        }
+    }
+    for (offset, name) in open {
+        report(
+            offset,
+            DiagnosticKind::MalformedMarkup,
+            format!("Unclosed tag <{}>", name),
+        );
+    }
+    diagnostics.sort_by_key(|d| (d.location.line, d.location.column));
+    diagnostics
+}
+
+/// 检查实体引用，返回问题类型和说明
+fn check_reference(reference: &str, html_entities: bool) -> Option<(DiagnosticKind, String)> {
+    let name = &reference[1..reference.len() - 1];
+    if name.starts_with('#') {
+        return match numeric_reference(reference) {
+            Some(c) if is_xml_char(c) => None,
+            _ => Some((
+                DiagnosticKind::InvalidCharacter,
+                format!("Character reference {} is not an XML character", reference),
+            )),
+        };
+    }
+    if XML_ENTITIES.contains(&name) || (html_entities && html_entity(name).is_some()) {
+        return None;
+    }
+    Some((
+        DiagnosticKind::UndeclaredEntity,
+        format!("Undeclared entity {}", reference),
+    ))
+}
+
+/// 检查属性值，返回 (字节偏移, 问题类型, 说明)
+fn check_text(text: &str, html_entities: bool) -> Vec<(usize, DiagnosticKind, String)> {
+    let mut problems = Vec::new();
+    for (index, c) in text.char_indices() {
+        match c {
+            '&' => match Tokenizer::new(&text[index..]).next() {
+                Some((_, Token::Reference(reference))) => {
+                    if let Some((kind, message)) = check_reference(reference, html_entities) {
+                        problems.push((index, kind, message));
+                    }
+                }
+                _ => problems.push((
+                    index,
+                    DiagnosticKind::MalformedMarkup,
+                    String::from("Unescaped '&', use &amp;"),
+                )),
+            },
+            '<' => problems.push((
+                index,
+                DiagnosticKind::MalformedMarkup,
+                String::from("Unescaped '<' in attribute value, use &lt;"),
+            )),
+            c if !is_xml_char(c) => problems.push((
+                index,
+                DiagnosticKind::InvalidCharacter,
+                format!("Invalid character U+{:04X}", c as u32),
+            )),
+            _ => {}
+        }
+    }
+    problems
+}
+
+/// 修复常见问题：闭合标签（包括 HTML 中隐式结束的 `<p>`、`<li>` 等）、转换实体、转义 `&` 和 `<`、补全属性引号、删除非法字符
+pub(crate) fn repair(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut open: Vec<&str> = Vec::new();
+
+    let mut tokenizer = Tokenizer::new(input);
+    while let Some((_, token)) = tokenizer.next() {
+        match token {
+            Token::Text(text) => output.push_str(text),
+            Token::Reference(reference) => output.push_str(&repair_reference(reference)),
+            Token::BareAmpersand => output.push_str("&amp;"),
+            Token::BareLessThan => output.push_str("&lt;"),
+            Token::Invalid(_) => {}
+            Token::Other {
+                raw,
+                terminator,
+                terminated,
+            } => {
+                output.push_str(raw);
+                if !terminated {
+                    output.push_str(terminator);
+                }
+            }
+            Token::StartTag {
+                name,
+                attributes,
+                self_closing,
+                ..
+            } => {
+                let lowercase = name.to_ascii_lowercase();
+                while open
+                    .last()
+                    .is_some_and(|last| implies_end(&last.to_ascii_lowercase(), &lowercase))
+                {
+                    let last = open.pop().unwrap_or_default();
+                    output.push_str("</");
+                    output.push_str(last);
+                    output.push('>');
+                }
+                output.push('<');
+                output.push_str(name);
+                let mut names = Vec::new();
+                for attribute in attributes {
+                    if names.contains(&attribute.name) || !is_attribute_name(attribute.name) {
+                        continue;
+                    }
+                    names.push(attribute.name);
+                    let value = attribute.value.unwrap_or(attribute.name);
+                    output.push(' ');
+                    output.push_str(attribute.name);
+                    output.push_str("=\"");
+                    output.push_str(&repair_text(value).replace('"', "&quot;"));
+                    output.push('"');
+                }
+                if self_closing || is_void(name) {
+                    output.push_str("/>");
+                } else {
+                    output.push('>');
+                    open.push(name);
+                    // 脚本和样式的内容不是标记
+                    if is_raw_text(&lowercase, false) {
+                        output.push_str(&raw_text_markup(tokenizer.skip_raw_text(name)));
+                    }
+                }
+            }
+            Token::EndTag { name, .. } => {
+                let position = open
+                    .iter()
+                    .rposition(|open_name| open_name.eq_ignore_ascii_case(name));
+                // 没有对应开始标签的结束标签直接丢弃
+                if let Some(position) = position {
+                    for open_name in open.drain(position..).rev() {
+                        output.push_str("</");
+                        output.push_str(open_name);
+                        output.push('>');
+                    }
+                }
+            }
+        }
+    }
+    for open_name in open.into_iter().rev() {
+        output.push_str("</");
+        output.push_str(open_name);
+        output.push('>');
+    }
+    output
+}
+
+/// HTML 中隐式结束 `<p>` 的块级元素
+const CLOSES_PARAGRAPH: [&str; 27] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "details",
+    "div",
+    "dl",
+    "figcaption",
+    "figure",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "summary",
+    "table",
+    "ul",
+    "li",
+];
+
+/// 打开 `name` 时是否隐式结束仍打开的 `open`
+fn implies_end(open: &str, name: &str) -> bool {
+    match open {
+        "p" => CLOSES_PARAGRAPH.contains(&name),
+        "li" => name == "li",
+        "dt" | "dd" => matches!(name, "dt" | "dd"),
+        "td" | "th" => matches!(name, "td" | "th" | "tr" | "tbody" | "tfoot"),
+        "tr" => matches!(name, "tr" | "tbody" | "tfoot"),
+        "thead" | "tbody" => matches!(name, "tbody" | "tfoot"),
+        "rt" | "rp" => matches!(name, "rt" | "rp"),
+        _ => false,
+    }
+}
+
+/// 内容为原始文本的元素，其中的 `<` 不是标签
+const RAW_TEXT_ELEMENTS: [&str; 2] = ["script", "style"];
+
+/// 是否按原始文本跳过 `name` 的内容，HTML 中 `iframe` 和 `noscript` 的内容也不是标记
+fn is_raw_text(name: &str, html: bool) -> bool {
+    RAW_TEXT_ELEMENTS.contains(&name) || (html && matches!(name, "iframe" | "noscript"))
+}
+
+/// 原始文本中有 `<` 或 `&` 时放入 CDATA，已有 CDATA 时保持原样
+fn raw_text_markup(text: &str) -> String {
+    if text.contains("<![CDATA[") || !text.contains(['<', '&']) {
+        return text.to_string();
+    }
+    format!("<![CDATA[{}]]>", text.replace("]]>", "]]]]><![CDATA[>"))
+}
+
+/// 属性名只保留合法的 XML 名称
+fn is_attribute_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+}
+
+/// 按允许列表把 HTML 转换为 XHTML 正文
+pub(crate) fn sanitize(input: &str, sanitizer: &Sanitizer) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut open: Vec<String> = Vec::new();
+    // 正在删除的元素及其嵌套层数
+    let mut removing: Option<(String, usize)> = None;
+
+    let mut tokenizer = Tokenizer::new(input);
+    while let Some((_, token)) = tokenizer.next() {
+        if let Some((removed, depth)) = removing.as_mut() {
+            match token {
+                Token::StartTag {
+                    name, self_closing, ..
+                } if name.eq_ignore_ascii_case(removed) && !self_closing => *depth += 1,
+                Token::EndTag { name, .. } if name.eq_ignore_ascii_case(removed) => {
+                    if *depth == 0 {
+                        removing = None;
+                    } else {
+                        *depth -= 1;
+                    }
+                }
+                _ => {}
+            }
+            continue;
+        }
+        match token {
+            Token::Text(text) => output.push_str(text),
+            Token::Reference(reference) => output.push_str(&repair_reference(reference)),
+            Token::BareAmpersand => output.push_str("&amp;"),
+            Token::BareLessThan => output.push_str("&lt;"),
+            Token::Invalid(_) => {}
+            Token::Other { raw, .. } => {
+                // 只保留 CDATA 中的文本
+                if let Some(text) = raw.strip_prefix("<![CDATA[") {
+                    let text = text.strip_suffix("]]>").unwrap_or(text);
+                    let text: String = text.chars().filter(|c| is_xml_char(*c)).collect();
+                    output.push_str(&text.replace('&', "&amp;").replace('<', "&lt;"));
+                }
+            }
+            Token::StartTag {
+                name,
+                attributes,
+                self_closing,
+                ..
+            } => {
+                let name = name.to_ascii_lowercase();
+                if sanitizer.removes_element(&name) {
+                    if !self_closing && !is_void(&name) {
+                        // 原始文本中的标签不计入嵌套
+                        if is_raw_text(&name, true) {
+                            tokenizer.skip_raw_text(&name);
+                        }
+                        removing = Some((name, 0));
+                    }
+                    continue;
+                }
+                if !sanitizer.allows_element(&name) {
+                    continue;
+                }
+                while open.last().is_some_and(|last| implies_end(last, &name)) {
+                    let last = open.pop().unwrap_or_default();
+                    output.push_str(&format!("</{}>", last));
+                }
+                output.push('<');
+                output.push_str(&name);
+                let mut names = Vec::new();
+                for attribute in attributes {
+                    let attribute_name = attribute.name.to_ascii_lowercase();
+                    if names.contains(&attribute_name)
+                        || !is_attribute_name(&attribute_name)
+                        || !sanitizer.allows_attribute(&name, &attribute_name)
+                    {
+                        continue;
+                    }
+                    let value = repair_text(attribute.value.unwrap_or(&attribute_name));
+                    if REFERENCE_ATTRIBUTES.contains(&attribute_name.as_str())
+                        || attribute_name == "cite"
+                    {
+                        let scheme = value.trim_start().to_ascii_lowercase();
+                        if scheme.starts_with("javascript:") || scheme.starts_with("vbscript:") {
+                            continue;
+                        }
+                    }
+                    output.push_str(&format!(
+                        " {}=\"{}\"",
+                        attribute_name,
+                        value.replace('"', "&quot;")
+                    ));
+                    names.push(attribute_name);
+                }
+                if self_closing || is_void(&name) {
+                    output.push_str("/>");
+                } else {
+                    output.push('>');
+                    open.push(name);
+                }
+            }
+            Token::EndTag { name, .. } => {
+                let name = name.to_ascii_lowercase();
+                // 没有对应开始标签的结束标签直接丢弃
+                if let Some(position) = open.iter().rposition(|open_name| *open_name == name) {
+                    for open_name in open.drain(position..).rev() {
+                        output.push_str(&format!("</{}>", open_name));
+                    }
+                }
+            }
+        }
+    }
+    for open_name in open.into_iter().rev() {
+        output.push_str(&format!("</{}>", open_name));
+    }
+    output
+}
+
+/// 修复属性值中的实体和字符
+fn repair_text(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(index) = rest.find('&') {
+        output.push_str(&rest[..index]);
+        rest = &rest[index..];
+        match Tokenizer::new(rest).next() {
+            Some((_, Token::Reference(reference))) => {
+                output.push_str(&repair_reference(reference));
+                rest = &rest[reference.len()..];
+            }
+            _ => {
+                output.push_str("&amp;");
+                rest = &rest[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output.retain(is_xml_char);
+    output.replace('<', "&lt;")
+}
+
+/// 将 HTML 命名实体转换为数字引用，未知实体转义为文本
+fn repair_reference(reference: &str) -> String {
+    let name = &reference[1..reference.len() - 1];
+    if name.starts_with('#') {
+        return match numeric_reference(reference) {
+            Some(c) if is_xml_char(c) => reference.to_string(),
+            _ => String::new(),
+        };
+    }
+    if XML_ENTITIES.contains(&name) {
+        return reference.to_string();
+    }
+    match html_entity(name) {
+        Some(c) => format!("&#{};", c as u32),
+        None => format!("&amp;{}", &reference[1..]),
+    }
+}
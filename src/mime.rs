@@ -15,7 +15,7 @@ pub fn from_path<P: AsRef<Path>>(path: P) -> Option<String> {
             MIME_TYPES
                 .iter()
                 .find(|(k, _)| *k == ext)
-                .and_then(|(_, v)| v.get(0).map(|s| s.to_string()))
+                .and_then(|(_, v)| v.first().map(|s| s.to_string()))
         })
 }
 
@@ -24,7 +24,7 @@ pub fn first_or_octet_stream(ext: String) -> String {
     MIME_TYPES
         .iter()
         .find(|(k, _)| k == &ext)
-        .map(|(_, v)| String::from(*v.get(0).unwrap_or(&MIME_DEFAULT)))
+        .map(|(_, v)| String::from(*v.first().unwrap_or(&MIME_DEFAULT)))
         .unwrap_or(ext)
         .to_string()
 }
@@ -38,10 +38,15 @@ pub fn first<S: Into<String>>(filename: S) -> Option<String> {
             MIME_TYPES
                 .iter()
                 .find(|(k, _)| *k == ext)
-                .map(|(_, v)| String::from(*v.get(0).unwrap()))
+                .map(|(_, v)| String::from(*v.first().unwrap()))
         })
 }
 
+/// 根据文件名获取mime类型，如果获取失败则返回默认类型
+pub fn first_or_default<S: Into<String>>(filename: S) -> String {
+    first(filename).unwrap_or_else(|| String::from(MIME_DEFAULT))
+}
+
 /// 获取mime类型，如果获取成功则返回mime类型列表
 pub fn get_mime_type<'a>(ext: &str) -> Option<Vec<&'a str>> {
     MIME_TYPES
@@ -1,8 +1,9 @@
-use crate::epub::EpubVersion;
+use crate::epub::{EpubVersion, MEDIA_TYPE_NCX};
+use crate::Error;
 use serde::{Deserialize, Serialize};
 
 /// A struct representing an EPUB Package Document.
-
+#[derive(Default)]
 #[allow(dead_code)]
 pub struct Package {
     metadata: Metadata,
@@ -10,6 +11,8 @@ pub struct Package {
     spine: Vec<SpineItemRef>,
     guide: Vec<GuideReference>,
     bindings: Vec<BindingItem>,
+    version: Option<EpubVersion>,
+    unique_identifier: String,
 }
 
 /// A struct representing an EPUB Package Document.
@@ -21,6 +24,8 @@ impl Package {
             spine: Vec::new(),
             guide: Vec::new(),
             bindings: Vec::new(),
+            version: None,
+            unique_identifier: String::new(),
         }
     }
     /// Add a metadata item to the package
@@ -54,6 +59,88 @@ impl Package {
         self
     }
 
+    /// The package metadata
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+    /// The manifest items in document order
+    pub fn manifest(&self) -> &[ManifestItem] {
+        &self.manifest
+    }
+    /// The spine item references in reading order
+    pub fn spine(&self) -> &[SpineItemRef] {
+        &self.spine
+    }
+    /// The guide references
+    pub fn guide(&self) -> &[GuideReference] {
+        &self.guide
+    }
+    /// The binding items
+    pub fn bindings(&self) -> &[BindingItem] {
+        &self.bindings
+    }
+    /// The version declared by a decoded package document
+    pub fn version(&self) -> Option<EpubVersion> {
+        self.version
+    }
+    /// The `unique-identifier` attribute of a decoded package document
+    pub fn unique_identifier(&self) -> &str {
+        &self.unique_identifier
+    }
+    /// Find a manifest item by id
+    pub fn manifest_item(&self, id: &str) -> Option<&ManifestItem> {
+        self.manifest.iter().find(|m| m.id == id)
+    }
+
+    /// Decode a package document
+    pub fn decode_xml(xml: &str) -> Result<Package, super::Error> {
+        let opf: PackageOpf = quick_xml::de::from_str(xml)
+            .map_err(|e| super::Error::DeserializeErr(format!("Invalid package document:{}", e)))?;
+
+        let mut package = Package::new();
+        package.version = Some(if opf.version.starts_with('3') {
+            EpubVersion::V30
+        } else {
+            EpubVersion::V20
+        });
+        package.metadata = Metadata::from_opf(opf.metadata, &opf.unique_identifier);
+        package.unique_identifier = opf.unique_identifier;
+        package.manifest = opf
+            .manifest
+            .items
+            .into_iter()
+            .map(|m| ManifestItem {
+                id: m.id,
+                href: m.href,
+                media_type: m.media_type,
+                properties: m.properties,
+            })
+            .collect();
+        package.spine = opf
+            .spine
+            .items
+            .into_iter()
+            .map(|s| SpineItemRef {
+                idref: s.idref,
+                properties: s.properties,
+            })
+            .collect();
+        package.guide = opf
+            .guide
+            .items
+            .into_iter()
+            .map(|g| GuideReference::new(g.ref_type, g.title, g.href))
+            .collect();
+        package.bindings = opf
+            .binding
+            .map(|b| b.items)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|b| BindingItem::new(b.media_type, b.href))
+            .collect();
+        Ok(package)
+    }
+
     pub fn encode_xml(&self, ver: EpubVersion) -> Result<String, super::Error> {
         let mut xml: PackageOpf;
         match ver {
@@ -78,86 +165,199 @@ impl Package {
             .metadata
             .creator
             .first()
-            .unwrap_or(&"".to_string())
-            .clone();
-        let xml = PackageOpf::new(EpubVersion::V20, self.metadata.title.clone(), creator);
-        xml
+            .map(|c| c.name.clone())
+            .unwrap_or_default();
+        PackageOpf::new(EpubVersion::V20, self.metadata.title.clone(), creator)
     }
     fn encode_v3_xml(&self) -> PackageOpf {
         let creator = self
             .metadata
             .creator
             .first()
-            .unwrap_or(&"".to_string())
-            .clone();
+            .map(|c| c.name.clone())
+            .unwrap_or_default();
         let mut xml = PackageOpf::new(EpubVersion::V30, self.metadata.title.clone(), creator);
         self.convert_binding(&mut xml);
         xml
     }
 
     fn convert_metadata<'a>(&self, xml: &'a mut PackageOpf) -> &'a mut PackageOpf {
-        xml.metadata.title = self.metadata.title.clone();
-        xml.metadata.creator = self.metadata.creator.join(",");
-        xml.metadata.subject = Some(self.metadata.subject.join(","));
-        xml.metadata.description = self.metadata.description.clone();
-        xml.metadata.date = self
-            .metadata
-            .date_published
-            .map(|d| d.format("%Y-%m-%dT%H:%M:%S %z").to_string());
-        if let Some(ref date) = self.metadata.date_modified {
-            xml.metadata.meta.push(MetaItemOpf {
-                name: None,
-                text: date.format("%Y-%m-%dT%H:%M:%S %z").to_string(),
-                property: Some(String::from("dcterms:modified")),
-            });
+        let v3 = xml.version.starts_with('3');
+        xml.metadata.title.clear();
+        for (index, title) in self.titles().iter().enumerate() {
+            let id = format!("title-{}", index + 1);
+            let element = encode_title(title, &id, v3, &mut xml.metadata.meta);
+            xml.metadata.title.push(element);
+        }
+        xml.metadata.creator.clear();
+        for (index, creator) in self.metadata.creator.iter().enumerate() {
+            let id = format!("creator-{}", index + 1);
+            let element = encode_creator(creator, &id, v3, &mut xml.metadata.meta);
+            xml.metadata.creator.push(element);
+        }
+        // 每个主题写为一个 dc:subject
+        xml.metadata.subject = self.metadata.subject.clone();
+        if let Some(ref identifier) = self.metadata.identifier {
+            if !identifier.text.is_empty() {
+                xml.unique_identifier = identifier.id.clone();
+                xml.metadata
+                    .identifier
+                    .push(IdentifierOpf::new(identifier, v3));
+            }
+        }
+        for identifier in self.metadata.other_identifiers.iter() {
+            if !identifier.text.is_empty() {
+                xml.metadata
+                    .identifier
+                    .push(IdentifierOpf::new(identifier, v3));
+            }
+        }
+        xml.metadata.description = self.metadata.description.iter().cloned().collect();
+        self.convert_dates(xml, v3);
+        xml.metadata.category = self.metadata.category.iter().cloned().collect();
+        xml.metadata.publisher = self.metadata.publisher.iter().cloned().collect();
+        for (index, contributor) in self.metadata.contributor.iter().enumerate() {
+            let id = format!("contributor-{}", index + 1);
+            let element = encode_creator(contributor, &id, v3, &mut xml.metadata.meta);
+            xml.metadata.contributor.push(element);
+        }
+        xml.metadata.rights = self.metadata.rights.iter().cloned().collect();
+        xml.metadata.format = self.metadata.format.iter().cloned().collect();
+        xml.metadata.source = self.metadata.source.iter().cloned().collect();
+        xml.metadata.language = vec![self.metadata.language.clone()];
+        xml.metadata.relation = self.metadata.relation.iter().cloned().collect();
+        xml.metadata.coverage = self.metadata.coverage.iter().cloned().collect();
+
+        if v3 {
+            for (index, collection) in self.metadata.collection.iter().enumerate() {
+                let id = format!("collection-{}", index + 1);
+                encode_collection(collection, &id, &mut xml.metadata.meta);
+            }
+        }
+        if let Some(series) = self.metadata.collection.iter().find(|c| c.is_series()) {
+            xml.metadata
+                .meta
+                .push(MetaItemOpf::new_name("calibre:series", series.name.clone()));
+            if !series.position.is_empty() {
+                xml.metadata.meta.push(MetaItemOpf::new_name(
+                    "calibre:series_index",
+                    series.position.clone(),
+                ));
+            }
+        }
+
+        if let Some(ref cover) = self.metadata.cover {
+            xml.metadata
+                .meta
+                .push(MetaItemOpf::new_name("cover", cover.clone()));
         }
-        xml.metadata.category = self.metadata.category.clone();
-        xml.metadata.publisher = self.metadata.publisher.clone();
-        xml.metadata.contributor = self.metadata.contributor.clone();
-        xml.metadata.rights = self.metadata.rights.clone();
-        xml.metadata.format = self.metadata.format.clone();
-        xml.metadata.source = self.metadata.source.clone();
-        xml.metadata.language = Some(self.metadata.language.clone());
-        xml.metadata.relation = self.metadata.relation.clone();
-        xml.metadata.coverage = self.metadata.coverage.clone();
 
         self.metadata.meta.iter().for_each(|m| {
             xml.metadata.meta.push(MetaItemOpf {
-                name: Some(m.name.clone()),
                 text: m.data.clone(),
-                property: Some(m.property.clone()),
+                name: non_empty(&m.name),
+                content: non_empty(&m.content),
+                property: non_empty(&m.property),
+                id: non_empty(&m.id),
+                refines: non_empty(&m.refines),
+                scheme: non_empty(&m.scheme),
+                lang: None,
             });
         });
-        xml.metadata.meta.push(MetaItemOpf {
-            name: Some(String::from("generator")),
-            text: self.metadata.generator.clone(),
-            property: None,
-        });
-        xml.metadata.meta.push(MetaItemOpf {
-            name: Some(String::from("generator-name")),
-            text: self.metadata.generator_name.clone(),
-            property: None,
-        });
+        xml.metadata.meta.push(MetaItemOpf::new_name(
+            "generator",
+            self.metadata.generator.clone(),
+        ));
+        xml.metadata.meta.push(MetaItemOpf::new_name(
+            "generator-name",
+            self.metadata.generator_name.clone(),
+        ));
 
         xml
     }
 
+    /// EPUB 3 只有一个 `dc:date` 表示出版日期，创建和修改日期写为 `dcterms` 元数据；
+    /// EPUB 2 的日期都写为带 `opf:event` 的 `dc:date`
+    fn convert_dates(&self, xml: &mut PackageOpf, v3: bool) {
+        let metadata = &self.metadata;
+        let modified = metadata.date_modified.map(Date::from);
+        let dates = [
+            ("publication", "", metadata.date_published),
+            ("creation", "dcterms:created", metadata.date_created),
+            ("modification", "dcterms:modified", modified),
+        ];
+        for (event, property, date) in dates {
+            let Some(date) = date else {
+                continue;
+            };
+            if !v3 {
+                let mut element = DcElementOpf::new(date.to_string());
+                element.event = String::from(event);
+                xml.metadata.date.push(element);
+            } else if property.is_empty() {
+                xml.metadata.date.push(DcElementOpf::new(date.to_string()));
+            } else {
+                xml.metadata.meta.push(MetaItemOpf {
+                    text: date.to_string(),
+                    property: Some(String::from(property)),
+                    ..MetaItemOpf::default()
+                });
+            }
+        }
+    }
+
+    /// 要写入的标题，`metadata.title` 在最前
+    ///
+    /// 主标题使用类型为 `main` 或文字相同的标题的属性，其余标题按顺序排在后面。
+    fn titles(&self) -> Vec<Title> {
+        let titles = &self.metadata.titles;
+        let main = titles
+            .iter()
+            .position(|title| title.title_type == "main")
+            .or_else(|| {
+                titles
+                    .iter()
+                    .position(|title| title.text == self.metadata.title)
+            });
+        let mut result = vec![Title {
+            text: self.metadata.title.clone(),
+            ..main.map(|index| titles[index].clone()).unwrap_or_default()
+        }];
+        result.extend(
+            titles
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| Some(*index) != main)
+                .map(|(_, title)| title.clone()),
+        );
+        result
+    }
+
     fn convert_manifest<'a>(&self, xml: &'a mut PackageOpf) -> &'a mut PackageOpf {
         self.manifest.iter().for_each(|m| {
             xml.manifest.items.push(ManifestItemOpf {
                 id: m.id.clone(),
                 href: m.href.clone(),
                 media_type: m.media_type.clone(),
+                properties: m.properties.clone(),
             })
         });
         xml
     }
 
     fn convert_spine<'a>(&self, xml: &'a mut PackageOpf) -> &'a mut PackageOpf {
+        // spine 的 toc 属性指向 NCX 文件在 manifest 中的 id
+        if let Some(ncx) = self
+            .manifest
+            .iter()
+            .find(|m| m.media_type == MEDIA_TYPE_NCX)
+        {
+            xml.spine.toc = ncx.id.clone();
+        }
         self.spine.iter().for_each(|s| {
             xml.spine.items.push(SpineItemRefOpf {
                 idref: s.idref.clone(),
-                properties: None,
+                properties: s.properties.clone(),
             })
         });
         xml
@@ -194,21 +394,31 @@ impl Package {
 #[allow(dead_code)]
 pub struct Metadata {
     pub title: String,
-    pub creator: Vec<String>,
+    /// 所有标题，包括主标题、副标题等及其其他语言或文字的写法
+    pub titles: Vec<Title>,
+    pub creator: Vec<Creator>,
     pub subject: Vec<String>,
     pub description: Option<String>,
     pub category: Option<String>,
     pub publisher: Option<String>,
-    pub contributor: Option<String>,
+    pub contributor: Vec<Creator>,
+    pub collection: Vec<Collection>,
     pub format: Option<String>,
+    /// 唯一标识符，`unique-identifier` 指向它
     pub identifier: Option<Identifier>,
+    /// 其他标识符，如唯一标识符为 UUID 时的 ISBN
+    pub other_identifiers: Vec<Identifier>,
     pub source: Option<String>,
     pub language: String,
     pub relation: Option<String>,
     pub coverage: Option<String>,
     pub rights: Option<String>,
     pub cover: Option<String>,
-    pub date_published: Option<chrono::DateTime<chrono::Utc>>,
+    /// 出版日期，可以只有年份或年月
+    pub date_published: Option<Date>,
+    /// 创建日期
+    pub date_created: Option<Date>,
+    /// 最后修改时间，EPUB 3 写为 `dcterms:modified`
     pub date_modified: Option<chrono::DateTime<chrono::Utc>>,
 
     generator: String,
@@ -222,14 +432,17 @@ impl Default for Metadata {
     fn default() -> Self {
         Metadata {
             title: String::new(),
+            titles: Vec::new(),
             creator: vec![],
             subject: vec![],
             description: None,
             category: None,
             publisher: None,
-            contributor: None,
+            contributor: Vec::new(),
+            collection: Vec::new(),
             format: None,
             identifier: None,
+            other_identifiers: Vec::new(),
             source: None,
             language: String::from("zh"),
             relation: None,
@@ -237,6 +450,7 @@ impl Default for Metadata {
             rights: None,
             cover: None,
             date_published: None,
+            date_created: None,
             date_modified: None,
             generator: String::from("Rust EPUB library"),
             generator_name: String::from("Table Of Contents"),
@@ -246,6 +460,200 @@ impl Default for Metadata {
 }
 
 impl Metadata {
+    /// 从解析出的 OPF 元数据转换
+    fn from_opf(opf: MetadataOpf, unique_identifier: &str) -> Metadata {
+        let mut metadata = Metadata {
+            language: String::new(),
+            ..Metadata::default()
+        };
+        let title_ids: Vec<String> = opf.title.iter().map(|t| t.id.clone()).collect();
+        metadata.titles = opf
+            .title
+            .into_iter()
+            .map(|t| Title {
+                lang: t.lang,
+                ..Title::new(t.text.trim(), "")
+            })
+            .collect();
+        // dc:creator/dc:contributor 的 id，用于关联 EPUB 3 的 refines 元数据
+        let creator_ids: Vec<String> = opf.creator.iter().map(|c| c.id.clone()).collect();
+        let contributor_ids: Vec<String> = opf.contributor.iter().map(|c| c.id.clone()).collect();
+        metadata.creator = opf.creator.into_iter().map(Creator::from_opf).collect();
+        metadata.contributor = opf.contributor.into_iter().map(Creator::from_opf).collect();
+        metadata.subject = opf.subject;
+        metadata.description = opf.description.into_iter().next();
+        metadata.category = opf.category.into_iter().next();
+        metadata.publisher = opf.publisher.into_iter().next();
+        metadata.format = opf.format.into_iter().next();
+        metadata.source = opf.source.into_iter().next();
+        metadata.relation = opf.relation.into_iter().next();
+        metadata.coverage = opf.coverage.into_iter().next();
+        metadata.rights = opf.rights.into_iter().next();
+        if let Some(language) = opf.language.into_iter().next() {
+            metadata.language = language;
+        }
+        // EPUB 2 的 opf:event 区分日期的用途，没有 event 的日期为出版日期
+        for date in opf.date.iter() {
+            match date.event.to_ascii_lowercase().as_str() {
+                "creation" => {
+                    metadata.date_created = metadata.date_created.or(Date::parse(&date.text))
+                }
+                "modification" => {
+                    metadata.date_modified = metadata.date_modified.or(parse_date(&date.text))
+                }
+                _ => metadata.date_published = metadata.date_published.or(Date::parse(&date.text)),
+            }
+        }
+
+        // 优先使用 unique-identifier 指向的标识符
+        let position = opf
+            .identifier
+            .iter()
+            .position(|i| !unique_identifier.is_empty() && i.id == unique_identifier)
+            .unwrap_or(0);
+        for (index, i) in opf.identifier.into_iter().enumerate() {
+            let identifier = Identifier {
+                id: i.id,
+                scheme: i.scheme,
+                text: i.text.trim().to_string(),
+            };
+            if index == position {
+                metadata.identifier = Some(identifier);
+            } else {
+                metadata.other_identifiers.push(identifier);
+            }
+        }
+
+        // EPUB 3 的 belongs-to-collection，类型和位置通过 refines 关联
+        let collection_ids: Vec<String> = opf
+            .meta
+            .iter()
+            .filter(|meta| meta.property.as_deref() == Some("belongs-to-collection"))
+            .map(|meta| {
+                metadata
+                    .collection
+                    .push(Collection::new(meta.text.trim(), "", ""));
+                meta.id.clone().unwrap_or_default()
+            })
+            .collect();
+        let mut calibre_series = None;
+        let mut calibre_series_index = String::new();
+
+        for meta in opf.meta {
+            let refines = meta
+                .refines
+                .as_deref()
+                .and_then(|refines| refines.strip_prefix('#'))
+                .filter(|id| !id.is_empty());
+            let collection = refines
+                .and_then(|id| collection_ids.iter().position(|c| c == id))
+                .and_then(|index| metadata.collection.get_mut(index));
+            if let Some(collection) = collection {
+                match meta.property.as_deref() {
+                    Some("collection-type") => {
+                        collection.collection_type = meta.text.trim().to_string();
+                        continue;
+                    }
+                    Some("group-position") => {
+                        collection.position = meta.text.trim().to_string();
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+            let title = refines
+                .and_then(|id| title_ids.iter().position(|t| t == id))
+                .and_then(|index| metadata.titles.get_mut(index));
+            if let Some(title) = title {
+                match meta.property.as_deref() {
+                    Some("title-type") => {
+                        title.title_type = meta.text.trim().to_string();
+                        continue;
+                    }
+                    Some("display-seq") => {
+                        title.display_seq = meta.text.trim().parse().ok();
+                        continue;
+                    }
+                    Some("alternate-script") => {
+                        let lang = meta.lang.unwrap_or_default();
+                        title
+                            .alternate_script
+                            .push(AlternateScript::new(lang, meta.text.trim()));
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+            // EPUB 3 中通过 refines 关联到作者的角色和排序名称
+            let refined = meta
+                .refines
+                .as_deref()
+                .and_then(|refines| refines.strip_prefix('#'))
+                .filter(|id| !id.is_empty())
+                .and_then(|id| match creator_ids.iter().position(|c| c == id) {
+                    Some(index) => metadata.creator.get_mut(index),
+                    None => contributor_ids
+                        .iter()
+                        .position(|c| c == id)
+                        .and_then(|index| metadata.contributor.get_mut(index)),
+                });
+            if let Some(creator) = refined {
+                match meta.property.as_deref() {
+                    Some("role") => {
+                        creator.role = meta.text.trim().to_string();
+                        continue;
+                    }
+                    Some("file-as") => {
+                        creator.file_as = meta.text.trim().to_string();
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+            match (meta.name.as_deref(), meta.property.as_deref()) {
+                (Some("cover"), _) => metadata.cover = meta.content,
+                (Some("generator"), _) | (Some("generator-name"), _) => {}
+                (_, Some("dcterms:modified")) => metadata.date_modified = parse_date(&meta.text),
+                (_, Some("dcterms:created")) => metadata.date_created = Date::parse(&meta.text),
+                (_, Some("belongs-to-collection")) => {}
+                (Some("calibre:series"), _) => calibre_series = meta.content,
+                (Some("calibre:series_index"), _) => {
+                    calibre_series_index = meta.content.unwrap_or_default()
+                }
+                _ => {
+                    metadata.meta.push(MetaItem {
+                        refines: meta.refines.unwrap_or_default(),
+                        property: meta.property.unwrap_or_default(),
+                        scheme: meta.scheme.unwrap_or_default(),
+                        id: meta.id.unwrap_or_default(),
+                        data: meta.text,
+                        name: meta.name.unwrap_or_default(),
+                        content: meta.content.unwrap_or_default(),
+                    });
+                }
+            }
+        }
+        // 主标题为类型为 main 的标题，否则为第一个标题
+        metadata.title = metadata
+            .titles
+            .iter()
+            .find(|title| title.title_type == "main")
+            .or(metadata.titles.first())
+            .map(|title| title.text.clone())
+            .unwrap_or_default();
+        // 只有 calibre 元数据时作为系列
+        if let Some(name) = calibre_series.filter(|_| metadata.collection.is_empty()) {
+            metadata
+                .collection
+                .push(Collection::new(name, "series", calibre_series_index));
+        }
+        metadata
+    }
+
+    /// 自定义元数据
+    pub fn meta(&self) -> &[MetaItem] {
+        &self.meta
+    }
     /// 增加自定义元数据
     pub fn add_meta(&mut self, meta_item: MetaItem) -> &mut Self {
         self.meta.push(meta_item);
@@ -256,9 +664,22 @@ impl Metadata {
         self.title = title.into();
         self
     }
+    /// 增加标题，如副标题；类型为 `main` 时同时设置主标题
+    pub fn add_title(&mut self, title: Title) -> &mut Self {
+        if title.title_type == "main" {
+            self.title = title.text.clone();
+        }
+        self.titles.push(title);
+        self
+    }
     /// set meta creator
     pub fn set_creator<S: Into<String>>(&mut self, creator: S) -> &mut Self {
-        self.creator.push(creator.into());
+        self.creator.push(Creator::new(creator, ""));
+        self
+    }
+    /// 增加作者，可带角色和排序名称
+    pub fn add_creator(&mut self, creator: Creator) -> &mut Self {
+        self.creator.push(creator);
         self
     }
     /// set meta subject
@@ -281,9 +702,31 @@ impl Metadata {
         self.publisher = Some(publisher.into());
         self
     }
-    /// set meta contributor
+    /// set meta contributor, replacing the existing contributors
     pub fn set_contributor<S: Into<String>>(&mut self, contributor: S) -> &mut Self {
-        self.contributor = Some(contributor.into());
+        self.contributor = vec![Creator::new(contributor, "")];
+        self
+    }
+    /// 增加贡献者，可带角色和排序名称
+    pub fn add_contributor(&mut self, contributor: Creator) -> &mut Self {
+        self.contributor.push(contributor);
+        self
+    }
+    /// 增加所属的丛书或合集
+    pub fn add_collection(&mut self, collection: Collection) -> &mut Self {
+        self.collection.push(collection);
+        self
+    }
+    /// 设置所属系列及在系列中的位置，替换已有的系列
+    pub fn set_series<S1, S2>(&mut self, name: S1, position: S2) -> &mut Self
+    where
+        S1: Into<String>,
+        S2: Into<String>,
+    {
+        replace_series(
+            &mut self.collection,
+            Collection::new(name, "series", position),
+        );
         self
     }
     /// set meta format
@@ -296,6 +739,11 @@ impl Metadata {
         self.identifier = Some(identifier);
         self
     }
+    /// 增加其他标识符
+    pub fn add_identifier(&mut self, identifier: Identifier) -> &mut Self {
+        self.other_identifiers.push(identifier);
+        self
+    }
     /// set meta source
     pub fn set_source<S: Into<String>>(&mut self, source: S) -> &mut Self {
         self.source = Some(source.into());
@@ -312,8 +760,13 @@ impl Metadata {
         self
     }
     /// set meta coverage
+    pub fn set_coverage<S: Into<String>>(&mut self, coverage: S) -> &mut Self {
+        self.coverage = Some(coverage.into());
+        self
+    }
+    /// set the manifest id of the cover image
     pub fn set_cover<S: Into<String>>(&mut self, cover: S) -> &mut Self {
-        self.coverage = Some(cover.into());
+        self.cover = Some(cover.into());
         self
     }
     /// set meta rights
@@ -321,14 +774,16 @@ impl Metadata {
         self.rights = Some(rights.into());
         self
     }
-    /// set published date
-    pub fn set_date_published<D: Into<chrono::DateTime<chrono::Utc>>>(
-        &mut self,
-        date_published: D,
-    ) -> &mut Self {
+    /// set published date, e.g. `Date::year(2010)?` or a `chrono::DateTime<Utc>`
+    pub fn set_date_published<D: Into<Date>>(&mut self, date_published: D) -> &mut Self {
         self.date_published = Some(date_published.into());
         self
     }
+    /// set created date
+    pub fn set_date_created<D: Into<Date>>(&mut self, date_created: D) -> &mut Self {
+        self.date_created = Some(date_created.into());
+        self
+    }
     /// set modified date
     pub fn set_date_modified<D: Into<chrono::DateTime<chrono::Utc>>>(
         &mut self,
@@ -340,7 +795,7 @@ impl Metadata {
 }
 
 /// 其他自定义元数据
-#[derive(Debug)]
+#[derive(Debug, Default, Clone)]
 pub struct MetaItem {
     pub refines: String,
     pub property: String,
@@ -351,20 +806,6 @@ pub struct MetaItem {
     pub content: String,
 }
 
-impl Default for MetaItem {
-    fn default() -> Self {
-        MetaItem {
-            refines: String::new(),
-            property: String::new(),
-            scheme: String::new(),
-            id: String::new(),
-            data: String::new(),
-            name: String::new(),
-            content: String::new(),
-        }
-    }
-}
-
 /// epub meta item
 impl MetaItem {
     /// Create a new meta item
@@ -423,7 +864,10 @@ impl ManifestItem {
 }
 
 /// epub identifier
-#[derive(Debug)]
+///
+/// The scheme, e.g. `ISBN`, `UUID` or `DOI`, is written as `opf:scheme` in EPUB 2. EPUB 3
+/// does not allow `opf:scheme`, ISBN, UUID and DOI identifiers are written as URNs instead.
+#[derive(Debug, Clone, Default, PartialEq)]
 #[allow(dead_code)]
 pub struct Identifier {
     pub id: String,
@@ -431,6 +875,271 @@ pub struct Identifier {
     pub text: String,
 }
 
+/// epub identifier
+impl Identifier {
+    /// Create a new identifier
+    pub fn new<S1: Into<String>, S2: Into<String>>(id: S1, text: S2) -> Identifier {
+        Identifier {
+            id: id.into(),
+            scheme: String::new(),
+            text: text.into(),
+        }
+    }
+
+    /// An ISBN-10 or ISBN-13, hyphens and spaces are removed
+    ///
+    /// Returns [`Error::InvalidMetadataErr`] when the check digit is wrong.
+    pub fn isbn<S1: Into<String>>(id: S1, isbn: &str) -> Result<Identifier, Error> {
+        let text = normalize_isbn(isbn);
+        if !is_valid_isbn(&text) {
+            return Err(Error::InvalidMetadataErr(format!("Invalid ISBN:{}", isbn)));
+        }
+        Ok(Identifier {
+            id: id.into(),
+            scheme: String::from("ISBN"),
+            text,
+        })
+    }
+
+    /// A UUID such as `0b6b1b4e-2f4e-4c6c-9a5e-6c1d0d7e7f10`
+    pub fn uuid<S1: Into<String>, S2: AsRef<str>>(id: S1, uuid: S2) -> Identifier {
+        let uuid = uuid.as_ref();
+        Identifier {
+            id: id.into(),
+            scheme: String::from("UUID"),
+            text: strip_prefix_ignore_case(uuid, "urn:uuid:")
+                .unwrap_or(uuid)
+                .to_lowercase(),
+        }
+    }
+
+    /// A random version 4 UUID
+    pub fn random_uuid<S1: Into<String>>(id: S1) -> Identifier {
+        let mut bytes: [u8; 16] = rand::random();
+        bytes[6] = (bytes[6] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+        Identifier::uuid(id, format_uuid(&bytes))
+    }
+
+    /// Check the check digit of an ISBN identifier, other identifiers are always valid
+    pub fn check(&self) -> Result<(), Error> {
+        let isbn = strip_prefix_ignore_case(&self.text, "urn:isbn:");
+        if self.scheme.eq_ignore_ascii_case("ISBN") || isbn.is_some() {
+            let text = isbn.unwrap_or(&self.text);
+            if !is_valid_isbn(&normalize_isbn(text)) {
+                return Err(Error::InvalidMetadataErr(format!("Invalid ISBN:{}", text)));
+            }
+        }
+        Ok(())
+    }
+
+    /// 写入包文件的值，EPUB 3 中 ISBN、UUID 和 DOI 使用 URN
+    pub(crate) fn value(&self, v3: bool) -> String {
+        let prefix = match self.scheme.to_ascii_uppercase().as_str() {
+            "ISBN" => "urn:isbn:",
+            "UUID" => "urn:uuid:",
+            "DOI" => "urn:doi:",
+            _ => "",
+        };
+        if !v3 || prefix.is_empty() || strip_prefix_ignore_case(&self.text, "urn:").is_some() {
+            return self.text.clone();
+        }
+        format!("{}{}", prefix, self.text)
+    }
+}
+
+/// Whether `isbn` is an ISBN-10 or ISBN-13 with a correct check digit, hyphens and spaces
+/// are ignored
+pub fn is_valid_isbn(isbn: &str) -> bool {
+    let isbn = normalize_isbn(isbn);
+    let digits: Vec<u32> = isbn
+        .chars()
+        .enumerate()
+        .map_while(|(index, c)| match c {
+            // ISBN-10 的校验位可以为 X
+            'X' if index == 9 && isbn.len() == 10 => Some(10),
+            _ => c.to_digit(10),
+        })
+        .collect();
+    if digits.len() != isbn.len() {
+        return false;
+    }
+    match digits.len() {
+        10 => {
+            let sum: u32 = digits.iter().zip((1..=10).rev()).map(|(d, w)| d * w).sum();
+            sum.is_multiple_of(11)
+        }
+        13 => {
+            let sum: u32 = digits
+                .iter()
+                .enumerate()
+                .map(|(index, d)| if index % 2 == 0 { *d } else { d * 3 })
+                .sum();
+            sum.is_multiple_of(10)
+        }
+        _ => false,
+    }
+}
+
+/// 删除 ISBN 中的连字符和空格，校验位 `x` 转换为大写
+fn normalize_isbn(isbn: &str) -> String {
+    isbn.chars()
+        .filter(|c| *c != '-' && !c.is_whitespace())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// 按 `8-4-4-4-12` 格式输出 UUID
+pub(crate) fn format_uuid(bytes: &[u8; 16]) -> String {
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+/// 忽略大小写去掉前缀
+fn strip_prefix_ignore_case<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = text.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix)
+        .then(|| &text[prefix.len()..])
+}
+
+/// 标题
+///
+/// EPUB 3 中 `title_type`、`display_seq` 和 `alternate_script` 写为 `refines` 元数据，
+/// EPUB 2 只写入标题文字和语言。
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Title {
+    /// 标题文字
+    pub text: String,
+    /// 标题类型：`main`、`subtitle`、`short`、`collection`、`edition` 或 `expanded`，可为空
+    pub title_type: String,
+    /// 阅读系统显示多个标题时的顺序
+    pub display_seq: Option<u32>,
+    /// 标题的语言，`xml:lang`
+    pub lang: String,
+    /// 其他文字的写法，如拼音或英文
+    pub alternate_script: Vec<AlternateScript>,
+}
+
+impl Title {
+    /// Create a new title, `title_type` may be empty
+    pub fn new<S1: Into<String>, S2: Into<String>>(text: S1, title_type: S2) -> Title {
+        Title {
+            text: text.into(),
+            title_type: title_type.into(),
+            ..Title::default()
+        }
+    }
+
+    /// 增加其他文字的写法
+    pub fn add_alternate_script<S1, S2>(&mut self, lang: S1, text: S2) -> &mut Self
+    where
+        S1: Into<String>,
+        S2: Into<String>,
+    {
+        self.alternate_script.push(AlternateScript::new(lang, text));
+        self
+    }
+}
+
+/// 标题或名称以其他语言或文字的写法，`alternate-script`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AlternateScript {
+    /// 语言，如 `en` 或 `zh-Latn-pinyin`
+    pub lang: String,
+    pub text: String,
+}
+
+impl AlternateScript {
+    /// Create a new alternate script
+    pub fn new<S1: Into<String>, S2: Into<String>>(lang: S1, text: S2) -> AlternateScript {
+        AlternateScript {
+            lang: lang.into(),
+            text: text.into(),
+        }
+    }
+}
+
+/// 作者或贡献者
+///
+/// EPUB 2 写为 `opf:role` 和 `opf:file-as` 属性，EPUB 3 写为 `refines` 元数据。
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Creator {
+    /// 显示名称
+    pub name: String,
+    /// 排序名称，如 `Tolkien, J. R. R.`
+    pub file_as: String,
+    /// MARC relator 角色代码，如 `aut` 作者、`trl` 译者、`ill` 插画、`edt` 编辑
+    pub role: String,
+}
+
+impl Creator {
+    /// Create a new creator, `role` may be empty
+    pub fn new<S1: Into<String>, S2: Into<String>>(name: S1, role: S2) -> Creator {
+        Creator {
+            name: name.into(),
+            file_as: String::new(),
+            role: role.into(),
+        }
+    }
+
+    fn from_opf(opf: DcElementOpf) -> Creator {
+        Creator {
+            name: opf.text.trim().to_string(),
+            file_as: opf.file_as,
+            role: opf.role,
+        }
+    }
+}
+
+/// 书籍所属的丛书或合集
+///
+/// EPUB 3 写为 `belongs-to-collection` 元数据，第一个系列同时写为 `calibre:series` 和
+/// `calibre:series_index`。
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Collection {
+    /// 名称
+    pub name: String,
+    /// 类型，`series` 为有序的系列，`set` 为套装，可为空
+    pub collection_type: String,
+    /// 在合集中的位置，如 `2` 或 `2.5`，可为空
+    pub position: String,
+}
+
+impl Collection {
+    /// Create a new collection
+    pub fn new<S1, S2, S3>(name: S1, collection_type: S2, position: S3) -> Collection
+    where
+        S1: Into<String>,
+        S2: Into<String>,
+        S3: Into<String>,
+    {
+        Collection {
+            name: name.into(),
+            collection_type: collection_type.into(),
+            position: position.into(),
+        }
+    }
+
+    /// 是否为系列，未指定类型时也作为系列
+    fn is_series(&self) -> bool {
+        self.collection_type.is_empty() || self.collection_type == "series"
+    }
+}
+
+/// 用 `series` 替换列表中的系列，保留其位置和其他合集
+pub(crate) fn replace_series(collections: &mut Vec<Collection>, series: Collection) {
+    let index = collections.iter().position(Collection::is_series);
+    collections.retain(|collection| !collection.is_series());
+    collections.insert(index.unwrap_or(collections.len()), series);
+}
+
 /// epub spine item ref
 #[derive(Debug)]
 #[allow(dead_code)]
@@ -482,6 +1191,18 @@ impl Default for GuideReference {
 
 /// epub guide reference
 impl GuideReference {
+    /// The guide reference type, e.g. `cover`
+    pub fn ref_type(&self) -> &str {
+        &self.ref_type
+    }
+    /// The guide reference title
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+    /// The guide reference href
+    pub fn href(&self) -> &str {
+        &self.href
+    }
     /// Create a new guide reference
     pub fn new<S1: Into<String>, S2: Into<String>, S3: Into<String>>(
         ref_type: S1,
@@ -505,6 +1226,14 @@ pub struct BindingItem {
 
 /// epub binding item
 impl BindingItem {
+    /// The binding media type
+    pub fn media_type(&self) -> &str {
+        &self.media_type
+    }
+    /// The binding handler href
+    pub fn href(&self) -> &str {
+        &self.href
+    }
     /// Create a new binding item
     pub fn new<S1: Into<String>, S2: Into<String>>(media_type: S1, href: S2) -> BindingItem {
         BindingItem {
@@ -515,7 +1244,7 @@ impl BindingItem {
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
-#[serde(rename = "package")]
+#[serde(rename = "package", default)]
 struct PackageOpf {
     #[serde(rename = "metadata")]
     metadata: MetadataOpf,
@@ -523,14 +1252,21 @@ struct PackageOpf {
     manifest: ManifestOpf,
     #[serde(rename = "spine")]
     spine: SpineOpf,
-    #[serde(rename = "guide")]
+    #[serde(rename = "guide", skip_serializing_if = "GuideReferenceOpf::is_empty")]
     guide: GuideReferenceOpf,
 
-    #[serde(rename = "bindings", skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "bindings", skip_serializing_if = "skip_if_empty_binding")]
     binding: Option<BindingOpf>,
 
     #[serde(rename = "@version")]
     version: String,
+    #[serde(
+        rename = "@unique-identifier",
+        skip_serializing_if = "String::is_empty"
+    )]
+    unique_identifier: String,
+    #[serde(rename = "@xmlns", skip_serializing_if = "String::is_empty")]
+    xmlns: String,
     #[serde(rename = "@xmlns:opf", skip_serializing_if = "String::is_empty")]
     xmlns_opf: String,
     #[serde(rename = "@xmlns:dc", skip_serializing_if = "String::is_empty")]
@@ -548,10 +1284,7 @@ impl PackageOpf {
             EpubVersion::V20 => "http://www.idpf.org/2007/opf".to_string(),
             EpubVersion::V30 => "http://www.idpf.org/2007/opf".to_string(),
         };
-        let xmlns_dc = match ver {
-            EpubVersion::V20 => "".to_string(),
-            EpubVersion::V30 => "http://purl.org/dc/elements/1.1/".to_string(),
-        };
+        let xmlns_dc = "http://purl.org/dc/elements/1.1/".to_string();
         let xmlns_xsi = match ver {
             EpubVersion::V20 => "".to_string(),
             EpubVersion::V30 => "http://www.w3.org/2001/XMLSchema-instance".to_string(),
@@ -562,10 +1295,12 @@ impl PackageOpf {
             spine: SpineOpf::default(),
             guide: GuideReferenceOpf::default(),
             version: match ver {
-                EpubVersion::V20 => String::from("v2.0"),
-                EpubVersion::V30 => String::from("v3.0"),
+                EpubVersion::V20 => String::from("2.0"),
+                EpubVersion::V30 => String::from("3.0"),
             },
             binding: Some(BindingOpf::default()),
+            unique_identifier: String::new(),
+            xmlns: String::from("http://www.idpf.org/2007/opf"),
             xmlns_opf,
             xmlns_dc,
             xmlns_xsi,
@@ -574,43 +1309,94 @@ impl PackageOpf {
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
-#[serde(rename = "metadata")]
+#[serde(rename = "metadata", default)]
 struct MetadataOpf {
-    #[serde(rename = "dc:creator", skip_serializing_if = "String::is_empty")]
-    creator: String,
-    #[serde(rename = "dc:title")]
-    title: String,
-    #[serde(rename = "dc:subject", skip_serializing_if = "Option::is_none")]
-    subject: Option<String>,
-    #[serde(rename = "dc:description", skip_serializing_if = "Option::is_none")]
-    description: Option<String>,
-    #[serde(rename = "dc:date", skip_serializing_if = "Option::is_none")]
-    date: Option<String>,
-    #[serde(rename = "dc:type", skip_serializing_if = "Option::is_none")]
-    category: Option<String>,
-    #[serde(rename = "dc:publisher", skip_serializing_if = "Option::is_none")]
-    publisher: Option<String>,
-    #[serde(rename = "dc:contributor", skip_serializing_if = "Option::is_none")]
-    contributor: Option<String>,
-    #[serde(rename = "dc:format", skip_serializing_if = "Option::is_none")]
-    format: Option<String>,
+    #[serde(
+        rename = "dc:creator",
+        alias = "creator",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    creator: Vec<DcElementOpf>,
+    #[serde(rename = "dc:title", alias = "title")]
+    title: Vec<DcElementOpf>,
+    #[serde(
+        rename = "dc:subject",
+        alias = "subject",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    subject: Vec<String>,
+    #[serde(
+        rename = "dc:description",
+        alias = "description",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    description: Vec<String>,
+    #[serde(
+        rename = "dc:date",
+        alias = "date",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    date: Vec<DcElementOpf>,
+    #[serde(
+        rename = "dc:type",
+        alias = "type",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    category: Vec<String>,
+    #[serde(
+        rename = "dc:publisher",
+        alias = "publisher",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    publisher: Vec<String>,
+    #[serde(
+        rename = "dc:contributor",
+        alias = "contributor",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    contributor: Vec<DcElementOpf>,
+    #[serde(
+        rename = "dc:format",
+        alias = "format",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    format: Vec<String>,
     #[serde(
         rename = "dc:identifier",
-        skip_serializing_if = "skip_if_empty_identifier"
+        alias = "identifier",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    identifier: Vec<IdentifierOpf>,
+    #[serde(
+        rename = "dc:source",
+        alias = "source",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    source: Vec<String>,
+    #[serde(
+        rename = "dc:language",
+        alias = "language",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    language: Vec<String>,
+    #[serde(
+        rename = "dc:relation",
+        alias = "relation",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    relation: Vec<String>,
+    #[serde(
+        rename = "dc:coverage",
+        alias = "coverage",
+        skip_serializing_if = "Vec::is_empty"
     )]
-    identifier: Option<IdentifierOpf>,
-    #[serde(rename = "dc:source", skip_serializing_if = "Option::is_none")]
-    source: Option<String>,
-    #[serde(rename = "dc:language", skip_serializing_if = "Option::is_none")]
-    language: Option<String>,
-    #[serde(rename = "dc:relation", skip_serializing_if = "Option::is_none")]
-    relation: Option<String>,
-    #[serde(rename = "dc:coverage", skip_serializing_if = "Option::is_none")]
-    coverage: Option<String>,
-    #[serde(rename = "dc:rights", skip_serializing_if = "Option::is_none")]
-    rights: Option<String>,
-    #[serde(rename = "dc:cover", skip_serializing_if = "Option::is_none")]
-    cover: Option<String>,
+    coverage: Vec<String>,
+    #[serde(
+        rename = "dc:rights",
+        alias = "rights",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    rights: Vec<String>,
 
     #[serde(rename = "meta", skip_serializing_if = "Vec::is_empty")]
     meta: Vec<MetaItemOpf>,
@@ -618,73 +1404,391 @@ struct MetadataOpf {
 
 impl MetadataOpf {
     fn new<S: Into<String>>(title: S, creator: S) -> MetadataOpf {
+        let creator = creator.into();
         MetadataOpf {
-            creator: creator.into(),
-            title: title.into(),
-            subject: None,
-            description: None,
-            date: None,
-            category: None,
-            publisher: None,
-            contributor: None,
-            format: None,
-            identifier: None,
-            source: None,
-            language: None,
-            relation: None,
-            coverage: None,
-            rights: None,
-            cover: None,
-            meta: Vec::new(),
+            creator: if creator.is_empty() {
+                Vec::new()
+            } else {
+                vec![DcElementOpf::new(creator)]
+            },
+            title: vec![DcElementOpf::new(title)],
+            ..MetadataOpf::default()
+        }
+    }
+}
+
+/// 带属性的 Dublin Core 元素，如 `<dc:creator id="creator">...</dc:creator>`
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct DcElementOpf {
+    #[serde(rename = "@id", skip_serializing_if = "String::is_empty")]
+    id: String,
+    #[serde(
+        rename = "@opf:role",
+        alias = "@role",
+        skip_serializing_if = "String::is_empty"
+    )]
+    role: String,
+    #[serde(
+        rename = "@opf:file-as",
+        alias = "@file-as",
+        skip_serializing_if = "String::is_empty"
+    )]
+    file_as: String,
+    #[serde(
+        rename = "@xml:lang",
+        alias = "@lang",
+        skip_serializing_if = "String::is_empty"
+    )]
+    lang: String,
+    #[serde(
+        rename = "@opf:event",
+        alias = "@event",
+        skip_serializing_if = "String::is_empty"
+    )]
+    event: String,
+    #[serde(rename = "$text")]
+    text: String,
+}
+
+impl DcElementOpf {
+    fn new<S: Into<String>>(text: S) -> DcElementOpf {
+        DcElementOpf {
+            text: text.into(),
+            ..DcElementOpf::default()
+        }
+    }
+}
+
+/// 标题转换为 `dc:title`，EPUB 3 中的类型、顺序和其他写法写为 `refines` 元数据
+fn encode_title(title: &Title, id: &str, v3: bool, meta: &mut Vec<MetaItemOpf>) -> DcElementOpf {
+    let mut element = DcElementOpf::new(title.text.clone());
+    element.lang = title.lang.clone();
+    if !v3 {
+        return element;
+    }
+    if title.title_type.is_empty()
+        && title.display_seq.is_none()
+        && title.alternate_script.is_empty()
+    {
+        return element;
+    }
+    element.id = id.to_string();
+    let refines = Some(format!("#{}", id));
+    if !title.title_type.is_empty() {
+        meta.push(MetaItemOpf {
+            text: title.title_type.clone(),
+            property: Some(String::from("title-type")),
+            refines: refines.clone(),
+            ..MetaItemOpf::default()
+        });
+    }
+    if let Some(display_seq) = title.display_seq {
+        meta.push(MetaItemOpf {
+            text: display_seq.to_string(),
+            property: Some(String::from("display-seq")),
+            refines: refines.clone(),
+            ..MetaItemOpf::default()
+        });
+    }
+    for alternate in title.alternate_script.iter() {
+        meta.push(MetaItemOpf {
+            text: alternate.text.clone(),
+            property: Some(String::from("alternate-script")),
+            refines: refines.clone(),
+            lang: non_empty(&alternate.lang),
+            ..MetaItemOpf::default()
+        });
+    }
+    element
+}
+
+/// 合集转换为 `belongs-to-collection` 及其 `collection-type`、`group-position` 元数据
+fn encode_collection(collection: &Collection, id: &str, meta: &mut Vec<MetaItemOpf>) {
+    meta.push(MetaItemOpf {
+        text: collection.name.clone(),
+        property: Some(String::from("belongs-to-collection")),
+        id: Some(id.to_string()),
+        ..MetaItemOpf::default()
+    });
+    for (property, value) in [
+        ("collection-type", &collection.collection_type),
+        ("group-position", &collection.position),
+    ] {
+        if !value.is_empty() {
+            meta.push(MetaItemOpf {
+                text: value.clone(),
+                property: Some(String::from(property)),
+                refines: Some(format!("#{}", id)),
+                ..MetaItemOpf::default()
+            });
         }
     }
 }
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
-#[serde(rename = "meta")]
+
+/// 作者转换为 `dc:creator`/`dc:contributor`
+///
+/// EPUB 2 使用 `opf:role` 和 `opf:file-as` 属性，EPUB 3 使用指向 `id` 的 `refines` 元数据。
+fn encode_creator(
+    creator: &Creator,
+    id: &str,
+    v3: bool,
+    meta: &mut Vec<MetaItemOpf>,
+) -> DcElementOpf {
+    let mut element = DcElementOpf::new(creator.name.clone());
+    if !v3 {
+        element.role = creator.role.clone();
+        element.file_as = creator.file_as.clone();
+        return element;
+    }
+    if creator.role.is_empty() && creator.file_as.is_empty() {
+        return element;
+    }
+    element.id = id.to_string();
+    if !creator.role.is_empty() {
+        meta.push(MetaItemOpf {
+            text: creator.role.clone(),
+            property: Some(String::from("role")),
+            refines: Some(format!("#{}", id)),
+            scheme: Some(String::from("marc:relators")),
+            ..MetaItemOpf::default()
+        });
+    }
+    if !creator.file_as.is_empty() {
+        meta.push(MetaItemOpf {
+            text: creator.file_as.clone(),
+            property: Some(String::from("file-as")),
+            refines: Some(format!("#{}", id)),
+            ..MetaItemOpf::default()
+        });
+    }
+    element
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename = "meta", default)]
 struct MetaItemOpf {
     #[serde(rename = "$text", skip_serializing_if = "String::is_empty")]
     text: String,
     #[serde(rename = "@name", skip_serializing_if = "Option::is_none")]
     name: Option<String>,
+    #[serde(rename = "@content", skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
     #[serde(rename = "@property", skip_serializing_if = "Option::is_none")]
     property: Option<String>,
+    #[serde(rename = "@id", skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(rename = "@refines", skip_serializing_if = "Option::is_none")]
+    refines: Option<String>,
+    #[serde(rename = "@scheme", skip_serializing_if = "Option::is_none")]
+    scheme: Option<String>,
+    #[serde(
+        rename = "@xml:lang",
+        alias = "@lang",
+        skip_serializing_if = "Option::is_none"
+    )]
+    lang: Option<String>,
 }
 
-/// 自定义结构体的序列化条件
-fn skip_if_empty_identifier(identifier: &Option<IdentifierOpf>) -> bool {
-    // *identifier.unwrap()?.is_some_and(|id| id.text.is_empty())
-    match identifier {
-        Some(id) => id.text.is_empty(),
-        None => true,
+impl MetaItemOpf {
+    /// `<meta name="..." content="..."/>` 形式的元数据
+    fn new_name<S1: Into<String>, S2: Into<String>>(name: S1, content: S2) -> MetaItemOpf {
+        MetaItemOpf {
+            name: Some(name.into()),
+            content: Some(content.into()),
+            ..MetaItemOpf::default()
+        }
+    }
+}
+
+/// A W3CDTF date, e.g. `2010`, `2010-05`, `2010-05-01` or `2010-05-01T08:00:00Z`
+///
+/// Times are converted to UTC and written as `CCYY-MM-DDThh:mm:ssZ`. Only the years 0000 to
+/// 9999 can be written, full dates and times outside them are clamped to the nearest one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Date(DateValue);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DateValue {
+    Year(i32),
+    /// 年和月，月份在 1 到 12 之间
+    Month(i32, u32),
+    Day(chrono::NaiveDate),
+    Time(chrono::DateTime<chrono::Utc>),
+}
+
+impl Date {
+    /// A year such as `2010`, between 0 and 9999
+    pub fn year(year: i32) -> Result<Date, super::Error> {
+        check_year(year)?;
+        Ok(Date(DateValue::Year(year)))
+    }
+
+    /// A year and a month such as `2010-05`
+    pub fn month(year: i32, month: u32) -> Result<Date, super::Error> {
+        check_year(year)?;
+        if !(1..=12).contains(&month) {
+            return Err(super::Error::InvalidMetadataErr(format!(
+                "Invalid month:{}",
+                month
+            )));
+        }
+        Ok(Date(DateValue::Month(year, month)))
+    }
+
+    /// A full date such as `2010-05-01`
+    pub fn day(year: i32, month: u32, day: u32) -> Result<Date, super::Error> {
+        check_year(year)?;
+        chrono::NaiveDate::from_ymd_opt(year, month, day)
+            .map(|date| Date(DateValue::Day(date)))
+            .ok_or_else(|| {
+                super::Error::InvalidMetadataErr(format!("Invalid date:{}-{}-{}", year, month, day))
+            })
+    }
+
+    /// Parse a W3CDTF date, `None` when it is invalid
+    pub fn parse(value: &str) -> Option<Date> {
+        let value = value.trim();
+        if value.contains('T') {
+            return parse_date(value).map(Date::from);
+        }
+        let parts: Vec<&str> = value.split('-').collect();
+        let year = parts
+            .first()
+            .filter(|y| y.len() == 4)?
+            .parse::<i32>()
+            .ok()?;
+        match parts[1..] {
+            [] => Date::year(year).ok(),
+            [month] => Date::month(year, month.parse().ok()?).ok(),
+            [month, day] => Date::day(year, month.parse().ok()?, day.parse().ok()?).ok(),
+            _ => None,
+        }
+    }
+}
+
+/// W3CDTF 能表示的第一天和最后一天
+fn date_range() -> (chrono::NaiveDate, chrono::NaiveDate) {
+    (
+        chrono::NaiveDate::from_ymd_opt(0, 1, 1).unwrap_or_default(),
+        chrono::NaiveDate::from_ymd_opt(9999, 12, 31).unwrap_or_default(),
+    )
+}
+
+/// W3CDTF 只能表示 0000 到 9999 年
+fn check_year(year: i32) -> Result<(), super::Error> {
+    if (0..=9999).contains(&year) {
+        Ok(())
+    } else {
+        Err(super::Error::InvalidMetadataErr(format!(
+            "Invalid year:{}",
+            year
+        )))
+    }
+}
+
+impl std::fmt::Display for Date {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            DateValue::Year(year) => write!(f, "{:04}", year),
+            DateValue::Month(year, month) => write!(f, "{:04}-{:02}", year, month),
+            DateValue::Day(date) => write!(f, "{}", date.format("%Y-%m-%d")),
+            DateValue::Time(time) => write!(f, "{}", time.format("%Y-%m-%dT%H:%M:%SZ")),
+        }
+    }
+}
+
+impl From<chrono::DateTime<chrono::Utc>> for Date {
+    fn from(time: chrono::DateTime<chrono::Utc>) -> Self {
+        let (min, max) = date_range();
+        let min = min.and_time(chrono::NaiveTime::MIN).and_utc();
+        let max = max.and_hms_opt(23, 59, 59).unwrap_or_default().and_utc();
+        Date(DateValue::Time(time.clamp(min, max)))
     }
 }
 
-/// 出版社信息
+impl From<chrono::NaiveDate> for Date {
+    fn from(date: chrono::NaiveDate) -> Self {
+        let (min, max) = date_range();
+        Date(DateValue::Day(date.clamp(min, max)))
+    }
+}
+
+impl From<std::time::SystemTime> for Date {
+    fn from(time: std::time::SystemTime) -> Self {
+        Date::from(chrono::DateTime::<chrono::Utc>::from(time))
+    }
+}
+
+/// 解析 W3CDTF 日期，支持 `YYYY`、`YYYY-MM`、`YYYY-MM-DD` 及完整的日期时间
+fn parse_date(value: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    let value = value.trim();
+    if let Ok(date) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Some(date.with_timezone(&chrono::Utc));
+    }
+    if let Ok(date) = chrono::DateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S %z") {
+        return Some(date.with_timezone(&chrono::Utc));
+    }
+    if let Ok(date) = chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S") {
+        return Some(date.and_utc());
+    }
+    let mut parts = value.splitn(3, '-');
+    let year = parts.next()?.parse::<i32>().ok()?;
+    let month = parts.next().map_or(Some(1), |m| m.parse::<u32>().ok())?;
+    let day = parts.next().map_or(Some(1), |d| d.parse::<u32>().ok())?;
+    chrono::NaiveDate::from_ymd_opt(year, month, day)?
+        .and_hms_opt(0, 0, 0)
+        .map(|d| d.and_utc())
+}
+
+/// 空字符串转换为 None
+fn non_empty(value: &str) -> Option<String> {
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
+/// 标识符
 #[derive(Debug, Default, Serialize, Deserialize)]
-#[serde(rename = "identifier")]
+#[serde(rename = "identifier", default)]
 struct IdentifierOpf {
     #[serde(rename = "@id", skip_serializing_if = "String::is_empty")]
     id: String,
-    #[serde(rename = "opf:scheme", skip_serializing_if = "String::is_empty")]
+    #[serde(
+        rename = "@opf:scheme",
+        alias = "@scheme",
+        skip_serializing_if = "String::is_empty"
+    )]
     scheme: String,
     #[serde(rename = "$text")]
     text: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct ManifestOpf {
-    #[serde(rename = "item", skip_serializing_if = "Vec::is_empty")]
-    items: Vec<ManifestItemOpf>,
+impl IdentifierOpf {
+    /// EPUB 3 不允许 `opf:scheme` 属性
+    fn new(identifier: &Identifier, v3: bool) -> IdentifierOpf {
+        IdentifierOpf {
+            id: identifier.id.clone(),
+            scheme: if v3 {
+                String::new()
+            } else {
+                identifier.scheme.clone()
+            },
+            text: identifier.value(v3),
+        }
+    }
 }
 
-impl Default for ManifestOpf {
-    fn default() -> Self {
-        ManifestOpf { items: Vec::new() }
-    }
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ManifestOpf {
+    #[serde(rename = "item", default, skip_serializing_if = "Vec::is_empty")]
+    items: Vec<ManifestItemOpf>,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
-#[serde(rename = "item")]
+#[serde(rename = "item", default)]
 struct ManifestItemOpf {
     #[serde(rename = "@id")]
     id: String,
@@ -692,10 +1796,12 @@ struct ManifestItemOpf {
     href: String,
     #[serde(rename = "@media-type")]
     media_type: String,
+    #[serde(rename = "@properties", skip_serializing_if = "String::is_empty")]
+    properties: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-#[serde(rename = "spine")]
+#[serde(rename = "spine", default)]
 struct SpineOpf {
     #[serde(rename = "@toc")]
     toc: String,
@@ -712,8 +1818,8 @@ impl Default for SpineOpf {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename = "spine")]
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename = "itemref", default)]
 struct SpineItemRefOpf {
     #[serde(rename = "@idref")]
     idref: String,
@@ -721,21 +1827,21 @@ struct SpineItemRefOpf {
     properties: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 #[serde(rename = "guide ")]
 struct GuideReferenceOpf {
-    #[serde(rename = "reference", skip_serializing_if = "Vec::is_empty")]
+    #[serde(rename = "reference", default, skip_serializing_if = "Vec::is_empty")]
     items: Vec<GuideReferenceItemOpf>,
 }
 
-impl Default for GuideReferenceOpf {
-    fn default() -> Self {
-        GuideReferenceOpf { items: Vec::new() }
+impl GuideReferenceOpf {
+    fn is_empty(&self) -> bool {
+        self.items.is_empty()
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename = "reference ")]
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename = "reference ", default)]
 struct GuideReferenceItemOpf {
     #[serde(rename = "@type")]
     ref_type: String,
@@ -745,21 +1851,24 @@ struct GuideReferenceItemOpf {
     href: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 #[serde(rename = "bindings ")]
 struct BindingOpf {
-    #[serde(rename = "binding", skip_serializing_if = "Vec::is_empty")]
+    #[serde(rename = "binding", default, skip_serializing_if = "Vec::is_empty")]
     items: Vec<BindingItemOpf>,
 }
 
-impl Default for BindingOpf {
-    fn default() -> Self {
-        BindingOpf { items: Vec::new() }
+/// 没有 binding 时不输出 bindings 节点
+fn skip_if_empty_binding(binding: &Option<BindingOpf>) -> bool {
+    match binding {
+        Some(b) => b.items.is_empty(),
+        None => true,
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 #[allow(dead_code)]
+#[serde(default)]
 struct BindingItemOpf {
     #[serde(rename = "@media-type", skip_serializing_if = "String::is_empty")]
     media_type: String,
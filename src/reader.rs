@@ -0,0 +1,317 @@
+use crate::container::Container;
+use crate::epub::{EpubVersion, CONTAINER_FILENAME, MEDIA_TYPE_NCX, META_INF_FOLDER_NAME};
+use crate::opf::{ManifestItem, Metadata, Package};
+use crate::toc::TocNav;
+use crate::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{Cursor, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use zip::ZipArchive;
+
+/// zip 归档的底层读取器
+trait ReadSeek: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+/// 书籍文件的来源
+enum Source {
+    /// `.epub` zip 归档
+    Archive(Mutex<ZipArchive<Box<dyn ReadSeek>>>),
+    /// 已解压的目录
+    Directory(PathBuf),
+}
+
+/// An existing EPUB opened for reading.
+///
+/// The container document is resolved when the book is opened and the first rootfile is
+/// parsed into a [`Package`]. Resources are read lazily by href or manifest id.
+pub struct Book {
+    source: Source,
+    container: Container,
+    package: Package,
+    package_path: String,
+}
+
+impl fmt::Debug for Book {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Book")
+            .field("container", &self.container)
+            .field("package_path", &self.package_path)
+            .finish()
+    }
+}
+
+impl Book {
+    /// Open a `.epub` file or an exploded EPUB directory
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Book, Error> {
+        let path = path.as_ref();
+        if path.is_dir() {
+            return Book::load(Source::Directory(path.to_path_buf()));
+        }
+        if !path.exists() {
+            return Err(Error::FileNotFoundErr(format!(
+                "File not found:{}",
+                path.display()
+            )));
+        }
+        Book::from_reader(File::open(path)?)
+    }
+
+    /// Open an EPUB held in memory
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Book, Error> {
+        Book::from_reader(Cursor::new(bytes))
+    }
+
+    /// Open an EPUB zip archive from any seekable reader
+    pub fn from_reader<R: Read + Seek + Send + 'static>(reader: R) -> Result<Book, Error> {
+        let reader: Box<dyn ReadSeek> = Box::new(reader);
+        let archive = ZipArchive::new(reader)
+            .map_err(|e| Error::ArchiveErr(format!("Could not read archive:{}", e)))?;
+        Book::load(Source::Archive(Mutex::new(archive)))
+    }
+
+    fn load(source: Source) -> Result<Book, Error> {
+        let mut book = Book {
+            source,
+            container: Container::new(),
+            package: Package::new(),
+            package_path: String::new(),
+        };
+
+        let container_path = format!("{}/{}", META_INF_FOLDER_NAME, CONTAINER_FILENAME);
+        book.container = Container::decode_xml(&book.read_file_to_string(&container_path)?)?;
+        book.package_path = book
+            .container
+            .rootfiles()
+            .first()
+            .map(|rootfile| rootfile.full_path.clone())
+            .ok_or_else(|| Error::InvalidMetadataErr(String::from("Missing rootfile")))?;
+        book.package = Package::decode_xml(&book.read_file_to_string(&book.package_path)?)?;
+        Ok(book)
+    }
+
+    /// The container document
+    pub fn container(&self) -> &Container {
+        &self.container
+    }
+    /// The package document of the default rendition
+    pub fn package(&self) -> &Package {
+        &self.package
+    }
+    /// The package metadata
+    pub fn metadata(&self) -> &Metadata {
+        self.package.metadata()
+    }
+    /// The EPUB version declared by the package document
+    pub fn version(&self) -> EpubVersion {
+        self.package.version().unwrap_or(EpubVersion::V20)
+    }
+    /// Path of the package document inside the container, e.g. `EPUB/content.opf`
+    pub fn package_path(&self) -> &str {
+        &self.package_path
+    }
+
+    /// Resolve a manifest href to a path inside the container
+    pub fn resolve_href(&self, href: &str) -> String {
+        resolve_path(&self.package_path, href)
+    }
+
+    /// Find a manifest item by id
+    pub fn resource_by_id(&self, id: &str) -> Option<&ManifestItem> {
+        self.package.manifest_item(id)
+    }
+
+    /// Find a manifest item by href, fragments are ignored
+    pub fn resource_by_href(&self, href: &str) -> Option<&ManifestItem> {
+        let full_path = self.resolve_href(href);
+        self.package
+            .manifest()
+            .iter()
+            .find(|m| self.resolve_href(&m.href) == full_path)
+    }
+
+    /// Manifest items of the spine in reading order, unknown idrefs are skipped
+    pub fn spine_items(&self) -> Vec<&ManifestItem> {
+        self.package
+            .spine()
+            .iter()
+            .filter_map(|s| self.package.manifest_item(&s.idref))
+            .collect()
+    }
+
+    /// Decode the table of contents
+    ///
+    /// The EPUB 3 navigation document is preferred, the NCX is used when the book has no
+    /// navigation document.
+    pub fn toc(&self) -> Result<TocNav, Error> {
+        let item = self
+            .toc_item()
+            .ok_or_else(|| Error::FileNotFoundErr(String::from("Table of contents not found")))?;
+        let xml = self.read_href_to_string(&item.href)?;
+        if item.media_type == MEDIA_TYPE_NCX {
+            TocNav::decode_ncx(&xml)
+        } else {
+            TocNav::decode_nav(&xml)
+        }
+    }
+
+    /// 目录文件对应的 manifest 项，优先使用 EPUB 3 导航文档
+    pub(crate) fn toc_item(&self) -> Option<&ManifestItem> {
+        let manifest = self.package.manifest();
+        manifest
+            .iter()
+            .find(|m| m.properties.split_whitespace().any(|p| p == "nav"))
+            .or_else(|| manifest.iter().find(|m| m.media_type == MEDIA_TYPE_NCX))
+    }
+
+    /// Read a resource by manifest href
+    pub fn read_href(&self, href: &str) -> Result<Vec<u8>, Error> {
+        self.read_file(&self.resolve_href(href))
+    }
+
+    /// Read a resource by manifest id
+    pub fn read_id(&self, id: &str) -> Result<Vec<u8>, Error> {
+        let item = self
+            .resource_by_id(id)
+            .ok_or_else(|| Error::FileNotFoundErr(format!("Manifest item not found:{}", id)))?;
+        self.read_href(&item.href)
+    }
+
+    /// Read a text resource by manifest href
+    pub fn read_href_to_string(&self, href: &str) -> Result<String, Error> {
+        self.read_file_to_string(&self.resolve_href(href))
+    }
+
+    /// Read a file by its path inside the container
+    pub fn read_file(&self, full_path: &str) -> Result<Vec<u8>, Error> {
+        match &self.source {
+            Source::Archive(archive) => {
+                let mut archive = archive.lock().unwrap();
+                let mut file = archive
+                    .by_name(full_path)
+                    .map_err(|_| Error::FileNotFoundErr(format!("File not found:{}", full_path)))?;
+                let mut content = Vec::with_capacity(file.size() as usize);
+                file.read_to_end(&mut content)?;
+                Ok(content)
+            }
+            Source::Directory(root) => {
+                let path = root.join(full_path);
+                if !path.is_file() {
+                    return Err(Error::FileNotFoundErr(format!(
+                        "File not found:{}",
+                        full_path
+                    )));
+                }
+                Ok(fs::read(path)?)
+            }
+        }
+    }
+
+    /// Read a text file by its path inside the container
+    pub fn read_file_to_string(&self, full_path: &str) -> Result<String, Error> {
+        let content = self.read_file(full_path)?;
+        let content = String::from_utf8(content)
+            .map_err(|e| Error::DeserializeErr(format!("Invalid UTF-8 in {}:{}", full_path, e)))?;
+        // 去掉 UTF-8 BOM
+        Ok(content.trim_start_matches('\u{feff}').to_string())
+    }
+
+    /// 归档第一个条目的名称及是否未压缩存储，目录来源返回 `None`
+    pub(crate) fn first_entry(&self) -> Option<(String, bool)> {
+        match &self.source {
+            Source::Archive(archive) => {
+                let mut archive = archive.lock().unwrap();
+                let entry = archive.by_index_raw(0).ok()?;
+                Some((
+                    entry.name().to_string(),
+                    entry.compression() == zip::CompressionMethod::Stored,
+                ))
+            }
+            Source::Directory(_) => None,
+        }
+    }
+
+    /// All file paths in the container
+    pub fn file_names(&self) -> Vec<String> {
+        match &self.source {
+            Source::Archive(archive) => archive
+                .lock()
+                .unwrap()
+                .file_names()
+                .filter(|name| !name.ends_with('/'))
+                .map(String::from)
+                .collect(),
+            Source::Directory(root) => {
+                let mut names = Vec::new();
+                let mut stack = vec![root.clone()];
+                while let Some(dir) = stack.pop() {
+                    let Ok(entries) = fs::read_dir(&dir) else {
+                        continue;
+                    };
+                    for entry in entries.flatten() {
+                        let path = entry.path();
+                        if path.is_dir() {
+                            stack.push(path);
+                        } else if let Ok(relative) = path.strip_prefix(root) {
+                            let parts: Vec<_> = relative
+                                .components()
+                                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                                .collect();
+                            names.push(parts.join("/"));
+                        }
+                    }
+                }
+                names.sort();
+                names
+            }
+        }
+    }
+}
+
+/// 将相对于 `base_file` 的链接解析为容器内的完整路径，去掉片段标识并解码百分号编码
+pub(crate) fn resolve_path(base_file: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or_default();
+    let href = percent_decode(href);
+    let mut parts: Vec<&str> = Vec::new();
+    if !href.starts_with('/') {
+        parts.extend(base_file.split('/'));
+        parts.pop();
+    }
+    for part in href.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+    parts.retain(|p| !p.is_empty());
+    parts.join("/")
+}
+
+/// 解码 URL 中的百分号编码，如 `%20`
+pub(crate) fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%' && index + 2 < bytes.len() {
+            let hex = (hex_value(bytes[index + 1]), hex_value(bytes[index + 2]));
+            if let (Some(high), Some(low)) = hex {
+                decoded.push(high * 16 + low);
+                index += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[index]);
+        index += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn hex_value(byte: u8) -> Option<u8> {
+    (byte as char).to_digit(16).map(|v| v as u8)
+}
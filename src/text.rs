@@ -0,0 +1,292 @@
+//! Import plain-text novels.
+//!
+//! The encoding of the text is detected, the text is split into volumes and chapters by
+//! heading patterns and every line becomes a `<p>` paragraph.
+//!
+//! ```no_run
+//! use rust_epub::epub::{EpubBuilder, EpubVersion};
+//! use rust_epub::text::TextImporter;
+//!
+//! let mut epub = EpubBuilder::new("梦回大明春", EpubVersion::V30);
+//! TextImporter::new().import_file(&mut epub, "novel.txt").unwrap();
+//! ```
+use crate::epub::EpubBuilder;
+use crate::markup::is_xml_char;
+use crate::Error;
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8};
+use regex::Regex;
+use std::fs;
+use std::path::Path;
+
+/// 中文数字和阿拉伯数字
+const NUMBER: &str = "[0-9０-９零〇一二两三四五六七八九十百千万壹贰叁肆伍陆柒捌玖拾佰仟]+";
+
+/// 卷标题默认模式
+fn default_volume_patterns() -> Vec<Regex> {
+    [
+        format!(r"^第{}[卷部集](?:[\s:：、.].*)?$", NUMBER),
+        String::from(r"^(?i:volume|book|part)\s+[0-9IVXLC]+\b.*$"),
+    ]
+    .iter()
+    .filter_map(|pattern| Regex::new(pattern).ok())
+    .collect()
+}
+
+/// 章标题默认模式
+fn default_chapter_patterns() -> Vec<Regex> {
+    [
+        format!(r"^第{}[章节回](?:[\s:：、.].*)?$", NUMBER),
+        String::from(r"^(?i:chapter)\s+[0-9IVXLC]+\b.*$"),
+        String::from(r"^(?:序章|序言|楔子|引子|尾声|后记|番外)(?:[\s:：、.].*)?$"),
+    ]
+    .iter()
+    .filter_map(|pattern| Regex::new(pattern).ok())
+    .collect()
+}
+
+/// A volume or chapter found in the text
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TextSection {
+    /// The heading line
+    pub title: String,
+    /// Non-empty lines after the heading, trimmed
+    pub paragraphs: Vec<String>,
+    /// Chapters of a volume
+    pub chapters: Vec<TextSection>,
+}
+
+impl TextSection {
+    fn new<S: Into<String>>(title: S) -> TextSection {
+        TextSection {
+            title: title.into(),
+            ..TextSection::default()
+        }
+    }
+
+    /// 正文：标题和段落
+    fn body(&self, heading: &str) -> String {
+        let mut body = format!("<{}>{}</{}>\n", heading, escape(&self.title), heading);
+        for paragraph in self.paragraphs.iter() {
+            body.push_str("<p>");
+            body.push_str(&escape(paragraph));
+            body.push_str("</p>\n");
+        }
+        body
+    }
+}
+
+/// Imports plain-text novels into an [`EpubBuilder`].
+///
+/// A line is a volume heading when it matches one of the volume patterns, e.g. `第一卷 蛮家子`,
+/// and a chapter heading when it matches one of the chapter patterns, e.g. `第001章 穿越`
+/// or `Chapter 12`. Patterns are matched against the trimmed line and lines longer than
+/// the maximum title length are never headings. Text before the first heading becomes a
+/// preface section.
+#[derive(Debug, Clone)]
+pub struct TextImporter {
+    volume_patterns: Vec<Regex>,
+    chapter_patterns: Vec<Regex>,
+    encoding: Option<&'static Encoding>,
+    max_title_length: usize,
+    preface_title: String,
+}
+
+impl Default for TextImporter {
+    fn default() -> Self {
+        TextImporter {
+            volume_patterns: default_volume_patterns(),
+            chapter_patterns: default_chapter_patterns(),
+            encoding: None,
+            max_title_length: 50,
+            preface_title: String::from("前言"),
+        }
+    }
+}
+
+impl TextImporter {
+    pub fn new() -> TextImporter {
+        TextImporter::default()
+    }
+
+    /// Replace the volume heading patterns, an empty list disables volumes
+    pub fn set_volume_patterns<I, S>(&mut self, patterns: I) -> Result<&mut Self, Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.volume_patterns = compile(patterns)?;
+        Ok(self)
+    }
+
+    /// Replace the chapter heading patterns
+    pub fn set_chapter_patterns<I, S>(&mut self, patterns: I) -> Result<&mut Self, Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.chapter_patterns = compile(patterns)?;
+        Ok(self)
+    }
+
+    /// Use an encoding instead of detecting it, e.g. `gbk` or `gb18030`
+    pub fn set_encoding(&mut self, label: &str) -> Result<&mut Self, Error> {
+        let encoding = Encoding::for_label(label.as_bytes())
+            .ok_or_else(|| Error::NonEncodable(format!("Unknown encoding:{}", label)))?;
+        self.encoding = Some(encoding);
+        Ok(self)
+    }
+
+    /// Lines longer than `length` characters are never headings, 50 by default
+    pub fn set_max_title_length(&mut self, length: usize) -> &mut Self {
+        self.max_title_length = length;
+        self
+    }
+
+    /// Title of the section holding the text before the first heading
+    pub fn set_preface_title<S: Into<String>>(&mut self, title: S) -> &mut Self {
+        self.preface_title = title.into();
+        self
+    }
+
+    /// Decode the text
+    ///
+    /// A byte order mark wins, then the encoding set with [`TextImporter::set_encoding`],
+    /// then valid UTF-8. Anything else is guessed, GBK and GB18030 text is decoded as
+    /// GB18030.
+    pub fn decode(&self, bytes: &[u8]) -> Result<String, Error> {
+        let encoding = match Encoding::for_bom(bytes) {
+            Some((encoding, _)) => encoding,
+            None => match self.encoding {
+                Some(encoding) => encoding,
+                None if std::str::from_utf8(bytes).is_ok() => UTF_8,
+                None => {
+                    let mut detector = EncodingDetector::new();
+                    detector.feed(bytes, true);
+                    detector.guess(None, true)
+                }
+            },
+        };
+        // GBK 是 GB18030 的子集
+        let encoding = if encoding == encoding_rs::GBK {
+            encoding_rs::GB18030
+        } else {
+            encoding
+        };
+        let (text, used, had_errors) = encoding.decode(bytes);
+        if had_errors {
+            return Err(Error::NonEncodable(format!(
+                "Text is not valid {}",
+                used.name()
+            )));
+        }
+        log::debug!("Decoded text as {}", used.name());
+        Ok(text.into_owned())
+    }
+
+    /// Split text into sections
+    ///
+    /// Chapters before the first volume and the preface are returned at the top level,
+    /// chapters after a volume heading are nested in that volume.
+    pub fn parse(&self, text: &str) -> Vec<TextSection> {
+        let mut sections: Vec<TextSection> = Vec::new();
+        // 当前章节是否属于最后一卷
+        let mut in_volume = false;
+        for line in text.lines() {
+            let line = line.trim_matches(|c: char| c.is_whitespace() || c == '\u{feff}');
+            if line.is_empty() {
+                continue;
+            }
+            if self.matches(&self.volume_patterns, line) {
+                sections.push(TextSection::new(line));
+                in_volume = true;
+            } else if self.matches(&self.chapter_patterns, line) {
+                match sections.last_mut() {
+                    Some(volume) if in_volume => volume.chapters.push(TextSection::new(line)),
+                    _ => sections.push(TextSection::new(line)),
+                }
+            } else {
+                let current = match sections.last_mut() {
+                    Some(volume) if in_volume => match volume.chapters.last_mut() {
+                        Some(chapter) => chapter,
+                        None => volume,
+                    },
+                    Some(chapter) => chapter,
+                    None => {
+                        sections.push(TextSection::new(self.preface_title.as_str()));
+                        sections.last_mut().unwrap()
+                    }
+                };
+                current.paragraphs.push(line.to_string());
+            }
+        }
+        sections
+    }
+
+    /// Decode, split and add the text to `epub`, returns the filenames of the new sections
+    pub fn import(&self, epub: &mut EpubBuilder, bytes: &[u8]) -> Result<Vec<String>, Error> {
+        let text = self.decode(bytes)?;
+        let mut filenames = Vec::new();
+        for section in self.parse(&text) {
+            let filename =
+                epub.add_section(section.body("h1"), section.title.as_str(), None, None)?;
+            filenames.push(filename.clone());
+            for chapter in section.chapters.iter() {
+                filenames.push(epub.add_sub_section(
+                    Some(filename.clone()),
+                    chapter.body("h2"),
+                    chapter.title.as_str(),
+                    None,
+                    None,
+                )?);
+            }
+        }
+        Ok(filenames)
+    }
+
+    /// Read a text file and add it to `epub`, see [`TextImporter::import`]
+    pub fn import_file<P: AsRef<Path>>(
+        &self,
+        epub: &mut EpubBuilder,
+        path: P,
+    ) -> Result<Vec<String>, Error> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(|e| {
+            Error::FileNotFoundErr(format!("Could not read {}:{}", path.display(), e))
+        })?;
+        self.import(epub, &bytes)
+    }
+
+    fn matches(&self, patterns: &[Regex], line: &str) -> bool {
+        line.chars().count() <= self.max_title_length
+            && patterns.iter().any(|pattern| pattern.is_match(line))
+    }
+}
+
+fn compile<I, S>(patterns: I) -> Result<Vec<Regex>, Error>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    patterns
+        .into_iter()
+        .map(|pattern| {
+            Regex::new(pattern.as_ref())
+                .map_err(|e| Error::InvalidPatternErr(format!("Invalid pattern:{}", e)))
+        })
+        .collect()
+}
+
+/// 转义文本并删除 XML 不允许的字符
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars().filter(|c| is_xml_char(*c)) {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -1,13 +1,20 @@
 use crate::epub::EpubVersion;
 use dashmap::DashMap;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct TocNav {
     title: String,
     lang: String,
     metadata: DashMap<String, String>,
     elements: Vec<TocElement>,
+    authors: Vec<String>,
+    landmarks: Vec<Landmark>,
+    page_list: Vec<PageTarget>,
+    reading_order: Vec<String>,
 }
 
 impl TocNav {
@@ -17,6 +24,10 @@ impl TocNav {
             lang: lang.into(),
             metadata: DashMap::new(),
             elements: Vec::new(),
+            authors: Vec::new(),
+            landmarks: Vec::new(),
+            page_list: Vec::new(),
+            reading_order: Vec::new(),
         }
     }
 
@@ -36,6 +47,261 @@ impl TocNav {
         self.elements.push(elem);
         self
     }
+    /// 目录标题
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+    /// 目录语言
+    pub fn lang(&self) -> &str {
+        &self.lang
+    }
+    /// 获取元数据
+    pub fn metadata(&self, name: &str) -> Option<String> {
+        self.metadata.get(name).map(|v| v.value().clone())
+    }
+    /// 顶层目录节点
+    pub fn elements(&self) -> &[TocElement] {
+        &self.elements
+    }
+    /// 可修改的顶层目录节点，用于增删、排序或改写目录
+    pub fn elements_mut(&mut self) -> &mut Vec<TocElement> {
+        &mut self.elements
+    }
+    /// 增加一个作者，写入 NCX 的 `docAuthor`
+    pub fn add_author<S: Into<String>>(&mut self, author: S) -> &mut TocNav {
+        self.authors.push(author.into());
+        self
+    }
+    /// 作者
+    pub fn authors(&self) -> &[String] {
+        &self.authors
+    }
+    /// 增加一个地标，写入 EPUB 3 导航文档的 `landmarks`
+    pub fn add_landmark(&mut self, landmark: Landmark) -> &mut TocNav {
+        self.landmarks.push(landmark);
+        self
+    }
+    /// 地标
+    pub fn landmarks(&self) -> &[Landmark] {
+        &self.landmarks
+    }
+    /// 可修改的地标
+    pub fn landmarks_mut(&mut self) -> &mut Vec<Landmark> {
+        &mut self.landmarks
+    }
+    /// 增加一个印刷页，写入导航文档的 `page-list` 和 NCX 的 `pageList`
+    pub fn add_page(&mut self, page: PageTarget) -> &mut TocNav {
+        self.page_list.push(page);
+        self
+    }
+    /// 印刷页列表
+    pub fn pages(&self) -> &[PageTarget] {
+        &self.page_list
+    }
+    /// 可修改的印刷页列表
+    pub fn pages_mut(&mut self) -> &mut Vec<PageTarget> {
+        &mut self.page_list
+    }
+    /// 设置阅读顺序，依次为各文档及其中带 id 的元素，如 `xhtml/chapter_1.xhtml#page_12`
+    ///
+    /// NCX 的 playOrder 按此顺序编排目录节点和印刷页，未设置时印刷页排在所有目录节点之后。
+    pub fn set_reading_order(&mut self, order: Vec<String>) -> &mut TocNav {
+        self.reading_order = order;
+        self
+    }
+    /// 设置目录标题
+    pub fn set_title<S: Into<String>>(&mut self, title: S) -> &mut TocNav {
+        self.title = title.into();
+        self
+    }
+
+    /// Decode an EPUB 2 `toc.ncx` document
+    pub fn decode_ncx(xml: &str) -> Result<TocNav, super::Error> {
+        let ncx: TocNCX = quick_xml::de::from_str(xml)
+            .map_err(|e| super::Error::DeserializeErr(format!("Invalid ncx document:{}", e)))?;
+        let mut toc = TocNav::new(ncx.doc_title.text, ncx.lang);
+        toc.authors = ncx
+            .doc_author
+            .into_iter()
+            .map(|author| normalize_space(&author.text))
+            .collect();
+        for meta in ncx.head.meta {
+            toc.add_metadata(meta.name, meta.content);
+        }
+        toc.elements = ncx
+            .nav_map
+            .nav_point
+            .iter()
+            .map(|p| p.to_toc_element(1))
+            .collect();
+        if let Some(page_list) = ncx.page_list {
+            toc.page_list = page_list
+                .page_target
+                .into_iter()
+                .map(|target| PageTarget {
+                    name: normalize_space(&target.nav_label.text),
+                    url: target.content.src,
+                })
+                .collect();
+        }
+        Ok(toc)
+    }
+
+    /// Decode the `nav epub:type="toc"` of an EPUB 3 navigation document
+    pub fn decode_nav(xml: &str) -> Result<TocNav, super::Error> {
+        let mut reader = Reader::from_str(xml);
+        reader.config_mut().check_end_names = false;
+
+        let mut toc = TocNav::default();
+        let mut in_toc = false;
+        let mut found = false;
+        let mut nav_depth = 0;
+        let mut in_title = false;
+        // 当前打开的 li 节点
+        let mut stack: Vec<TocElement> = Vec::new();
+        // 正在读取的标题或链接文本, 以及嵌套深度
+        let mut label: Option<(String, usize)> = None;
+        let mut heading: Option<String> = None;
+
+        loop {
+            let event = reader
+                .read_event()
+                .map_err(|e| super::Error::DeserializeErr(format!("Invalid nav document:{}", e)))?;
+            match event {
+                Event::Start(ref e) => {
+                    let name = e.local_name();
+                    let name = name.as_ref();
+                    if !in_toc {
+                        match name {
+                            b"html" => {
+                                if let Some(lang) =
+                                    attribute(e, b"xml:lang").or(attribute(e, b"lang"))
+                                {
+                                    toc.lang = lang;
+                                }
+                            }
+                            b"title" => in_title = true,
+                            b"nav" if !found && is_toc_nav(e) => {
+                                in_toc = true;
+                                found = true;
+                                nav_depth = 0;
+                            }
+                            _ => {}
+                        }
+                        continue;
+                    }
+                    nav_depth += 1;
+                    if let Some((_, ref mut depth)) = label {
+                        *depth += 1;
+                        continue;
+                    }
+                    match name {
+                        b"li" => stack.push(TocElement {
+                            level: stack.len() as i32 + 1,
+                            ..TocElement::default()
+                        }),
+                        b"a" | b"span" => {
+                            if let Some(current) = stack.last_mut() {
+                                if current.title.is_empty() {
+                                    if name == b"a" {
+                                        current.url = attribute(e, b"href").unwrap_or_default();
+                                    }
+                                    label = Some((String::new(), 1));
+                                }
+                            }
+                        }
+                        b"h1" | b"h2" | b"h3" | b"h4" | b"h5" | b"h6" if stack.is_empty() => {
+                            heading = Some(String::new());
+                        }
+                        _ => {}
+                    }
+                }
+                Event::Empty(ref e) => {
+                    let name = e.local_name();
+                    if in_toc && name.as_ref() == b"a" && label.is_none() {
+                        if let Some(current) = stack.last_mut() {
+                            if current.url.is_empty() {
+                                current.url = attribute(e, b"href").unwrap_or_default();
+                            }
+                        }
+                    }
+                }
+                Event::Text(ref e) => {
+                    let text = e
+                        .unescape()
+                        .map(|t| t.into_owned())
+                        .unwrap_or_else(|_| String::from_utf8_lossy(e).into_owned());
+                    if let Some((ref mut buffer, _)) = label {
+                        buffer.push_str(&text);
+                    } else if let Some(ref mut buffer) = heading {
+                        buffer.push_str(&text);
+                    } else if in_title && toc.title.is_empty() {
+                        toc.title = normalize_space(&text);
+                    }
+                }
+                Event::CData(ref e) => {
+                    if let Some((ref mut buffer, _)) = label {
+                        buffer.push_str(&String::from_utf8_lossy(e));
+                    }
+                }
+                Event::End(ref e) => {
+                    if !in_toc {
+                        if e.local_name().as_ref() == b"title" {
+                            in_title = false;
+                        }
+                        continue;
+                    }
+                    if nav_depth == 0 {
+                        // </nav>
+                        in_toc = false;
+                        continue;
+                    }
+                    nav_depth -= 1;
+                    if let Some((buffer, depth)) = label.take() {
+                        if depth > 1 {
+                            label = Some((buffer, depth - 1));
+                        } else if let Some(current) = stack.last_mut() {
+                            current.title = normalize_space(&buffer);
+                        }
+                        continue;
+                    }
+                    match e.local_name().as_ref() {
+                        b"li" => {
+                            if let Some(element) = stack.pop() {
+                                match stack.last_mut() {
+                                    Some(parent) => parent.childs.push(element),
+                                    None => toc.elements.push(element),
+                                }
+                            }
+                        }
+                        b"h1" | b"h2" | b"h3" | b"h4" | b"h5" | b"h6" => {
+                            if let Some(text) = heading.take() {
+                                toc.title = normalize_space(&text);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+
+        if !found {
+            return Err(super::Error::DeserializeErr(String::from(
+                "Invalid nav document:toc nav not found",
+            )));
+        }
+        toc.landmarks = decode_nav_links(xml, "landmarks")?
+            .into_iter()
+            .map(|(kind, url, title)| Landmark { kind, url, title })
+            .collect();
+        toc.page_list = decode_nav_links(xml, "page-list")?
+            .into_iter()
+            .map(|(_, url, name)| PageTarget { name, url })
+            .collect();
+        Ok(toc)
+    }
     /// Encode toc file
     pub fn encode_file(&mut self, ver: EpubVersion) -> Result<String, super::Error> {
         match ver {
@@ -46,18 +312,40 @@ impl TocNav {
 
     fn encode_ncx_file(&mut self) -> Result<String, super::Error> {
         let mut ncx = TocNCX::new(self.title.clone(), self.lang.clone());
+        // dtb:depth 按实际嵌套层数计算
+        let depth = self
+            .elements
+            .iter()
+            .map(TocElement::depth)
+            .max()
+            .unwrap_or(1);
+        self.metadata
+            .insert(String::from("dtb:depth"), depth.to_string());
         self.covert_meta_item(|meta| {
             ncx.head.meta.push(meta);
         });
+        ncx.doc_author = self
+            .authors
+            .iter()
+            .map(|author| Text {
+                text: author.clone(),
+            })
+            .collect();
 
-        for el in self.elements.iter_mut() {
-            ncx.nav_map.nav_point.push(NavPoint::from_toc_element(el));
+        let mut order = NcxOrder::new(&self.reading_order, &self.page_list);
+        ncx.nav_map.nav_point = self
+            .elements
+            .iter()
+            .filter_map(|el| NavPoint::from_toc_element(el, &mut order))
+            .collect();
+        let page_target = order.finish();
+        if !page_target.is_empty() {
+            ncx.page_list = Some(PageList { page_target });
         }
         let ret = super::encode_xml(&ncx)?;
         Ok(format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
-        <!DOCTYPE PUBLIC \"-//NISO//DTD ncx 2005-1//EN\"\n\"http://www.daisy.org/z3986/2005/ncx-2005-1.dtd\">\n{}",
+        <!DOCTYPE ncx PUBLIC \"-//NISO//DTD ncx 2005-1//EN\"\n\"http://www.daisy.org/z3986/2005/ncx-2005-1.dtd\">\n{}",
                     ret))
-
     }
 
     fn encode_nav_file(&mut self) -> Result<String, super::Error> {
@@ -66,33 +354,101 @@ impl TocNav {
             html.head.meta.push(meta);
         });
 
+        let mut nav_toc = NavToc::new(self.title.clone());
         for el in self.elements.iter() {
-            html.body.nav_toc.add_list(List::from_toc_element(el));
+            nav_toc.add_list(List::from_toc_element(el));
+        }
+        html.body.navs.push(nav_toc);
+        if !self.landmarks.is_empty() {
+            let mut landmarks = NavToc::hidden("landmarks", "Landmarks");
+            for landmark in self.landmarks.iter() {
+                let mut list = List::new(landmark.title.clone(), landmark.url.clone());
+                if let Some(anchor) = list.anchor.as_mut() {
+                    anchor.epub_type = Some(landmark.kind.clone());
+                }
+                landmarks.add_list(list);
+            }
+            html.body.navs.push(landmarks);
+        }
+        if !self.page_list.is_empty() {
+            let mut page_list = NavToc::hidden("page-list", "Page List");
+            for page in self.page_list.iter() {
+                page_list.add_list(List::new(page.name.clone(), page.url.clone()));
+            }
+            html.body.navs.push(page_list);
         }
         let ret = super::encode_xml(&html)?;
-        Ok(format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html>\n{}", ret))
+        Ok(format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html>\n{}",
+            ret
+        ))
     }
 
-    fn covert_meta_item<F>(&self, mut callback: F)
+    fn covert_meta_item<F>(&self, callback: F)
     where
         F: FnMut(MetaItem),
     {
-        self.metadata
+        // 按名称排序，保证输出稳定
+        let mut items: Vec<MetaItem> = self
+            .metadata
             .iter()
-            .enumerate()
-            .map(|(_, item)| MetaItem {
+            .map(|item| MetaItem {
                 name: String::from(item.key()),
                 content: String::from(item.value()),
             })
-            .for_each(|meta| callback(meta));
+            .collect();
+        items.sort_by(|a, b| a.name.cmp(&b.name));
+        items.into_iter().for_each(callback);
     }
 }
 
-#[derive(Debug, Default)]
+/// A landmark of the EPUB 3 navigation document
+#[derive(Debug, Clone, PartialEq)]
+pub struct Landmark {
+    /// Structural semantics, e.g. `cover`, `toc`, `bodymatter` or `copyright-page`
+    pub kind: String,
+    /// Link relative to the package document, e.g. `xhtml/cover.xhtml`
+    pub url: String,
+    pub title: String,
+}
+
+impl Landmark {
+    pub fn new<S1: Into<String>, S2: Into<String>, S3: Into<String>>(
+        kind: S1,
+        url: S2,
+        title: S3,
+    ) -> Self {
+        Landmark {
+            kind: kind.into(),
+            url: url.into(),
+            title: title.into(),
+        }
+    }
+}
+
+/// A print page of the page list
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageTarget {
+    /// The printed page number, e.g. `12` or `iv`
+    pub name: String,
+    /// Link to the `epub:type="pagebreak"` element, e.g. `xhtml/chapter_1.xhtml#page_12`
+    pub url: String,
+}
+
+impl PageTarget {
+    pub fn new<S1: Into<String>, S2: Into<String>>(name: S1, url: S2) -> Self {
+        PageTarget {
+            name: name.into(),
+            url: url.into(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct TocElement {
     /// 排序
     pub level: i32,
-    /// 链接地址
+    /// 链接地址，可带 `#fragment`；为空时作为分组标题，链接到第一个子节点
     pub url: String,
     /// 标题
     pub title: String,
@@ -139,6 +495,21 @@ impl TocElement {
         }
         true
     }
+    /// 节点的嵌套层数，叶子节点为 1
+    pub fn depth(&self) -> usize {
+        1 + self.childs.iter().map(TocElement::depth).max().unwrap_or(0)
+    }
+    /// 链接地址，分组标题使用第一个有链接的子节点
+    fn href(&self) -> &str {
+        if !self.url.is_empty() {
+            return &self.url;
+        }
+        self.childs
+            .iter()
+            .map(TocElement::href)
+            .find(|href| !href.is_empty())
+            .unwrap_or_default()
+    }
     /// 增加一个子节点
     pub fn add_child(&mut self, mut child: TocElement) -> &mut TocElement {
         if child.level <= self.level {
@@ -150,21 +521,25 @@ impl TocElement {
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
-#[serde(rename = "ncx")]
+#[serde(rename = "ncx", default)]
 struct TocNCX {
     #[serde(rename = "head")]
     head: Metadata,
     #[serde(rename = "docTitle")]
     doc_title: Text,
+    #[serde(rename = "docAuthor", skip_serializing_if = "Vec::is_empty")]
+    doc_author: Vec<Text>,
     /// This holds the body XML for the EPUB v2 TOC file (nav.xhtml).
     /// http://www.idpf.org/epub/20/spec/OPF_2.0.1_draft.htm#Section2.4.1
     #[serde(rename = "navMap")]
     nav_map: NavMap,
+    #[serde(rename = "pageList", skip_serializing_if = "Option::is_none")]
+    page_list: Option<PageList>,
     #[serde(rename = "@xmlns")]
     xmlns: String,
     #[serde(rename = "@version")]
     version: String,
-    #[serde(rename = "@xml:lang")]
+    #[serde(rename = "@xml:lang", alias = "@lang")]
     lang: String,
 }
 
@@ -173,12 +548,14 @@ impl TocNCX {
         TocNCX {
             head: Metadata { meta: Vec::new() },
             doc_title: Text { text: title },
+            doc_author: Vec::new(),
             nav_map: NavMap {
                 nav_point: Vec::new(),
             },
+            page_list: None,
             xmlns: String::from("http://www.daisy.org/z3986/2005/ncx/"),
             version: String::from("2005-1"),
-            lang: String::from(lang),
+            lang,
         }
     }
 }
@@ -190,7 +567,8 @@ struct Metadata {
     meta: Vec<MetaItem>,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 struct MetaItem {
     #[serde(rename = "@name")]
     name: String,
@@ -198,9 +576,60 @@ struct MetaItem {
     content: String,
 }
 
+/// NCX 的印刷页列表
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
-#[serde(rename = "navMap")]
+#[serde(default)]
+struct PageList {
+    #[serde(rename = "pageTarget")]
+    page_target: Vec<NcxPageTarget>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct NcxPageTarget {
+    #[serde(rename = "@id")]
+    id: String,
+    #[serde(rename = "@type")]
+    kind: String,
+    #[serde(rename = "@value", skip_serializing_if = "Option::is_none")]
+    value: Option<String>,
+    #[serde(rename = "@playOrder", skip_serializing_if = "Option::is_none")]
+    play_order: Option<i32>,
+    #[serde(rename = "navLabel")]
+    nav_label: Text,
+    #[serde(rename = "content")]
+    content: Content,
+}
+
+impl NcxPageTarget {
+    fn from_page(page: &PageTarget, index: usize, play_order: i32) -> Self {
+        // 阿拉伯数字为正文页，罗马数字为前言页，其余为特殊页
+        let (kind, value) = if let Ok(number) = page.name.parse::<u32>() {
+            ("normal", Some(number.to_string()))
+        } else if !page.name.is_empty() && page.name.chars().all(|c| "ivxlcdm".contains(c)) {
+            ("front", None)
+        } else {
+            ("special", None)
+        };
+        NcxPageTarget {
+            id: format!("pageTarget-{}", index),
+            kind: kind.to_string(),
+            value,
+            play_order: Some(play_order),
+            nav_label: Text {
+                text: page.name.clone(),
+            },
+            content: Content {
+                src: page.url.clone(),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename = "navMap", default)]
 struct NavMap {
+    #[serde(rename = "navPoint")]
     nav_point: Vec<NavPoint>,
 }
 
@@ -208,17 +637,23 @@ struct NavMap {
 impl NavMap {
     /// 将 TocElement 集合转换为epub v2版本的navMap结构
     fn from_toc_elements(elements: &[TocElement]) -> Self {
+        let mut order = NcxOrder::new(&[], &[]);
         NavMap {
-            nav_point: elements.iter().map(NavPoint::from_toc_element).collect(),
+            nav_point: elements
+                .iter()
+                .filter_map(|el| NavPoint::from_toc_element(el, &mut order))
+                .collect(),
         }
     }
 }
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
-#[serde(rename = "navPoint")]
+#[serde(rename = "navPoint", default)]
 struct NavPoint {
     #[serde(rename = "@id")]
     id: String,
+    #[serde(rename = "navLabel")]
     nav_label: Option<Text>,
+    #[serde(rename = "content")]
     content: Option<Content>,
     #[serde(rename = "navPoint")]
     nav_element: Vec<NavPoint>,
@@ -272,33 +707,156 @@ impl NavPoint {
         }
     }
 
+    /// 将navPoint转换为TocElement
+    fn to_toc_element(&self, level: i32) -> TocElement {
+        TocElement {
+            level,
+            url: self
+                .content
+                .as_ref()
+                .map(|c| c.src.clone())
+                .unwrap_or_default(),
+            title: self
+                .nav_label
+                .as_ref()
+                .map(|l| normalize_space(&l.text))
+                .unwrap_or_default(),
+            childs: self
+                .nav_element
+                .iter()
+                .map(|p| p.to_toc_element(level + 1))
+                .collect(),
+        }
+    }
+
     /// 将TocElement转换为epub v2版本的navPoint结构
-    fn from_toc_element(element: &TocElement) -> Self {
-        NavPoint {
-            id: format!("navPoint-{}", element.level),
+    ///
+    /// 按深度优先顺序编号，自身和所有子节点都没有链接时返回 `None`。
+    fn from_toc_element(element: &TocElement, order: &mut NcxOrder) -> Option<Self> {
+        let href = element.href();
+        if href.is_empty() {
+            return None;
+        }
+        order.count += 1;
+        let id = format!("navPoint-{}", order.count);
+        let play_order = order.nav_point(href);
+        Some(NavPoint {
+            id,
             nav_label: Option::from(Text {
                 text: element.title.clone(),
             }),
             content: Option::from(Content {
-                src: element.url.clone(),
+                src: href.to_string(),
             }),
             nav_element: element
                 .childs
                 .iter()
-                .map(NavPoint::from_toc_element)
+                .filter_map(|child| NavPoint::from_toc_element(child, order))
                 .collect(),
-            play_order: Option::from(element.level),
+            play_order: Option::from(play_order),
+        })
+    }
+}
+
+/// 按深度优先顺序分配 playOrder，指向同一目标的节点共用同一个值
+#[derive(Debug, Default)]
+struct PlayOrder {
+    orders: HashMap<String, i32>,
+}
+
+impl PlayOrder {
+    fn get(&mut self, src: &str) -> i32 {
+        let next = self.orders.len() as i32 + 1;
+        *self.orders.entry(src.to_string()).or_insert(next)
+    }
+}
+
+/// 在同一遍深度优先遍历中为 navPoint 和 pageTarget 编号
+///
+/// 印刷页按阅读顺序插在目录节点之间，不在阅读顺序中的印刷页排在最后。
+struct NcxOrder<'a> {
+    play_order: PlayOrder,
+    /// 已转换的 navPoint 数量，用于生成唯一的 id
+    count: usize,
+    positions: HashMap<&'a str, usize>,
+    /// 按阅读顺序排列的 (位置, 印刷页)
+    pages: Vec<(Option<usize>, &'a PageTarget)>,
+    next_page: usize,
+    page_targets: Vec<NcxPageTarget>,
+}
+
+impl<'a> NcxOrder<'a> {
+    fn new(reading_order: &'a [String], pages: &'a [PageTarget]) -> Self {
+        let mut positions = HashMap::new();
+        for (position, target) in reading_order.iter().enumerate() {
+            positions.entry(target.as_str()).or_insert(position);
+        }
+        let mut order = NcxOrder {
+            play_order: PlayOrder::default(),
+            count: 0,
+            positions,
+            pages: Vec::new(),
+            next_page: 0,
+            page_targets: Vec::new(),
+        };
+        order.pages = pages
+            .iter()
+            .map(|page| (order.position(&page.url), page))
+            .collect();
+        order
+            .pages
+            .sort_by_key(|(position, _)| (position.is_none(), *position));
+        order
+    }
+
+    /// 链接在阅读顺序中的位置，找不到片段时使用所在文档的位置
+    fn position(&self, href: &str) -> Option<usize> {
+        let file = href.split('#').next().unwrap_or_default();
+        self.positions
+            .get(href)
+            .or_else(|| self.positions.get(file))
+            .copied()
+    }
+
+    /// 先为阅读顺序在前的印刷页编号，再为目录节点编号
+    fn nav_point(&mut self, href: &str) -> i32 {
+        if let Some(position) = self.position(href) {
+            self.pages_before(Some(position));
+        }
+        self.play_order.get(href)
+    }
+
+    fn pages_before(&mut self, position: Option<usize>) {
+        while let Some(&(page_position, page)) = self.pages.get(self.next_page) {
+            match (page_position, position) {
+                (Some(page_position), Some(position)) if page_position >= position => break,
+                (None, Some(_)) => break,
+                _ => {}
+            }
+            let play_order = self.play_order.get(&page.url);
+            let index = self.page_targets.len() + 1;
+            self.page_targets
+                .push(NcxPageTarget::from_page(page, index, play_order));
+            self.next_page += 1;
         }
     }
+
+    /// 为剩余的印刷页编号，返回所有 pageTarget
+    fn finish(mut self) -> Vec<NcxPageTarget> {
+        self.pages_before(None);
+        self.page_targets
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 struct Text {
     #[serde(rename = "text", skip_serializing_if = "String::is_empty")]
     text: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 struct Content {
     #[serde(rename = "@src", skip_serializing_if = "String::is_empty")]
     src: String,
@@ -324,7 +882,7 @@ impl Html {
             xmlns: "http://www.w3.org/1999/xhtml".to_string(),
             xmlns_epub: "http://www.idpf.org/2007/ops".to_string(),
             head: Head::new(title_str.clone()),
-            body: Body::new(title_str),
+            body: Body::default(),
         }
     }
 }
@@ -350,15 +908,7 @@ impl Head {
 #[serde(rename = "body")]
 struct Body {
     #[serde(rename = "nav")]
-    nav_toc: NavToc,
-}
-
-impl Body {
-    fn new<S1: Into<String>>(title: S1) -> Self {
-        Body {
-            nav_toc: NavToc::new(title.into()),
-        }
-    }
+    navs: Vec<NavToc>,
 }
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 #[serde(rename = "nav")]
@@ -367,6 +917,8 @@ struct NavToc {
     epub_type: String,
     #[serde(rename = "@id")]
     id: String,
+    #[serde(rename = "@hidden", skip_serializing_if = "Option::is_none")]
+    hidden: Option<String>,
     #[serde(rename = "h1")]
     h1: H1,
     #[serde(rename = "ol")]
@@ -378,6 +930,7 @@ impl NavToc {
         NavToc {
             epub_type: String::from("toc"),
             id: String::from("toc"),
+            hidden: None,
             h1: H1 {
                 id: None,
                 text: title.into(),
@@ -386,6 +939,16 @@ impl NavToc {
         }
     }
 
+    /// 隐藏的导航，如 `landmarks` 和 `page-list`
+    fn hidden(epub_type: &str, title: &str) -> Self {
+        NavToc {
+            epub_type: epub_type.to_string(),
+            id: epub_type.to_string(),
+            hidden: Some(String::new()),
+            ..NavToc::new(title)
+        }
+    }
+
     fn add_list(&mut self, list: List) -> &mut Self {
         self.order_list.list.push(list);
         self
@@ -409,15 +972,18 @@ struct OrderList {
 impl OrderList {
     fn from_toc_elements(elements: &[TocElement]) -> Self {
         OrderList {
-            list: elements.iter().map(|e| List::from_toc_element(e)).collect(),
+            list: elements.iter().map(List::from_toc_element).collect(),
         }
     }
 }
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename = "li")]
 struct List {
-    #[serde(rename = "a")]
-    anchor: Anchor,
+    #[serde(rename = "a", skip_serializing_if = "Option::is_none")]
+    anchor: Option<Anchor>,
+    /// 没有链接的分组标题
+    #[serde(rename = "span", skip_serializing_if = "Option::is_none")]
+    span: Option<Span>,
     #[serde(rename = "ol", skip_serializing_if = "Option::is_none")]
     order_list: Option<OrderList>,
 }
@@ -426,20 +992,33 @@ struct List {
 impl List {
     fn new<S1: Into<String>, S2: Into<String>>(title: S1, href: S2) -> Self {
         List {
-            anchor: Anchor {
+            anchor: Some(Anchor {
+                epub_type: None,
                 href: href.into(),
                 text: title.into(),
-            },
+            }),
+            span: None,
             order_list: None,
         }
     }
 
     fn from_toc_element(element: &TocElement) -> Self {
-        List {
-            anchor: Anchor {
+        let (anchor, span) = if element.url.is_empty() {
+            let span = Span {
+                text: element.title.clone(),
+            };
+            (None, Some(span))
+        } else {
+            let anchor = Anchor {
+                epub_type: None,
                 href: element.url.clone(),
                 text: element.title.clone(),
-            },
+            };
+            (Some(anchor), None)
+        };
+        List {
+            anchor,
+            span,
             order_list: if element.childs.is_empty() {
                 None
             } else {
@@ -453,8 +1032,95 @@ impl List {
 #[allow(dead_code)]
 #[serde(rename = "a")]
 struct Anchor {
+    #[serde(rename = "@epub:type", skip_serializing_if = "Option::is_none")]
+    epub_type: Option<String>,
     #[serde(rename = "@href")]
     href: String,
     #[serde(rename = "$text")]
     text: String,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename = "span")]
+struct Span {
+    #[serde(rename = "$text")]
+    text: String,
+}
+
+/// 读取 `epub:type` 为 `nav_type` 的导航中的链接，返回 (链接的 epub:type, 地址, 文本)
+fn decode_nav_links(
+    xml: &str,
+    nav_type: &str,
+) -> Result<Vec<(String, String, String)>, super::Error> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().check_end_names = false;
+    let mut links = Vec::new();
+    let mut depth: Option<usize> = None;
+    let mut current: Option<(String, String, String)> = None;
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| super::Error::DeserializeErr(format!("Invalid nav document:{}", e)))?;
+        match event {
+            Event::Start(ref e) => match depth.as_mut() {
+                None => {
+                    let matched = attribute(e, b"epub:type")
+                        .is_some_and(|t| t.split_whitespace().any(|t| t == nav_type));
+                    if e.local_name().as_ref() == b"nav" && matched {
+                        depth = Some(0);
+                    }
+                }
+                Some(depth) => {
+                    *depth += 1;
+                    if e.local_name().as_ref() == b"a" {
+                        current = Some((
+                            attribute(e, b"epub:type").unwrap_or_default(),
+                            attribute(e, b"href").unwrap_or_default(),
+                            String::new(),
+                        ));
+                    }
+                }
+            },
+            Event::Text(ref e) => {
+                if let Some((_, _, ref mut text)) = current {
+                    text.push_str(&e.unescape().map(|t| t.into_owned()).unwrap_or_default());
+                }
+            }
+            Event::End(ref e) => match depth {
+                Some(0) => break,
+                Some(ref mut open) => {
+                    *open -= 1;
+                    if e.local_name().as_ref() == b"a" {
+                        if let Some((kind, href, text)) = current.take() {
+                            links.push((kind, href, normalize_space(&text)));
+                        }
+                    }
+                }
+                None => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(links)
+}
+
+/// 判断 nav 节点是否为目录导航 `epub:type="toc"`
+fn is_toc_nav(e: &BytesStart) -> bool {
+    attribute(e, b"epub:type")
+        .map(|t| t.split_whitespace().any(|t| t == "toc"))
+        .unwrap_or(false)
+}
+
+/// 读取节点属性值
+fn attribute(e: &BytesStart, name: &[u8]) -> Option<String> {
+    e.attributes()
+        .flatten()
+        .find(|a| a.key.as_ref() == name)
+        .and_then(|a| a.unescape_value().ok().map(|v| v.into_owned()))
+}
+
+/// 合并连续空白字符
+fn normalize_space(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
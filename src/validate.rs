@@ -0,0 +1,456 @@
+use crate::epub::{
+    EpubVersion, MEDIA_TYPE_EPUB, MEDIA_TYPE_NCX, MEDIA_TYPE_XHTML, MIMETYPE_FILENAME,
+};
+use crate::reader::Book;
+use crate::xhtml::check_document;
+use std::collections::HashSet;
+use std::fmt;
+
+/// 诊断的严重程度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// The book works in most reading systems but does not follow the specification
+    Warning,
+    /// The book violates the specification
+    Error,
+}
+
+/// The rule a diagnostic was raised for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DiagnosticKind {
+    /// `dc:identifier` is missing or `unique-identifier` does not reference it
+    MissingIdentifier,
+    /// An ISBN identifier has a wrong check digit
+    InvalidIdentifier,
+    /// `dc:title` is missing
+    MissingTitle,
+    /// `dc:language` is missing
+    MissingLanguage,
+    /// `dcterms:modified` is missing from an EPUB 3 package
+    MissingModified,
+    /// Two items of the package document share an id
+    DuplicateId,
+    /// A manifest item has no href or media type
+    InvalidManifestItem,
+    /// The spine is empty
+    EmptySpine,
+    /// A spine itemref does not reference a manifest item
+    UnknownSpineItem,
+    /// A manifest item appears more than once in the spine
+    DuplicateSpineItem,
+    /// A spine item is not an XHTML content document
+    NonContentSpineItem,
+    /// An XHTML content document is not in the spine
+    ContentNotInSpine,
+    /// A manifest item has no file in the container
+    MissingFile,
+    /// The `mimetype` file is missing, misplaced, compressed or has the wrong content
+    InvalidMimetype,
+    /// The EPUB 3 navigation document or the EPUB 2 NCX is missing or unreadable
+    MissingNavigation,
+    /// XHTML markup is not well-formed, e.g. an unclosed tag or an unescaped `&`
+    MalformedMarkup,
+    /// An entity such as `&nbsp;` is not declared
+    UndeclaredEntity,
+    /// A character is not allowed in XML
+    InvalidCharacter,
+    /// A link or resource reference does not resolve to a file of the book
+    BrokenLink,
+    /// The `#fragment` of a link does not match an element id of the target document
+    MissingFragment,
+    /// A media file is not referenced by any section or stylesheet
+    UnreferencedMedia,
+}
+
+/// Where a diagnostic was found
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Location {
+    /// Path inside the container, e.g. `EPUB/content.opf`
+    pub file: String,
+    /// Line number counting from 1, when known
+    pub line: Option<usize>,
+    /// Column number counting from 1, when known
+    pub column: Option<usize>,
+}
+
+impl Location {
+    /// A location covering a whole file
+    pub fn file<S: Into<String>>(file: S) -> Location {
+        Location {
+            file: file.into(),
+            line: None,
+            column: None,
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.file)?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+            if let Some(column) = self.column {
+                write!(f, ":{}", column)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A single validation finding
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub kind: DiagnosticKind,
+    pub location: Location,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new<S: Into<String>>(
+        severity: Severity,
+        kind: DiagnosticKind,
+        location: Location,
+        message: S,
+    ) -> Diagnostic {
+        Diagnostic {
+            severity,
+            kind,
+            location,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "WARNING",
+            Severity::Error => "ERROR",
+        };
+        write!(
+            f,
+            "{}({:?}) {}: {}",
+            severity, self.kind, self.location, self.message
+        )
+    }
+}
+
+/// The diagnostics of a validation run
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Report {
+    pub fn new() -> Report {
+        Report::default()
+    }
+    /// Add a diagnostic
+    pub fn push(&mut self, diagnostic: Diagnostic) -> &mut Self {
+        self.diagnostics.push(diagnostic);
+        self
+    }
+    /// All diagnostics in the order they were found
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+    /// Diagnostics with [`Severity::Error`]
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+    }
+    /// Diagnostics with [`Severity::Warning`]
+    pub fn warnings(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Warning)
+    }
+    /// Diagnostics raised for a rule
+    pub fn of_kind(&self, kind: DiagnosticKind) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter().filter(move |d| d.kind == kind)
+    }
+    /// `true` when there is no error, warnings are allowed
+    pub fn is_valid(&self) -> bool {
+        self.errors().next().is_none()
+    }
+
+    fn error<S: Into<String>>(&mut self, kind: DiagnosticKind, file: &str, message: S) {
+        self.push(Diagnostic::new(
+            Severity::Error,
+            kind,
+            Location::file(file),
+            message,
+        ));
+    }
+
+    fn warning<S: Into<String>>(&mut self, kind: DiagnosticKind, file: &str, message: S) {
+        self.push(Diagnostic::new(
+            Severity::Warning,
+            kind,
+            Location::file(file),
+            message,
+        ));
+    }
+}
+
+/// Validate an opened book
+///
+/// Use [`EpubBuilder::validate`](crate::epub::EpubBuilder::validate) for a book that is
+/// still being built.
+pub fn validate_book(book: &Book) -> Report {
+    let mut report = Report::new();
+    check_mimetype(book, &mut report);
+    check_metadata(book, &mut report);
+    check_ids(book, &mut report);
+    check_manifest(book, &mut report);
+    check_spine(book, &mut report);
+    check_navigation(book, &mut report);
+    check_documents(book, &mut report);
+    report
+}
+
+/// `mimetype` 必须是归档中第一个未压缩的条目，内容为 `application/epub+zip`
+fn check_mimetype(book: &Book, report: &mut Report) {
+    let kind = DiagnosticKind::InvalidMimetype;
+    match book.read_file(MIMETYPE_FILENAME) {
+        Ok(content) if content == MEDIA_TYPE_EPUB.as_bytes() => {}
+        Ok(_) => report.error(
+            kind,
+            MIMETYPE_FILENAME,
+            format!("The mimetype file must contain \"{}\"", MEDIA_TYPE_EPUB),
+        ),
+        Err(_) => {
+            report.error(kind, MIMETYPE_FILENAME, "The mimetype file is missing");
+            return;
+        }
+    }
+    if let Some((name, stored)) = book.first_entry() {
+        if name != MIMETYPE_FILENAME {
+            report.error(
+                kind,
+                MIMETYPE_FILENAME,
+                "The mimetype file must be the first entry of the archive",
+            );
+        } else if !stored {
+            report.error(
+                kind,
+                MIMETYPE_FILENAME,
+                "The mimetype file must not be compressed",
+            );
+        }
+    }
+}
+
+fn check_metadata(book: &Book, report: &mut Report) {
+    let package_path = book.package_path();
+    let metadata = book.metadata();
+    let unique_identifier = book.package().unique_identifier();
+    match metadata.identifier {
+        Some(ref identifier) if !identifier.text.trim().is_empty() => {
+            if unique_identifier.is_empty() || identifier.id != unique_identifier {
+                report.error(
+                    DiagnosticKind::MissingIdentifier,
+                    package_path,
+                    format!(
+                        "unique-identifier \"{}\" does not reference a dc:identifier",
+                        unique_identifier
+                    ),
+                );
+            }
+        }
+        _ => report.error(
+            DiagnosticKind::MissingIdentifier,
+            package_path,
+            "The package has no dc:identifier",
+        ),
+    }
+    for identifier in metadata
+        .identifier
+        .iter()
+        .chain(metadata.other_identifiers.iter())
+    {
+        if identifier.check().is_err() {
+            report.warning(
+                DiagnosticKind::InvalidIdentifier,
+                package_path,
+                format!("\"{}\" is not a valid ISBN", identifier.text),
+            );
+        }
+    }
+    if metadata.title.trim().is_empty() {
+        report.error(
+            DiagnosticKind::MissingTitle,
+            package_path,
+            "The package has no dc:title",
+        );
+    }
+    if metadata.language.trim().is_empty() {
+        report.error(
+            DiagnosticKind::MissingLanguage,
+            package_path,
+            "The package has no dc:language",
+        );
+    }
+    if book.version() == EpubVersion::V30 && metadata.date_modified.is_none() {
+        report.error(
+            DiagnosticKind::MissingModified,
+            package_path,
+            "EPUB 3 packages require a dcterms:modified meta",
+        );
+    }
+}
+
+/// 包文件中的 id 必须唯一
+fn check_ids(book: &Book, report: &mut Report) {
+    let package = book.package();
+    let metadata = package.metadata();
+    let ids = package
+        .manifest()
+        .iter()
+        .map(|m| m.id.as_str())
+        .chain(metadata.identifier.iter().map(|i| i.id.as_str()))
+        .chain(metadata.other_identifiers.iter().map(|i| i.id.as_str()))
+        .chain(metadata.meta().iter().map(|m| m.id.as_str()))
+        .filter(|id| !id.is_empty());
+    let mut seen = HashSet::new();
+    let mut reported = HashSet::new();
+    for id in ids {
+        if !seen.insert(id) && reported.insert(id) {
+            report.error(
+                DiagnosticKind::DuplicateId,
+                book.package_path(),
+                format!("Duplicate id \"{}\"", id),
+            );
+        }
+    }
+}
+
+fn check_manifest(book: &Book, report: &mut Report) {
+    let files: HashSet<String> = book.file_names().into_iter().collect();
+    for item in book.package().manifest() {
+        if item.href.is_empty() || item.media_type.is_empty() {
+            report.error(
+                DiagnosticKind::InvalidManifestItem,
+                book.package_path(),
+                format!(
+                    "Manifest item \"{}\" needs an href and a media-type",
+                    item.id
+                ),
+            );
+            continue;
+        }
+        // 远程资源不在容器中
+        if item.href.contains("://") {
+            continue;
+        }
+        let full_path = book.resolve_href(&item.href);
+        if !files.contains(&full_path) {
+            report.error(
+                DiagnosticKind::MissingFile,
+                &full_path,
+                format!("Manifest item \"{}\" references a missing file", item.id),
+            );
+        }
+    }
+}
+
+fn check_spine(book: &Book, report: &mut Report) {
+    let package_path = book.package_path();
+    let package = book.package();
+    if package.spine().is_empty() {
+        report.error(
+            DiagnosticKind::EmptySpine,
+            package_path,
+            "The spine has no itemref",
+        );
+    }
+    let mut seen = HashSet::new();
+    for itemref in package.spine() {
+        let Some(item) = package.manifest_item(&itemref.idref) else {
+            report.error(
+                DiagnosticKind::UnknownSpineItem,
+                package_path,
+                format!("Spine itemref \"{}\" is not in the manifest", itemref.idref),
+            );
+            continue;
+        };
+        if !seen.insert(itemref.idref.as_str()) {
+            report.error(
+                DiagnosticKind::DuplicateSpineItem,
+                package_path,
+                format!("Spine itemref \"{}\" appears more than once", itemref.idref),
+            );
+        }
+        if item.media_type != MEDIA_TYPE_XHTML && item.media_type != "image/svg+xml" {
+            report.warning(
+                DiagnosticKind::NonContentSpineItem,
+                package_path,
+                format!(
+                    "Spine item \"{}\" has media type {} instead of a content document",
+                    item.id, item.media_type
+                ),
+            );
+        }
+    }
+    for item in package.manifest() {
+        let is_nav = item.properties.split_whitespace().any(|p| p == "nav");
+        if item.media_type == MEDIA_TYPE_XHTML && !is_nav && !seen.contains(item.id.as_str()) {
+            report.warning(
+                DiagnosticKind::ContentNotInSpine,
+                &book.resolve_href(&item.href),
+                format!("Content document \"{}\" is not in the spine", item.id),
+            );
+        }
+    }
+}
+
+/// EPUB 3 需要导航文档，EPUB 2 需要 NCX
+fn check_navigation(book: &Book, report: &mut Report) {
+    let manifest = book.package().manifest();
+    let kind = DiagnosticKind::MissingNavigation;
+    let item = if book.version() == EpubVersion::V30 {
+        let nav = manifest
+            .iter()
+            .find(|m| m.properties.split_whitespace().any(|p| p == "nav"));
+        if nav.is_none() {
+            report.error(
+                kind,
+                book.package_path(),
+                "EPUB 3 packages require a navigation document",
+            );
+        }
+        nav
+    } else {
+        let ncx = manifest.iter().find(|m| m.media_type == MEDIA_TYPE_NCX);
+        if ncx.is_none() {
+            report.error(kind, book.package_path(), "EPUB 2 packages require an NCX");
+        }
+        ncx
+    };
+    if let Some(item) = item {
+        let full_path = book.resolve_href(&item.href);
+        // 缺失的文件已由 check_manifest 报告
+        if book.read_file(&full_path).is_ok() && book.toc().is_err() {
+            report.error(kind, &full_path, "The table of contents could not be read");
+        }
+    }
+}
+
+/// XHTML 内容文档必须格式正确
+fn check_documents(book: &Book, report: &mut Report) {
+    for item in book.package().manifest() {
+        if item.media_type != MEDIA_TYPE_XHTML {
+            continue;
+        }
+        let full_path = book.resolve_href(&item.href);
+        let Ok(xml) = book.read_file_to_string(&full_path) else {
+            continue;
+        };
+        for mut diagnostic in check_document(&xml) {
+            diagnostic.location.file = full_path.clone();
+            report.push(diagnostic);
+        }
+    }
+}
@@ -1,56 +1,274 @@
 use crate::{epub, Error};
-use dashmap::DashMap;
+use chrono::{Datelike, Timelike};
+use std::collections::HashMap;
+use std::fmt;
 use std::fs;
-use std::path::Path;
+use std::fs::File;
+use std::io::{Cursor, Seek, Write};
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, DateTime, ZipWriter};
 
-/// Create the folders for an epub
-pub(crate) fn create_epub_folders(root_dir: &Path) -> Result<(), Error> {
-    // Create the root folder
-    if let Err(e) = fs::create_dir_all(root_dir.join(epub::CONTENT_FOLDER_NAME)) {
-        return Err(Error::PathCreateErr(format!("Could not create content folder:{}", e)));
+/// A destination the files of an epub are written to.
+///
+/// Names are paths inside the container separated by `/`, e.g. `EPUB/content.opf`.
+/// [`EpubBuilder::write_to`](crate::epub::EpubBuilder::write_to) writes the `mimetype` file
+/// first, then every folder before the files it contains.
+pub trait Storage {
+    /// Create a folder, e.g. `META-INF`
+    fn add_folder(&mut self, name: &str) -> Result<(), Error>;
+    /// Write a file
+    fn write_file(&mut self, name: &str, content: &[u8]) -> Result<(), Error>;
+}
+
+/// Receives progress events while an epub is written.
+///
+/// Every method has an empty default implementation, implement the ones you need.
+pub trait Progress: Send {
+    /// A section is about to be written, `index` counts from 1
+    fn on_section(&mut self, _filename: &str, _index: usize, _total: usize) {}
+    /// A media file is about to be written, `index` counts from 1
+    fn on_media(&mut self, _name: &str, _index: usize, _total: usize) {}
+    /// A file has been written to the storage, `name` is its path inside the container
+    fn on_entry(&mut self, _name: &str, _size: usize) {}
+}
+
+/// 保存在构建器中的进度观察者
+pub(crate) struct ProgressObserver(pub(crate) Box<dyn Progress>);
+
+impl fmt::Debug for ProgressObserver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressObserver")
+    }
+}
+
+/// 记录日志并通知进度观察者的存储包装
+pub(crate) struct ObservedStorage<'a, S: Storage + ?Sized> {
+    storage: &'a mut S,
+    progress: Option<&'a mut dyn Progress>,
+}
+
+impl<'a, S: Storage + ?Sized> ObservedStorage<'a, S> {
+    pub(crate) fn new(storage: &'a mut S, progress: Option<&'a mut dyn Progress>) -> Self {
+        ObservedStorage { storage, progress }
+    }
+
+    pub(crate) fn on_section(&mut self, filename: &str, index: usize, total: usize) {
+        if let Some(progress) = self.progress.as_mut() {
+            progress.on_section(filename, index, total);
+        }
+    }
+
+    pub(crate) fn on_media(&mut self, name: &str, index: usize, total: usize) {
+        if let Some(progress) = self.progress.as_mut() {
+            progress.on_media(name, index, total);
+        }
+    }
+}
+
+impl<S: Storage + ?Sized> Storage for ObservedStorage<'_, S> {
+    fn add_folder(&mut self, name: &str) -> Result<(), Error> {
+        log::trace!("Creating folder: {}", name);
+        self.storage.add_folder(name)
     }
-    // Create the meta-inf folder
-    if let Err(e) = fs::create_dir_all(root_dir.join(epub::META_INF_FOLDER_NAME)) {
-        return Err(Error::PathCreateErr(format!("Could not create meta-inf folder:{}", e)));
+
+    fn write_file(&mut self, name: &str, content: &[u8]) -> Result<(), Error> {
+        log::debug!("Writing: {}", name);
+        self.storage.write_file(name, content)?;
+        if let Some(progress) = self.progress.as_mut() {
+            progress.on_entry(name, content.len());
+        }
+        Ok(())
     }
-    Ok(())
 }
 
-/// Create media folder
-pub(crate) fn create_media_folder(root_dir: &Path,media_folder_name:&str,hashmap :&mut DashMap<String,String>)
-    -> Result<(), Error> {
-    if !hashmap.is_empty() {
-        let media_folder_path = root_dir.join(epub::CONTENT_FOLDER_NAME).join(media_folder_name);
-        if let Err(e) = fs::create_dir_all(&media_folder_path) {
-            return Err(Error::PathCreateErr(format!("Could not create media folder:{}", e)));
+/// Writes the epub as an exploded folder tree
+#[derive(Debug)]
+pub struct DirectoryStorage {
+    root: PathBuf,
+}
+
+impl DirectoryStorage {
+    /// Write into `root`, the folder is created when missing
+    pub fn new<P: AsRef<Path>>(root: P) -> DirectoryStorage {
+        DirectoryStorage {
+            root: root.as_ref().to_path_buf(),
         }
     }
-    Ok(())
+    /// The root folder
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
 }
 
-/// write file
-pub(crate) fn write_file(output_path: &Path,content:&str) -> Result<(), Error> {
-    if let Err(e) = fs::write(output_path, content) {
-        return  Err(Error::PathCreateErr(format!("Could not create file:{}", e)))
+impl Storage for DirectoryStorage {
+    fn add_folder(&mut self, name: &str) -> Result<(), Error> {
+        if let Err(e) = fs::create_dir_all(self.root.join(name)) {
+            return Err(Error::PathCreateErr(format!(
+                "Could not create folder:{}",
+                e
+            )));
+        }
+        Ok(())
+    }
+
+    fn write_file(&mut self, name: &str, content: &[u8]) -> Result<(), Error> {
+        let path = self.root.join(name);
+        if let Some(parent) = path.parent() {
+            if let Err(e) = fs::create_dir_all(parent) {
+                return Err(Error::PathCreateErr(format!(
+                    "Could not create folder:{}",
+                    e
+                )));
+            }
+        }
+        if let Err(e) = fs::write(path, content) {
+            return Err(Error::PathCreateErr(format!("Could not create file:{}", e)));
+        }
+        Ok(())
     }
-    Ok(())
 }
 
-/// copy media file
-pub(crate) fn write_media_file (root_dir: &Path,media_folder_name:&str,hashmap :&DashMap<String,String>)
-    -> Result<(), Error> {
-    if !hashmap.is_empty() {
-        for item in hashmap.iter() {
-            let media_folder_path = root_dir.
-                join(epub::CONTENT_FOLDER_NAME).
-                join(media_folder_name).
-                join(item.key());
-            let source_path = Path::new(item.value());
-            if let Err(e) = fs::copy(source_path,media_folder_path) {
-                return  Err(Error::PathCreateErr(format!("Could not copy file:{}", e)))
+/// Writes the epub as a `.epub` zip archive.
+///
+/// The `mimetype` entry is stored uncompressed as required by the OCF specification, every
+/// other file is deflated. Every entry carries the same timestamp, 1980-01-01 00:00:00 unless
+/// set with [`ZipStorage::set_last_modified`], so identical input gives identical bytes.
+/// Call [`ZipStorage::finish`] to write the central directory.
+pub struct ZipStorage<W: Write + Seek> {
+    zip: ZipWriter<W>,
+    last_modified: DateTime,
+}
+
+impl ZipStorage<File> {
+    /// Create the archive file, truncating any existing file at `output_file`
+    pub fn create<P: AsRef<Path>>(output_file: P) -> Result<Self, Error> {
+        let output_file = output_file.as_ref();
+        if let Some(parent) = output_file.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent).map_err(|e| {
+                    Error::PathCreateErr(format!("Could not create archive folder:{}", e))
+                })?;
             }
         }
+        let file = File::create(output_file)
+            .map_err(|e| Error::PathCreateErr(format!("Could not create archive:{}", e)))?;
+        Ok(ZipStorage::new(file))
     }
-    Ok(())
+}
 
- }
+impl ZipStorage<Cursor<Vec<u8>>> {
+    /// Write the archive into memory, [`ZipStorage::finish`] returns the bytes in a cursor
+    pub fn in_memory() -> Self {
+        ZipStorage::new(Cursor::new(Vec::new()))
+    }
+}
+
+impl<W: Write + Seek> ZipStorage<W> {
+    /// Write the archive into any seekable writer
+    pub fn new(writer: W) -> Self {
+        ZipStorage {
+            zip: ZipWriter::new(writer),
+            last_modified: DateTime::default(),
+        }
+    }
+
+    /// Set the timestamp of the entries written afterwards
+    ///
+    /// Zip timestamps have a two second resolution and cover 1980 to 2107, times outside
+    /// that range are clamped.
+    pub fn set_last_modified(&mut self, time: chrono::DateTime<chrono::Utc>) -> &mut Self {
+        let time = time.naive_utc();
+        self.last_modified = match time.year() {
+            ..=1979 => DateTime::default(),
+            2108.. => DateTime::from_date_and_time(2107, 12, 31, 23, 59, 58).unwrap_or_default(),
+            year => DateTime::from_date_and_time(
+                year as u16,
+                time.month() as u8,
+                time.day() as u8,
+                time.hour() as u8,
+                time.minute() as u8,
+                time.second() as u8,
+            )
+            .unwrap_or_default(),
+        };
+        self
+    }
+
+    fn options(&self, method: CompressionMethod) -> SimpleFileOptions {
+        SimpleFileOptions::default()
+            .compression_method(method)
+            .last_modified_time(self.last_modified)
+    }
+
+    /// Write the central directory and return the underlying writer
+    pub fn finish(self) -> Result<W, Error> {
+        self.zip.finish().map_err(archive_error)
+    }
+}
+
+impl<W: Write + Seek> Storage for ZipStorage<W> {
+    fn add_folder(&mut self, name: &str) -> Result<(), Error> {
+        let options = self.options(CompressionMethod::Stored);
+        self.zip.add_directory(name, options).map_err(archive_error)
+    }
+
+    fn write_file(&mut self, name: &str, content: &[u8]) -> Result<(), Error> {
+        let method = if name == epub::MIMETYPE_FILENAME {
+            CompressionMethod::Stored
+        } else {
+            CompressionMethod::Deflated
+        };
+        let options = self.options(method);
+        self.zip.start_file(name, options).map_err(archive_error)?;
+        self.zip.write_all(content)?;
+        Ok(())
+    }
+}
+
+/// Keeps every file of the epub in memory
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    files: HashMap<String, Vec<u8>>,
+    folders: Vec<String>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        MemoryStorage::default()
+    }
+    /// Get a file by its path inside the container
+    pub fn file(&self, name: &str) -> Option<&[u8]> {
+        self.files.get(name).map(Vec::as_slice)
+    }
+    /// All written files
+    pub fn files(&self) -> &HashMap<String, Vec<u8>> {
+        &self.files
+    }
+    /// All created folders, in creation order
+    pub fn folders(&self) -> &[String] {
+        &self.folders
+    }
+    /// Take the written files
+    pub fn into_files(self) -> HashMap<String, Vec<u8>> {
+        self.files
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn add_folder(&mut self, name: &str) -> Result<(), Error> {
+        if !self.folders.iter().any(|folder| folder == name) {
+            self.folders.push(name.to_string());
+        }
+        Ok(())
+    }
+
+    fn write_file(&mut self, name: &str, content: &[u8]) -> Result<(), Error> {
+        self.files.insert(name.to_string(), content.to_vec());
+        Ok(())
+    }
+}
+
+fn archive_error(e: zip::result::ZipError) -> Error {
+    Error::ArchiveErr(format!("Could not write archive:{}", e))
+}
@@ -1,4 +1,9 @@
+use crate::markup;
+use crate::validate::{Diagnostic, Location};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename = "html")]
@@ -52,6 +57,197 @@ impl XHtmlRoot {
             .push(StyleContent::new(style, String::from("text/css")));
         self
     }
+    /// Get the body markup
+    pub fn body(&self) -> &str {
+        &self.body.content
+    }
+    /// Get the title
+    pub fn title(&self) -> &str {
+        &self.head.title.text
+    }
+    /// Get the links in the head
+    pub fn links(&self) -> &[XHtmlLinkItem] {
+        &self.head.link
+    }
+
+    /// 改写正文、样式链接和内联样式中引用的资源地址
+    pub(crate) fn relink<F>(&mut self, mut rewrite: F)
+    where
+        F: FnMut(&str) -> Option<String>,
+    {
+        self.body.content = rewrite_references(&self.body.content, &mut rewrite);
+        for markup in self.head.extra.iter_mut() {
+            *markup = rewrite_references(markup, &mut rewrite);
+        }
+        for link in self.head.link.iter_mut() {
+            if let Some(href) = rewrite(&link.href) {
+                link.href = href;
+            }
+        }
+        for style in self.head.style_content.iter_mut() {
+            if let Some(value) = style.value.as_mut() {
+                *value = rewrite_css_urls(value, &mut rewrite);
+            }
+        }
+    }
+
+    /// 正文、样式链接和内联样式中引用的地址，正文中的位置相对于正文
+    pub(crate) fn references(&self, file: &str) -> Vec<(Location, String)> {
+        let mut references = markup::references(&self.body.content);
+        for (location, _) in references.iter_mut() {
+            location.file = file.to_string();
+        }
+        for link in self.head.link.iter() {
+            references.push((Location::file(file), link.href.clone()));
+        }
+        for (_, href) in self
+            .head
+            .extra
+            .iter()
+            .flat_map(|markup| markup::references(markup))
+        {
+            references.push((Location::file(file), href));
+        }
+        for style in self.head.style_content.iter() {
+            if let Some(value) = style.value.as_ref() {
+                for url in css_references(value) {
+                    references.push((Location::file(file), url));
+                }
+            }
+        }
+        references
+    }
+
+    /// 是否包含脚本，EPUB 3 中需要 `scripted` 属性
+    pub(crate) fn is_scripted(&self) -> bool {
+        self.head
+            .extra
+            .iter()
+            .any(|markup| markup.contains("<script"))
+            || self.body.content.contains("<script")
+    }
+
+    /// 正文中所有元素的 `id`
+    pub(crate) fn element_ids(&self) -> HashSet<String> {
+        markup::element_ids(&self.body.content)
+    }
+
+    /// Decode an XHTML document, the body is kept as raw markup
+    pub fn decode_xml(xml: &str) -> Result<XHtmlRoot, super::Error> {
+        let mut reader = Reader::from_str(xml);
+        reader.config_mut().check_end_names = false;
+
+        let mut root = XHtmlRoot::default();
+        root.head.title.dir = None;
+        root.body.dir = None;
+        let mut in_title = false;
+        let mut style: Option<(Option<String>, usize)> = None;
+        let mut body: Option<(usize, usize)> = None;
+        // head 中其他元素（meta、script 等）的起始位置和嵌套深度
+        let mut extra: Option<(usize, usize)> = None;
+        let mut in_head = false;
+
+        loop {
+            let position = reader.buffer_position() as usize;
+            let event = reader.read_event().map_err(|e| {
+                super::Error::DeserializeErr(format!("Invalid xhtml document:{}", e))
+            })?;
+            if let Some((start, ref mut depth)) = body {
+                match event {
+                    Event::Start(_) => *depth += 1,
+                    Event::End(_) if *depth > 0 => *depth -= 1,
+                    Event::End(_) => {
+                        root.body.content = xml[start..position].to_string();
+                        body = None;
+                    }
+                    Event::Eof => {
+                        root.body.content = xml[start..].to_string();
+                        break;
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            if let Some((start, ref mut depth)) = extra {
+                match event {
+                    Event::Start(_) => *depth += 1,
+                    Event::End(_) if *depth > 0 => *depth -= 1,
+                    Event::End(_) => {
+                        let end = reader.buffer_position() as usize;
+                        root.head.extra.push(xml[start..end].to_string());
+                        extra = None;
+                    }
+                    Event::Eof => break,
+                    _ => {}
+                }
+                continue;
+            }
+            match event {
+                Event::Start(ref e) | Event::Empty(ref e) => {
+                    let is_empty = matches!(event, Event::Empty(_));
+                    match e.local_name().as_ref() {
+                        b"head" => in_head = !is_empty,
+                        b"html" => {
+                            if let Some(lang) = attribute(e, b"xml:lang").or(attribute(e, b"lang"))
+                            {
+                                root.head.lang = lang;
+                            }
+                        }
+                        b"title" if !is_empty => in_title = true,
+                        b"title" => {}
+                        b"link" => root.head.link.push(XHtmlLinkItem {
+                            href: attribute(e, b"href").unwrap_or_default(),
+                            rel: attribute(e, b"rel"),
+                            link_type: attribute(e, b"type"),
+                        }),
+                        b"style" if !is_empty => {
+                            style =
+                                Some((attribute(e, b"type"), reader.buffer_position() as usize));
+                        }
+                        b"body" => {
+                            in_head = false;
+                            root.body.dir = attribute(e, b"dir");
+                            root.body.attributes = raw_attributes(e, &[b"dir"]);
+                            if !is_empty {
+                                body = Some((reader.buffer_position() as usize, 0));
+                            }
+                        }
+                        _ if in_head && is_empty => {
+                            let end = reader.buffer_position() as usize;
+                            root.head.extra.push(xml[position..end].to_string());
+                        }
+                        _ if in_head => extra = Some((position, 0)),
+                        _ => {}
+                    }
+                }
+                Event::Text(ref e) if in_title => {
+                    let text = e
+                        .unescape()
+                        .map(|t| t.into_owned())
+                        .unwrap_or_else(|_| String::from_utf8_lossy(e).into_owned());
+                    root.head.title.text.push_str(&text);
+                }
+                Event::End(ref e) => match e.local_name().as_ref() {
+                    b"head" => in_head = false,
+                    b"title" => in_title = false,
+                    b"style" => {
+                        if let Some((style_type, start)) = style.take() {
+                            root.head.style_content.push(StyleContent {
+                                style_type,
+                                value: Some(unescape_text(&xml[start..position])),
+                            });
+                        }
+                    }
+                    _ => {}
+                },
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        root.head.title.text = root.head.title.text.trim().to_string();
+        Ok(root)
+    }
+
     /// Encode the XML string
     pub fn encode_xml(&mut self) -> Result<String, super::Error> {
         let body = self.body.content.clone();
@@ -59,7 +255,20 @@ impl XHtmlRoot {
         let ret = super::encode_xml(self);
 
         match ret {
-            Ok(s) => {
+            Ok(mut s) => {
+                // 原文档中的 body 属性和 head 中的其他元素按原样写回
+                if !self.body.attributes.is_empty() {
+                    s = s.replacen("<body", &format!("<body{}", self.body.attributes), 1);
+                }
+                if !self.head.extra.is_empty() {
+                    let extra: String = self
+                        .head
+                        .extra
+                        .iter()
+                        .map(|markup| format!("  {}\n  ", markup))
+                        .collect();
+                    s = s.replacen("</head>", &format!("{}</head>", extra), 1);
+                }
                 let xml_str = format!(
                     "<?xml version=\"1.0\" encoding=\"UTF-8\" ?>\n<!DOCTYPE html>\n{}",
                     s.replace("{body}", &body)
@@ -83,6 +292,9 @@ struct XHtmlHead {
     link: Vec<XHtmlLinkItem>,
     #[serde(rename = "style", skip_serializing_if = "Vec::is_empty")]
     style_content: Vec<StyleContent>,
+    /// 其他元素的原始标记，如 meta 和 script
+    #[serde(skip)]
+    extra: Vec<String>,
 }
 
 impl Default for XHtmlHead {
@@ -95,11 +307,12 @@ impl Default for XHtmlHead {
             },
             link: Vec::new(),
             style_content: Vec::new(),
+            extra: Vec::new(),
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct StyleContent {
     #[serde(rename = "@type", skip_serializing_if = "is_none_or_empty")]
     style_type: Option<String>,
@@ -107,15 +320,6 @@ pub struct StyleContent {
     value: Option<String>,
 }
 
-impl Default for StyleContent {
-    fn default() -> Self {
-        StyleContent {
-            style_type: None,
-            value: None,
-        }
-    }
-}
-
 impl StyleContent {
     pub fn new<S1: Into<String>, S2: Into<String>>(value: S1, style_type: S2) -> Self {
         StyleContent {
@@ -228,6 +432,15 @@ impl Default for XHtmlLinkItem {
 }
 
 impl XHtmlLinkItem {
+    /// Get the href
+    pub fn href(&self) -> &str {
+        &self.href
+    }
+    /// Set the href
+    pub fn set_href<S: Into<String>>(&mut self, href: S) -> &mut Self {
+        self.href = href.into();
+        self
+    }
     pub fn new<S1: Into<String>, S2: Into<String>>(
         href: S1,
         link_type: S2,
@@ -247,6 +460,9 @@ struct XHtmlBody {
     content: String,
     #[serde(rename = "@dir", skip_serializing_if = "Option::is_none")]
     dir: Option<String>,
+    /// 除 `dir` 外的原始属性，如 ` class="x"`
+    #[serde(skip)]
+    attributes: String,
 }
 
 impl Default for XHtmlBody {
@@ -259,6 +475,7 @@ impl XHtmlBody {
         XHtmlBody {
             content: content.into(),
             dir: Some(String::from("auto")),
+            attributes: String::new(),
         }
     }
 }
@@ -267,3 +484,471 @@ impl XHtmlBody {
 fn is_none_or_empty(value: &Option<String>) -> bool {
     value.is_none() || value.as_ref().unwrap().trim().is_empty()
 }
+
+/// 解码文本中的实体，无法识别时保留原文
+fn unescape_text(text: &str) -> String {
+    quick_xml::escape::unescape(text)
+        .map(|t| t.into_owned())
+        .unwrap_or_else(|_| text.to_string())
+}
+
+/// Check that a section body is a well-formed XML fragment
+///
+/// Unclosed or mismatched tags, undeclared entities such as `&nbsp;`, unescaped `&` and `<`,
+/// unquoted attributes and characters not allowed in XML are reported with line and column
+/// positions relative to the body. The file of each location is left empty.
+pub fn check_body(body: &str) -> Vec<Diagnostic> {
+    markup::check(body, false)
+}
+
+/// Check that a complete XHTML document is well-formed
+///
+/// HTML named entities are accepted when the document declares an XHTML 1.x DTD.
+pub fn check_document(xml: &str) -> Vec<Diagnostic> {
+    markup::check(xml, xml.contains("//DTD XHTML"))
+}
+
+/// Repair common well-formedness problems of a section body
+///
+/// Unclosed tags are closed, stray end tags dropped, void elements such as `<br>`
+/// self-closed, HTML named entities replaced by numeric character references, unescaped `&`
+/// and `<` escaped, attribute values quoted and characters not allowed in XML removed.
+pub fn repair_body(body: &str) -> String {
+    markup::repair(body)
+}
+
+/// 默认允许的元素
+const ALLOWED_ELEMENTS: [&str; 72] = [
+    "a",
+    "abbr",
+    "address",
+    "article",
+    "aside",
+    "audio",
+    "b",
+    "bdi",
+    "bdo",
+    "blockquote",
+    "br",
+    "caption",
+    "cite",
+    "code",
+    "col",
+    "colgroup",
+    "dd",
+    "del",
+    "details",
+    "dfn",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "figcaption",
+    "figure",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "i",
+    "img",
+    "ins",
+    "kbd",
+    "li",
+    "mark",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "q",
+    "rp",
+    "rt",
+    "ruby",
+    "s",
+    "samp",
+    "section",
+    "small",
+    "source",
+    "span",
+    "strong",
+    "sub",
+    "summary",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "time",
+    "tr",
+    "track",
+    "u",
+    "ul",
+    "var",
+    "video",
+];
+
+/// 默认允许的属性，`*` 表示所有元素
+const ALLOWED_ATTRIBUTES: [(&str, &str); 37] = [
+    ("*", "id"),
+    ("*", "class"),
+    ("*", "title"),
+    ("*", "lang"),
+    ("*", "xml:lang"),
+    ("*", "dir"),
+    ("*", "epub:type"),
+    ("a", "href"),
+    ("img", "src"),
+    ("img", "alt"),
+    ("img", "width"),
+    ("img", "height"),
+    ("td", "colspan"),
+    ("td", "rowspan"),
+    ("th", "colspan"),
+    ("th", "rowspan"),
+    ("th", "scope"),
+    ("col", "span"),
+    ("colgroup", "span"),
+    ("ol", "start"),
+    ("ol", "reversed"),
+    ("li", "value"),
+    ("blockquote", "cite"),
+    ("q", "cite"),
+    ("del", "cite"),
+    ("ins", "cite"),
+    ("time", "datetime"),
+    ("audio", "src"),
+    ("audio", "controls"),
+    ("video", "src"),
+    ("video", "controls"),
+    ("video", "poster"),
+    ("source", "src"),
+    ("source", "type"),
+    ("track", "src"),
+    ("track", "kind"),
+    ("track", "srclang"),
+];
+
+/// 默认连同内容一起删除的元素
+const REMOVED_ELEMENTS: [&str; 16] = [
+    "applet", "button", "canvas", "embed", "form", "frameset", "head", "iframe", "noframes",
+    "noscript", "object", "script", "select", "style", "template", "textarea",
+];
+
+/// Converts loose HTML into body markup that is well-formed XHTML.
+///
+/// Elements outside the allowlist are unwrapped, keeping their content, e.g. `<font>` or
+/// `<center>`. Elements such as `<script>`, `<style>` and `<iframe>` are removed together with
+/// their content. Attributes outside the allowlist, including every `on*` event handler, are
+/// dropped, so are `javascript:` links. Tags and attribute names are lower-cased, void elements
+/// self-closed, implied end tags such as `</p>` and `</li>` inserted, named entities replaced
+/// by numeric character references and comments removed.
+///
+/// ```
+/// use rust_epub::xhtml::Sanitizer;
+///
+/// let body = Sanitizer::new().sanitize("<P onclick=go()>A&nbsp;<font color=red>B</font><br>");
+/// assert_eq!(body, "<p>A&#160;B<br/></p>");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Sanitizer {
+    elements: HashSet<String>,
+    attributes: HashMap<String, HashSet<String>>,
+    removed: HashSet<String>,
+}
+
+impl Default for Sanitizer {
+    fn default() -> Self {
+        let mut sanitizer = Sanitizer {
+            elements: HashSet::new(),
+            attributes: HashMap::new(),
+            removed: REMOVED_ELEMENTS.iter().map(|e| e.to_string()).collect(),
+        };
+        for element in ALLOWED_ELEMENTS {
+            sanitizer.allow_element(element);
+        }
+        for (element, attribute) in ALLOWED_ATTRIBUTES {
+            sanitizer.allow_attribute(element, attribute);
+        }
+        sanitizer
+    }
+}
+
+impl Sanitizer {
+    /// A sanitizer with the default allowlist
+    pub fn new() -> Sanitizer {
+        Sanitizer::default()
+    }
+    /// A sanitizer that allows no element, every tag is unwrapped
+    pub fn empty() -> Sanitizer {
+        Sanitizer {
+            elements: HashSet::new(),
+            attributes: HashMap::new(),
+            removed: REMOVED_ELEMENTS.iter().map(|e| e.to_string()).collect(),
+        }
+    }
+    /// Allow an element
+    pub fn allow_element<S: Into<String>>(&mut self, element: S) -> &mut Self {
+        let element = element.into().to_ascii_lowercase();
+        self.removed.remove(&element);
+        self.elements.insert(element);
+        self
+    }
+    /// Unwrap an element, its content is kept
+    pub fn deny_element(&mut self, element: &str) -> &mut Self {
+        self.elements.remove(&element.to_ascii_lowercase());
+        self
+    }
+    /// Remove an element together with its content
+    pub fn remove_element<S: Into<String>>(&mut self, element: S) -> &mut Self {
+        let element = element.into().to_ascii_lowercase();
+        self.elements.remove(&element);
+        self.removed.insert(element);
+        self
+    }
+    /// Allow an attribute on an element, `*` allows it on every element
+    pub fn allow_attribute<S1: Into<String>, S2: Into<String>>(
+        &mut self,
+        element: S1,
+        attribute: S2,
+    ) -> &mut Self {
+        self.attributes
+            .entry(element.into().to_ascii_lowercase())
+            .or_default()
+            .insert(attribute.into().to_ascii_lowercase());
+        self
+    }
+    /// Drop an attribute from an element, `*` drops the attributes allowed on every element
+    pub fn deny_attribute(&mut self, element: &str, attribute: &str) -> &mut Self {
+        if let Some(attributes) = self.attributes.get_mut(&element.to_ascii_lowercase()) {
+            attributes.remove(&attribute.to_ascii_lowercase());
+        }
+        self
+    }
+
+    /// Convert `html` into body markup for [`XHtmlRoot::set_body`]
+    ///
+    /// A complete document may be given, its `<head>` is removed and the content of
+    /// `<html>` and `<body>` kept.
+    pub fn sanitize(&self, html: &str) -> String {
+        markup::sanitize(html, self)
+    }
+
+    pub(crate) fn allows_element(&self, element: &str) -> bool {
+        self.elements.contains(element)
+    }
+
+    pub(crate) fn removes_element(&self, element: &str) -> bool {
+        self.removed.contains(element)
+    }
+
+    pub(crate) fn allows_attribute(&self, element: &str, attribute: &str) -> bool {
+        [element, "*"].iter().any(|key| {
+            self.attributes
+                .get(*key)
+                .is_some_and(|attributes| attributes.contains(attribute))
+        })
+    }
+}
+
+/// Convert loose HTML into XHTML body markup with the default [`Sanitizer`]
+pub fn sanitize_html(html: &str) -> String {
+    Sanitizer::default().sanitize(html)
+}
+
+/// 读取节点属性值
+fn attribute(e: &BytesStart, name: &[u8]) -> Option<String> {
+    e.attributes()
+        .flatten()
+        .find(|a| a.key.as_ref() == name)
+        .and_then(|a| a.unescape_value().ok().map(|v| v.into_owned()))
+}
+
+/// 除 `skip` 外的属性，保持原值的转义，如 ` class="x"`
+fn raw_attributes(e: &BytesStart, skip: &[&[u8]]) -> String {
+    let mut attributes = String::new();
+    for a in e.attributes().with_checks(false).flatten() {
+        if skip.contains(&a.key.as_ref()) {
+            continue;
+        }
+        let value = String::from_utf8_lossy(&a.value).replace('"', "&quot;");
+        attributes.push_str(&format!(
+            " {}=\"{}\"",
+            String::from_utf8_lossy(a.key.as_ref()),
+            value
+        ));
+    }
+    attributes
+}
+
+/// 引用其他资源的属性
+pub(crate) const REFERENCE_ATTRIBUTES: [&str; 4] = ["href", "src", "xlink:href", "poster"];
+
+/// 改写标记中 `href`、`src`、`xlink:href`、`poster` 属性的值
+///
+/// `rewrite` 返回 `None` 时保留原值，其余内容原样输出。
+pub(crate) fn rewrite_references<F>(markup: &str, mut rewrite: F) -> String
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    let mut output = String::with_capacity(markup.len());
+    let mut rest = markup;
+    while let Some(start) = rest.find('<') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = if rest.starts_with("<!--") {
+            rest.find("-->").map_or(rest.len(), |i| i + 3)
+        } else if rest.starts_with("<![CDATA[") {
+            rest.find("]]>").map_or(rest.len(), |i| i + 3)
+        } else {
+            tag_end(rest)
+        };
+        let tag = &rest[..end];
+        if tag.starts_with("<!") || tag.starts_with("<?") || tag.starts_with("</") {
+            output.push_str(tag);
+        } else {
+            output.push_str(&rewrite_tag(tag, &mut rewrite));
+        }
+        rest = &rest[end..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// 标签结束位置（`>` 之后），忽略引号中的 `>`
+fn tag_end(tag: &str) -> usize {
+    let mut quote = None;
+    for (index, c) in tag.char_indices() {
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, '>') => return index + 1,
+            _ => {}
+        }
+    }
+    tag.len()
+}
+
+fn rewrite_tag<F>(tag: &str, rewrite: &mut F) -> String
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    let mut output = String::with_capacity(tag.len());
+    let mut rest = tag;
+    // 依次查找 name=value
+    while let Some(eq) = rest.find('=') {
+        let name_part = &rest[..eq];
+        let name = name_part
+            .trim_end()
+            .rsplit(|c: char| c.is_whitespace())
+            .next()
+            .unwrap_or_default();
+        let after = &rest[eq + 1..];
+        let value_offset = after.len() - after.trim_start().len();
+        let after = after.trim_start();
+        let (value, quote, consumed) = match after.chars().next() {
+            // 没有结束引号时取到末尾
+            Some(q @ ('"' | '\'')) => match after[1..].find(q) {
+                Some(end) => (&after[1..end + 1], Some(q), end + 2),
+                None => (&after[1..], Some(q), after.len()),
+            },
+            _ => {
+                let end = after
+                    .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+                    .unwrap_or(after.len());
+                (&after[..end], None, end)
+            }
+        };
+        output.push_str(&rest[..eq + 1 + value_offset]);
+        let new_value = if REFERENCE_ATTRIBUTES.contains(&name.to_ascii_lowercase().as_str()) {
+            rewrite(value)
+        } else {
+            None
+        };
+        match (new_value, quote) {
+            (Some(new_value), Some(q)) => {
+                let escaped = new_value
+                    .replace('&', "&amp;")
+                    .replace(q, if q == '"' { "&quot;" } else { "&apos;" });
+                output.push(q);
+                output.push_str(&escaped);
+                output.push(q);
+            }
+            (Some(new_value), None) => {
+                output.push('"');
+                output.push_str(&new_value.replace('&', "&amp;").replace('"', "&quot;"));
+                output.push('"');
+            }
+            (None, _) => output.push_str(&after[..consumed]),
+        }
+        rest = &after[consumed..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// 改写样式表中 `url(...)` 和 `@import "..."` 引用的地址
+pub(crate) fn rewrite_css_urls<F>(css: &str, mut rewrite: F) -> String
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    let mut output = String::with_capacity(css.len());
+    let mut rest = css;
+    loop {
+        let url = rest.find("url(");
+        let import = rest.find("@import");
+        let (start, is_url) = match (url, import) {
+            (Some(u), Some(i)) if i < u => (i, false),
+            (Some(u), _) => (u, true),
+            (None, Some(i)) => (i, false),
+            (None, None) => break,
+        };
+        let prefix_len = if is_url { 4 } else { 7 };
+        output.push_str(&rest[..start + prefix_len]);
+        rest = &rest[start + prefix_len..];
+
+        let leading = rest.len() - rest.trim_start().len();
+        output.push_str(&rest[..leading]);
+        rest = &rest[leading..];
+        let (value, quote, consumed) = match rest.chars().next() {
+            // 没有结束引号时取到末尾
+            Some(q @ ('"' | '\'')) => match rest[1..].find(q) {
+                Some(end) => (&rest[1..end + 1], Some(q), end + 2),
+                None => (&rest[1..], Some(q), rest.len()),
+            },
+            // `@import url(...)` 交给下一轮处理
+            _ if !is_url => continue,
+            _ => {
+                let end = rest.find(')').unwrap_or(rest.len());
+                (rest[..end].trim_end(), None, rest[..end].trim_end().len())
+            }
+        };
+        match (rewrite(value), quote) {
+            (Some(new_value), Some(q)) => {
+                output.push(q);
+                output.push_str(&new_value);
+                output.push(q);
+            }
+            (Some(new_value), None) => output.push_str(&new_value),
+            (None, _) => output.push_str(&rest[..consumed]),
+        }
+        rest = &rest[consumed..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// 样式表中 `url(...)` 和 `@import` 引用的地址
+pub(crate) fn css_references(css: &str) -> Vec<String> {
+    let mut references = Vec::new();
+    rewrite_css_urls(css, |url| {
+        references.push(url.to_string());
+        None
+    });
+    references
+}
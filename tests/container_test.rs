@@ -0,0 +1,19 @@
+use rust_epub::container::{Container, RootFile, MEDIA_TYPE_OPF};
+
+#[test]
+fn test_encode_xml() {
+    let mut container = Container::new();
+    container
+        .add_rootfile(RootFile::new("EPUB/content.opf", MEDIA_TYPE_OPF))
+        .add_rootfile(RootFile::new("EPUB/fixed.opf", MEDIA_TYPE_OPF));
+
+    let ret = container.encode_xml();
+    assert!(ret.is_ok());
+    let xml = ret.unwrap();
+    println!("{}", xml);
+    assert!(xml.contains("xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\""));
+    assert!(xml.contains(
+        "<rootfile full-path=\"EPUB/content.opf\" media-type=\"application/oebps-package+xml\"/>"
+    ));
+    assert!(xml.contains("<rootfile full-path=\"EPUB/fixed.opf\""));
+}
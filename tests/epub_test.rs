@@ -1,5 +1,11 @@
 use rand::{thread_rng, Rng};
-use rust_epub::epub::{EpubBuilder, EpubVersion};
+use rust_epub::epub::{BodyCheck, EpubBuilder, EpubVersion};
+use rust_epub::opf::{is_valid_isbn, Creator, Identifier, Title};
+use rust_epub::reader::Book;
+use rust_epub::toc::{TocElement, TocNav};
+use rust_epub::validate::DiagnosticKind;
+use rust_epub::xhtml::XHtmlRoot;
+use rust_epub::{Error, MediaSource};
 use std::env;
 use std::fs;
 
@@ -180,3 +186,970 @@ fn test_encode_toc_xml() {
     let ret = epub.output(random_dir_path.as_path());
     println!("{:?}", ret);
 }
+
+#[test]
+fn test_output_epub() {
+    let mut epub = EpubBuilder::new("大语言模型：辩论", EpubVersion::V30);
+    let testdata = env::current_dir().unwrap().join("tests").join("testdata");
+    let css_path = testdata.join("style.css").to_str().unwrap().to_string();
+    let image_path = testdata.join("cover.jpg").to_str().unwrap().to_string();
+
+    let section = epub.add_section("测试章节内容", "测试章节", None, Some(css_path));
+    assert!(section.is_ok());
+    assert!(epub.add_image(&image_path, None).is_ok());
+
+    let output_file = env::temp_dir()
+        .join(format!("rust-epub-{}", std::process::id()))
+        .join("output.epub");
+    let ret = epub.output_epub(output_file.as_path());
+    assert!(ret.is_ok());
+
+    let mut archive = zip::ZipArchive::new(fs::File::open(&output_file).unwrap()).unwrap();
+    {
+        let mimetype = archive.by_index(0).unwrap();
+        assert_eq!(mimetype.name(), "mimetype");
+        assert_eq!(mimetype.compression(), zip::CompressionMethod::Stored);
+    }
+    let names: Vec<String> = archive.file_names().map(String::from).collect();
+    assert!(names.contains(&String::from("META-INF/")));
+    assert!(names.contains(&String::from("META-INF/container.xml")));
+    assert!(names.contains(&String::from("EPUB/content.opf")));
+    assert!(names.contains(&String::from("EPUB/toc.ncx")));
+    assert!(names.contains(&String::from("EPUB/nav.xhtml")));
+    assert!(names.contains(&String::from("EPUB/xhtml/section_1.xhtml")));
+    assert!(names.contains(&String::from("EPUB/css/style.css")));
+    assert!(names.contains(&String::from("EPUB/images/cover.jpg")));
+
+    let _ = fs::remove_dir_all(output_file.parent().unwrap());
+}
+
+#[test]
+fn test_output_package() {
+    let mut epub = EpubBuilder::new("大语言模型：辩论", EpubVersion::V30);
+    let testdata = env::current_dir().unwrap().join("tests").join("testdata");
+    let image_path = testdata.join("cover.jpg").to_str().unwrap().to_string();
+
+    epub.set_id("urn:isbn:9787115000000")
+        .set_language("zh-CN")
+        .set_publisher("人民邮电出版社");
+    assert!(epub.set_cover(&image_path, None).is_ok());
+    let section = epub.add_section("测试章节内容", "测试章节", None, None);
+    assert!(epub
+        .add_sub_section(section.ok(), "子章节内容", "子章节", None, None)
+        .is_ok());
+
+    let output_path = env::temp_dir().join(format!("rust-epub-opf-{}", std::process::id()));
+    assert!(epub.output(output_path.as_path()).is_ok());
+
+    let opf = fs::read_to_string(output_path.join("EPUB").join("content.opf")).unwrap();
+    assert!(opf.contains("version=\"3.0\""));
+    assert!(opf.contains("<dc:title>大语言模型：辩论</dc:title>"));
+    assert!(opf.contains("<dc:publisher>人民邮电出版社</dc:publisher>"));
+    assert!(opf.contains("unique-identifier=\"BookId\""));
+    assert!(opf.contains("<dc:identifier id=\"BookId\">urn:isbn:9787115000000</dc:identifier>"));
+    assert!(opf.contains("<dc:language>zh-CN</dc:language>"));
+    assert!(
+        opf.contains("href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"")
+    );
+    assert!(opf.contains(
+        "href=\"images/cover.jpg\" media-type=\"image/jpeg\" properties=\"cover-image\""
+    ));
+    assert!(opf.contains("<item id=\"xhtml_section_3_xhtml\" href=\"xhtml/section_3.xhtml\" media-type=\"application/xhtml+xml\"/>"));
+    assert!(opf.contains("<spine toc=\"ncx\"><itemref idref=\"xhtml_cover_xhtml\"/><itemref idref=\"xhtml_section_2_xhtml\"/><itemref idref=\"xhtml_section_3_xhtml\"/></spine>"));
+
+    let _ = fs::remove_dir_all(output_path);
+}
+
+#[test]
+fn test_open_and_resave() {
+    let root = env::temp_dir().join(format!("rust-epub-roundtrip-{}", std::process::id()));
+    let testdata = env::current_dir().unwrap().join("tests").join("testdata");
+    let image_path = testdata.join("cover.jpg").to_str().unwrap().to_string();
+
+    let mut epub = EpubBuilder::new("梦回大明春", EpubVersion::V30);
+    epub.set_id("urn:uuid:0b6a8e50-4b7e-4d6f-9a55-4a0f3f1d3f10")
+        .set_language("zh-CN");
+    epub.set_cover(&image_path, None).unwrap();
+    let volume = epub
+        .add_section("<p>第一卷内容</p>", "第一卷 蛮家子", None, None)
+        .unwrap();
+    epub.add_sub_section(Some(volume), "<p>正文&#160;</p>", "第001章", None, None)
+        .unwrap();
+    epub.output_epub(&root.join("original.epub")).unwrap();
+
+    let mut epub = EpubBuilder::open(root.join("original.epub")).unwrap();
+    epub.set_title("梦回大明春（修订版）");
+    epub.add_section("<p>后记</p>", "后记", None, None).unwrap();
+    epub.output_epub(&root.join("edited.epub")).unwrap();
+    epub.output(&root.join("edited")).unwrap();
+
+    for path in [root.join("edited.epub"), root.join("edited")] {
+        let book = Book::open(path).unwrap();
+        assert_eq!(book.metadata().title, "梦回大明春（修订版）");
+        assert_eq!(
+            book.metadata().identifier.as_ref().unwrap().text,
+            "urn:uuid:0b6a8e50-4b7e-4d6f-9a55-4a0f3f1d3f10"
+        );
+
+        let toc = book.toc().unwrap();
+        let titles: Vec<&str> = toc.elements().iter().map(|e| e.title.as_str()).collect();
+        assert_eq!(titles, vec!["封面", "第一卷 蛮家子", "后记"]);
+        assert_eq!(toc.elements()[1].childs[0].title, "第001章");
+
+        let chapter = book
+            .read_href_to_string(&toc.elements()[1].childs[0].url)
+            .unwrap();
+        assert!(chapter.contains("<p>正文&#160;</p>"));
+
+        let cover_id = book.metadata().cover.clone().unwrap();
+        assert_eq!(
+            book.read_id(&cover_id).unwrap(),
+            fs::read(&image_path).unwrap()
+        );
+    }
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn test_open_relinks_resources() {
+    let root = env::temp_dir().join(format!("rust-epub-relink-{}", std::process::id()));
+    let source = root.join("source");
+    let files = [
+        ("mimetype", "application/epub+zip"),
+        (
+            "META-INF/container.xml",
+            r#"<?xml version="1.0"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>"#,
+        ),
+        (
+            "OEBPS/content.opf",
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<package version="2.0" unique-identifier="uid" xmlns="http://www.idpf.org/2007/opf">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf">
+    <dc:title>Old Book</dc:title>
+    <dc:creator>Someone</dc:creator>
+    <dc:language>en</dc:language>
+    <dc:identifier id="uid">old-book-1</dc:identifier>
+    <meta name="cover" content="cover-image"/>
+  </metadata>
+  <manifest>
+    <item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>
+    <item id="style" href="Styles/main.css" media-type="text/css"/>
+    <item id="cover-image" href="Images/cover.jpg" media-type="image/jpeg"/>
+    <item id="part" href="Text/part.html" media-type="application/xhtml+xml"/>
+    <item id="ch1" href="Text/ch1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="notes" href="Text/notes.xhtml" media-type="application/xhtml+xml"/>
+    <item id="popup" href="Text/popup.xhtml" media-type="application/xhtml+xml"/>
+    <item id="script" href="Misc/app.js" media-type="application/javascript"/>
+    <item id="overlay" href="Audio/ch1.smil" media-type="application/smil+xml"/>
+  </manifest>
+  <spine toc="ncx">
+    <itemref idref="part"/>
+    <itemref idref="ch1"/>
+    <itemref idref="notes"/>
+  </spine>
+</package>"#,
+        ),
+        (
+            "OEBPS/toc.ncx",
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1">
+  <head><meta name="dtb:uid" content="old-book-1"/></head>
+  <docTitle><text>Old Book</text></docTitle>
+  <navMap>
+    <navPoint id="p1" playOrder="1">
+      <navLabel><text>Part One</text></navLabel>
+      <content src="Text/part.html"/>
+      <navPoint id="p2" playOrder="2">
+        <navLabel><text>Chapter One</text></navLabel>
+        <content src="Text/ch1.xhtml#start"/>
+      </navPoint>
+    </navPoint>
+  </navMap>
+</ncx>"#,
+        ),
+        (
+            "OEBPS/Styles/main.css",
+            "body { background: url(\"../Images/cover.jpg\"); }",
+        ),
+        (
+            "OEBPS/Text/part.html",
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml"><head><title>Part</title>
+<link href="../Styles/main.css" rel="stylesheet" type="text/css"/></head>
+<body><h1>Part One</h1><a href="ch1.xhtml#start">next</a></body></html>"#,
+        ),
+        (
+            "OEBPS/Text/ch1.xhtml",
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml"><head><title>Ch1</title>
+<meta name="viewport" content="width=600, height=800"/><script type="text/javascript" src="../Misc/app.js"></script></head>
+<body class="chapter" id="top"><p id="start"><img src="../Images/cover.jpg" alt="c"/><a href="part.html">up</a><a href="http://example.com/">web</a><a href="popup.xhtml#n1">1</a></p></body></html>"#,
+        ),
+        (
+            "OEBPS/Text/popup.xhtml",
+            r#"<html xmlns="http://www.w3.org/1999/xhtml"><head><title>Popup</title></head><body><p id="n1"><img src="../Images/cover.jpg" alt="c"/><a href="ch1.xhtml#start">back</a></p></body></html>"#,
+        ),
+        ("OEBPS/Misc/app.js", "document.title = 'app';"),
+        (
+            "OEBPS/Audio/ch1.smil",
+            r#"<smil xmlns="http://www.w3.org/ns/SMIL" version="3.0"><body/></smil>"#,
+        ),
+        (
+            "OEBPS/Text/notes.xhtml",
+            r#"<html xmlns="http://www.w3.org/1999/xhtml"><head><title>Notes</title></head><body/></html>"#,
+        ),
+    ];
+    for (name, content) in files {
+        let path = source.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+    fs::create_dir_all(source.join("OEBPS/Images")).unwrap();
+    fs::write(
+        source.join("OEBPS/Images/cover.jpg"),
+        fs::read("tests/testdata/cover.jpg").unwrap(),
+    )
+    .unwrap();
+
+    let mut epub = EpubBuilder::open(&source).unwrap();
+    epub.output_epub(&root.join("relinked.epub")).unwrap();
+
+    let book = Book::open(root.join("relinked.epub")).unwrap();
+    assert_eq!(book.metadata().title, "Old Book");
+    assert_eq!(book.metadata().creator, vec![Creator::new("Someone", "")]);
+    assert_eq!(book.metadata().language, "en");
+
+    let toc = book.toc().unwrap();
+    assert_eq!(toc.elements().len(), 1);
+    assert_eq!(toc.elements()[0].title, "Part One");
+    assert_eq!(toc.elements()[0].url, "xhtml/part.xhtml");
+    assert_eq!(toc.elements()[0].childs[0].title, "Chapter One");
+    // 不在目录中的文件与前一个文件同级
+    assert_eq!(toc.elements()[0].childs[1].title, "Notes");
+
+    let part = book.read_href_to_string("xhtml/part.xhtml").unwrap();
+    assert!(part.contains(r#"href="../css/main.css""#));
+    assert!(part.contains(r#"<a href="ch1.xhtml#start">next</a>"#));
+    let chapter = book.read_href_to_string("xhtml/ch1.xhtml").unwrap();
+    assert!(chapter.contains(r#"<img src="../images/cover.jpg" alt="c"/>"#));
+    assert!(chapter.contains(r#"<a href="part.xhtml">up</a>"#));
+    assert!(chapter.contains(r#"<a href="http://example.com/">web</a>"#));
+    // head 中的其他元素和 body 属性保持不变
+    assert!(chapter.contains(r#"<meta name="viewport" content="width=600, height=800"/>"#));
+    assert!(chapter.contains(r#"<script type="text/javascript" src="../Misc/app.js"></script>"#));
+    assert!(chapter.contains(r#"<body class="chapter" id="top""#));
+    assert!(chapter.contains(r#"<a href="../Text/popup.xhtml#n1">1</a>"#));
+
+    // 不在书脊中的文档和其他资源保留原路径和媒体类型
+    let popup = book.read_href_to_string("Text/popup.xhtml").unwrap();
+    assert!(popup.contains(r#"<img src="../images/cover.jpg" alt="c"/>"#));
+    assert!(popup.contains(r#"<a href="../xhtml/ch1.xhtml#start">back</a>"#));
+    assert_eq!(
+        book.read_href_to_string("Misc/app.js").unwrap(),
+        "document.title = 'app';"
+    );
+    assert_eq!(
+        book.resource_by_href("Misc/app.js").unwrap().media_type,
+        "application/javascript"
+    );
+    assert_eq!(
+        book.resource_by_href("Audio/ch1.smil").unwrap().media_type,
+        "application/smil+xml"
+    );
+    assert_eq!(book.spine_items().len(), 3);
+    assert!(epub.check_links().is_valid());
+
+    let css = book.read_href_to_string("css/main.css").unwrap();
+    assert_eq!(css, "body { background: url(\"../images/cover.jpg\"); }");
+    let cover_id = book.metadata().cover.clone().unwrap();
+    assert_eq!(
+        book.read_id(&cover_id).unwrap(),
+        fs::read("tests/testdata/cover.jpg").unwrap()
+    );
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn test_add_media_from_memory() {
+    let image = fs::read("tests/testdata/cover.jpg").unwrap();
+    let mut epub = EpubBuilder::new("内存资源", EpubVersion::V30);
+
+    let cover = image.clone();
+    epub.set_cover_image(cover, Some(String::from("cover.jpg")), None)
+        .unwrap();
+    let ret = epub.add_image(image.as_slice(), Some(String::from("bytes.jpg")));
+    assert_eq!(ret.unwrap(), "../images/bytes.jpg");
+    let reader = MediaSource::from_reader(fs::File::open("tests/testdata/style.css").unwrap());
+    assert!(epub
+        .add_stylesheet(reader.unwrap(), Some(String::from("style.css")))
+        .is_ok());
+    let ret = epub.add_font(
+        MediaSource::lazy(|| Ok(b"font data".to_vec())),
+        Some(String::from("font.ttf")),
+    );
+    assert_eq!(ret.unwrap(), "../fonts/font.ttf");
+
+    // 内存数据没有文件名
+    assert!(epub.add_image(image.clone(), None).is_err());
+    assert!(epub.set_cover_image(image, None, None).is_err());
+
+    let book = Book::from_bytes(epub.output_bytes().unwrap()).unwrap();
+    let cover_id = book.metadata().cover.clone().unwrap();
+    assert_eq!(
+        book.read_id(&cover_id).unwrap(),
+        fs::read("tests/testdata/cover.jpg").unwrap()
+    );
+    assert_eq!(
+        book.read_href("images/bytes.jpg").unwrap(),
+        fs::read("tests/testdata/cover.jpg").unwrap()
+    );
+    assert_eq!(
+        book.read_href("css/style.css").unwrap(),
+        fs::read("tests/testdata/style.css").unwrap()
+    );
+    assert_eq!(book.read_href("fonts/font.ttf").unwrap(), b"font data");
+}
+
+#[test]
+fn test_body_check() {
+    let mut unchecked = EpubBuilder::new("正文检查", EpubVersion::V30);
+    assert!(unchecked
+        .add_section("<p>第一段\n<p>第二段&nbsp;</p>", "第一章", None, None)
+        .is_ok());
+    assert!(!unchecked.check_sections().is_valid());
+
+    let mut epub = EpubBuilder::new("正文检查", EpubVersion::V30);
+    epub.set_body_check(BodyCheck::Reject);
+    let ret = epub.add_section("<p>第一段\n<p>第二段&nbsp;</p>", "第一章", None, None);
+    match ret {
+        Err(Error::MalformedXhtmlErr(message)) => {
+            assert!(message.contains("section_1.xhtml:1:1"), "{}", message);
+            assert!(message.contains("&nbsp;"), "{}", message);
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    epub.set_body_check(BodyCheck::Repair);
+    let filename = epub
+        .add_section("<p>第一段<br>\n<p>第二段&nbsp;</p>", "第一章", None, None)
+        .unwrap();
+    assert!(epub.check_sections().is_valid());
+
+    let book = Book::from_bytes(epub.output_bytes().unwrap()).unwrap();
+    let chapter = book
+        .read_href_to_string(&format!("xhtml/{}", filename))
+        .unwrap();
+    assert!(chapter.contains("<p>第一段<br/>\n</p><p>第二段&#160;</p>"));
+
+    epub.set_body_check(BodyCheck::Off);
+    assert!(epub.add_section("<p>未检查", "第二章", None, None).is_ok());
+    assert_eq!(epub.check_sections().errors().count(), 1);
+}
+
+#[test]
+fn test_check_links() {
+    let mut epub = EpubBuilder::new("链接检查", EpubVersion::V30);
+    epub.add_font(
+        MediaSource::from(&b"font data"[..]),
+        Some(String::from("font.ttf")),
+    )
+    .unwrap();
+    let root = env::temp_dir().join(format!("rust_epub_links_{}", thread_rng().gen::<u32>()));
+    fs::create_dir_all(&root).unwrap();
+    let css_path = root.join("style.css");
+    let css = "@font-face { src: url(../fonts/font.ttf); }\nbody { background: url(missing.png); }";
+    fs::write(&css_path, css).unwrap();
+    epub.add_image("tests/testdata/cover.jpg", Some(String::from("used.jpg")))
+        .unwrap();
+    epub.add_image("tests/testdata/cover.jpg", Some(String::from("unused.jpg")))
+        .unwrap();
+
+    let body = "<p id=\"top\"><a href=\"chapter_2.xhtml#note\">注</a></p>\n\
+                <p><img src=\"../images/used.jpg\" alt=\"\"/><a href=\"#top\">顶部</a></p>\n\
+                <p><a href=\"chapter_3.xhtml\">下一章</a><a href=\"#bottom\">底部</a></p>\n\
+                <p><a href=\"https://example.com/\">外部</a></p>";
+    epub.add_section(
+        body,
+        "第一章",
+        Some(String::from("chapter_1.xhtml")),
+        Some(css_path.to_string_lossy().into_owned()),
+    )
+    .unwrap();
+    epub.add_section(
+        "<p id=\"note\">注释</p><p><a href=\"chapter_1.xhtml#nowhere\">返回</a></p>",
+        "第二章",
+        Some(String::from("chapter_2.xhtml")),
+        None,
+    )
+    .unwrap();
+
+    let report = epub.check_links();
+    let found: Vec<String> = report.diagnostics().iter().map(|d| d.to_string()).collect();
+    assert_eq!(
+        found,
+        vec![
+            "ERROR(BrokenLink) EPUB/xhtml/chapter_1.xhtml:3:13: chapter_3.xhtml does not resolve to a file of the book",
+            "ERROR(MissingFragment) EPUB/xhtml/chapter_1.xhtml:3:46: Fragment #bottom not found in EPUB/xhtml/chapter_1.xhtml",
+            "ERROR(MissingFragment) EPUB/xhtml/chapter_2.xhtml:1:32: Fragment #nowhere not found in EPUB/xhtml/chapter_1.xhtml",
+            "ERROR(BrokenLink) EPUB/css/style.css: missing.png does not resolve to a file of the book",
+            "WARNING(UnreferencedMedia) EPUB/images/unused.jpg: EPUB/images/unused.jpg is not referenced by any section or stylesheet",
+        ]
+    );
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn test_add_markdown_section() {
+    let markdown = "# 序章 {#intro}\n\n\
+                    正文~~删除~~，见脚注[^1]。<br>\n\n\
+                    ![封面](tests/testdata/cover.jpg \"cover\")\n\
+                    ![远程](https://example.com/a.png)\n\n\
+                    ## Second Part\n\n\
+                    | 名称 | 数量 |\n|:---|---:|\n| A & B | 1 |\n\n\
+                    ## Second Part\n\n\
+                    [^1]: 脚注内容\n";
+    let mut epub = EpubBuilder::new("Markdown", EpubVersion::V30);
+    let filename = epub
+        .add_markdown_section(None, markdown, "序章", Some(String::from("intro")), None)
+        .unwrap();
+    assert_eq!(filename, "intro.xhtml");
+    epub.add_markdown_section(
+        None,
+        "![again](tests/testdata/cover.jpg)",
+        "重复图片",
+        None,
+        None,
+    )
+    .unwrap();
+
+    let book = Book::from_bytes(epub.output_bytes().unwrap()).unwrap();
+    let body = XHtmlRoot::decode_xml(&book.read_href_to_string("xhtml/intro.xhtml").unwrap())
+        .unwrap()
+        .body()
+        .to_string();
+    assert!(body.contains("<h1 id=\"intro\">序章</h1>"));
+    assert!(body.contains("<h2 id=\"second-part\">Second Part</h2>"));
+    assert!(body.contains("<h2 id=\"second-part-1\">Second Part</h2>"));
+    assert!(body.contains("<del>删除</del>"));
+    assert!(body.contains("<br/>"));
+    assert!(body.contains("<img src=\"../images/cover.jpg\" alt=\"封面\" title=\"cover\"/>"));
+    assert!(body.contains("<img src=\"https://example.com/a.png\""));
+    assert!(body.contains("<td style=\"text-align: left\">A &amp; B</td>"));
+    assert!(body.contains("<a href=\"#fn-1\" epub:type=\"noteref\">1</a>"));
+    assert!(body.contains("id=\"fn-1\" epub:type=\"footnote\""));
+    assert_eq!(
+        book.read_href("images/cover.jpg").unwrap(),
+        fs::read("tests/testdata/cover.jpg").unwrap()
+    );
+    assert!(book.resource_by_href("images/cover_2.jpg").is_none());
+    assert!(epub.check_links().is_valid());
+}
+
+#[test]
+fn test_toc_from_headings() {
+    let mut epub = EpubBuilder::new("标题目录", EpubVersion::V30);
+    epub.set_toc_heading_depth(3);
+    let body =
+        "<h1>第一章</h1>\n<p>开头</p>\n<h2 id=\"first\">第一节</h2>\n<h3>Part &amp; One</h3>\n\
+                <h4>太深</h4>\n<h2>第二节</h2>\n<h3>Part &amp; One</h3>";
+    epub.add_section(body, "第一章", Some(String::from("chapter_1.xhtml")), None)
+        .unwrap();
+    epub.add_sub_section(
+        Some(String::from("chapter_1.xhtml")),
+        "<h2>小节</h2>",
+        "附录",
+        Some(String::from("appendix.xhtml")),
+        None,
+    )
+    .unwrap();
+
+    let book = Book::from_bytes(epub.output_bytes().unwrap()).unwrap();
+    let chapter = book.read_href_to_string("xhtml/chapter_1.xhtml").unwrap();
+    assert!(chapter.contains("<h1 id=\"第一章\">第一章</h1>"));
+    assert!(chapter.contains("<h3 id=\"part-one\">Part &amp; One</h3>"));
+    assert!(chapter.contains("<h4>太深</h4>"));
+    assert!(chapter.contains("<h2 id=\"第二节\">第二节</h2>\n<h3 id=\"part-one-1\">"));
+
+    let toc = book.toc().unwrap();
+    let chapter = &toc.elements()[0];
+    let entries: Vec<(&str, &str)> = chapter
+        .childs
+        .iter()
+        .map(|e| (e.title.as_str(), e.url.as_str()))
+        .collect();
+    assert_eq!(
+        entries,
+        vec![
+            ("第一节", "xhtml/chapter_1.xhtml#first"),
+            ("第二节", "xhtml/chapter_1.xhtml#第二节"),
+            ("附录", "xhtml/appendix.xhtml"),
+        ]
+    );
+    assert_eq!(chapter.childs[0].childs[0].title, "Part & One");
+    assert_eq!(
+        chapter.childs[0].childs[0].url,
+        "xhtml/chapter_1.xhtml#part-one"
+    );
+    assert!(chapter.childs[0].childs[0].childs.is_empty());
+    assert_eq!(chapter.childs[2].childs[0].url, "xhtml/appendix.xhtml#小节");
+    assert!(epub.check_links().is_valid());
+
+    let ncx = book.read_href_to_string("toc.ncx").unwrap();
+    assert!(ncx.contains("<meta name=\"dtb:depth\" content=\"3\"/>"));
+}
+
+#[test]
+fn test_custom_toc() {
+    let mut epub = EpubBuilder::new("自定义目录", EpubVersion::V30);
+    for (index, body) in [
+        "<p id=\"a\">甲</p><p id=\"b\">乙</p>",
+        "<p>丙</p>",
+        "<p>版权</p>",
+    ]
+    .iter()
+    .enumerate()
+    {
+        let filename = format!("file_{}.xhtml", index + 1);
+        epub.add_section(*body, format!("文件{}", index + 1), Some(filename), None)
+            .unwrap();
+    }
+
+    let mut toc = TocNav::new("目录", "zh-CN");
+    let mut group = TocElement::new("", "第一部分");
+    group
+        .add_child(TocElement::new("xhtml/file_1.xhtml#a", "甲"))
+        .add_child(TocElement::new("xhtml/file_1.xhtml#b", "乙"))
+        .add_child(TocElement::new("xhtml/file_2.xhtml", "丙"));
+    toc.add_element(group);
+    epub.set_toc(toc);
+    epub.edit_toc(|toc| {
+        toc.elements_mut()
+            .push(TocElement::new("xhtml/file_2.xhtml", "附录"));
+    });
+    assert_eq!(epub.toc().elements().len(), 2);
+
+    let book = Book::from_bytes(epub.output_bytes().unwrap()).unwrap();
+    let nav = book.read_href_to_string("nav.xhtml").unwrap();
+    assert!(nav.contains("<span>第一部分</span>"));
+    assert!(nav.contains("<a href=\"xhtml/file_1.xhtml#b\">乙</a>"));
+    assert!(!nav.contains("file_3.xhtml"));
+
+    let ncx = TocNav::decode_ncx(&book.read_href_to_string("toc.ncx").unwrap()).unwrap();
+    assert_eq!(ncx.elements()[0].title, "第一部分");
+    assert_eq!(ncx.elements()[0].url, "xhtml/file_1.xhtml#a");
+    assert_eq!(ncx.elements()[0].childs.len(), 3);
+    assert_eq!(ncx.elements()[1].title, "附录");
+
+    let toc = book.toc().unwrap();
+    assert_eq!(toc.title(), "目录");
+    assert_eq!(toc.elements()[0].url, "");
+    assert_eq!(toc.elements()[0].childs[2].title, "丙");
+
+    // 修改生成的目录
+    let mut epub = EpubBuilder::new("修改目录", EpubVersion::V20);
+    epub.add_section("<p>正文</p>", "正文", None, None).unwrap();
+    epub.add_section("<p>版权</p>", "版权", None, None).unwrap();
+    epub.edit_toc(|toc| {
+        toc.elements_mut().retain(|e| e.title != "版权");
+        toc.elements_mut()[0].title = String::from("第一章 正文");
+    });
+    let book = Book::from_bytes(epub.output_bytes().unwrap()).unwrap();
+    let toc = book.toc().unwrap();
+    assert_eq!(toc.elements().len(), 1);
+    assert_eq!(toc.elements()[0].title, "第一章 正文");
+    assert_eq!(book.spine_items().len(), 2);
+}
+
+#[test]
+fn test_landmarks_and_page_list() {
+    let mut epub = EpubBuilder::new("页码", EpubVersion::V30);
+    epub.set_cover("tests/testdata/cover.jpg", None).unwrap();
+    epub.add_section(
+        "<p>甲</p><span epub:type=\"pagebreak\" id=\"page_1\" title=\"1\"/>\
+         <p>乙</p><span epub:type=\"pagebreak\" id=\"page_2\">2</span>",
+        "第一章",
+        Some(String::from("chapter_1.xhtml")),
+        None,
+    )
+    .unwrap();
+    epub.add_section(
+        "<p>版权所有</p>",
+        "版权",
+        Some(String::from("copyright.xhtml")),
+        None,
+    )
+    .unwrap();
+    epub.add_landmark("copyright-page", "xhtml/copyright.xhtml", "版权")
+        .add_page("3", "xhtml/copyright.xhtml");
+
+    let toc = epub.toc();
+    let kinds: Vec<&str> = toc.landmarks().iter().map(|l| l.kind.as_str()).collect();
+    assert_eq!(kinds, vec!["cover", "toc", "bodymatter", "copyright-page"]);
+    assert_eq!(toc.landmarks()[2].url, "xhtml/chapter_1.xhtml");
+    let titles: Vec<&str> = toc.landmarks().iter().map(|l| l.title.as_str()).collect();
+    assert_eq!(titles, vec!["封面", "目录", "第一章", "版权"]);
+    let pages: Vec<(&str, &str)> = toc
+        .pages()
+        .iter()
+        .map(|p| (p.name.as_str(), p.url.as_str()))
+        .collect();
+    assert_eq!(
+        pages,
+        vec![
+            ("1", "xhtml/chapter_1.xhtml#page_1"),
+            ("2", "xhtml/chapter_1.xhtml#page_2"),
+            ("3", "xhtml/copyright.xhtml"),
+        ]
+    );
+
+    let book = Book::from_bytes(epub.output_bytes().unwrap()).unwrap();
+    let nav = TocNav::decode_nav(&book.read_href_to_string("nav.xhtml").unwrap()).unwrap();
+    assert_eq!(nav.landmarks(), toc.landmarks());
+    assert_eq!(nav.pages(), toc.pages());
+    let ncx = book.read_href_to_string("toc.ncx").unwrap();
+    assert!(ncx.contains("<meta name=\"dtb:totalPageCount\" content=\"3\"/>"));
+    assert!(ncx.contains("<meta name=\"dtb:maxPageNumber\" content=\"3\"/>"));
+    assert!(epub.check_links().is_valid());
+
+    // 地标标题跟随书籍语言，也可以单独设置
+    epub.set_language("en")
+        .set_landmark_title("bodymatter", "Start");
+    let titles: Vec<String> = epub
+        .toc()
+        .landmarks()
+        .iter()
+        .map(|l| l.title.clone())
+        .collect();
+    assert_eq!(titles, vec!["Cover", "Table of Contents", "Start", "版权"]);
+    epub.set_language("zh-TW")
+        .set_landmark_title("cover", "書封");
+    let titles: Vec<String> = epub
+        .toc()
+        .landmarks()
+        .iter()
+        .map(|l| l.title.clone())
+        .collect();
+    assert_eq!(titles, vec!["書封", "目錄", "Start", "版权"]);
+}
+
+#[test]
+fn test_titles_and_subject() {
+    let mut epub = EpubBuilder::new("梦回大明春", EpubVersion::V30);
+    epub.set_subject("历史")
+        .add_title(Title::new("第二卷 蛮家子", "subtitle"));
+    assert!(epub
+        .add_section("<p>正文</p>", "第一章", None, None)
+        .is_ok());
+
+    let output_path = env::temp_dir().join(format!("rust-epub-titles-{}", std::process::id()));
+    assert!(epub.output(output_path.as_path()).is_ok());
+    let opf = fs::read_to_string(output_path.join("EPUB").join("content.opf")).unwrap();
+    assert!(opf.contains("<dc:title>梦回大明春</dc:title>"));
+    assert!(opf.contains("<dc:title id=\"title-2\">第二卷 蛮家子</dc:title>"));
+    assert!(opf.contains("<dc:subject>历史</dc:subject>"));
+
+    let _ = fs::remove_dir_all(&output_path);
+}
+
+#[test]
+fn test_identifiers() {
+    assert!(is_valid_isbn("978-7-115-00000-2"));
+    assert!(is_valid_isbn("978-0-306-40615-7"));
+    assert!(is_valid_isbn("0-306-40615-2"));
+    assert!(is_valid_isbn("0-8044-2957-x"));
+    assert!(!is_valid_isbn("0-306-40615-3"));
+    assert!(Identifier::isbn("isbn", "978-0-306-40615-8").is_err());
+
+    let mut epub = EpubBuilder::new("梦回大明春", EpubVersion::V30);
+    let mut invalid = Identifier::new("isbn", "978-7-115-00000-0");
+    invalid.scheme = String::from("ISBN");
+    assert!(epub.add_identifier(invalid).is_err());
+    epub.add_identifier(Identifier::isbn("isbn", "978-0-306-40615-7").unwrap())
+        .unwrap()
+        .add_identifier(Identifier::new("doi", "urn:doi:10.1000/182"))
+        .unwrap();
+    assert!(epub.unique_identifier().is_none());
+    assert!(epub
+        .add_section("<p>正文</p>", "第一章", None, None)
+        .is_ok());
+
+    let bytes = epub.output_bytes().unwrap();
+    let uuid = epub.unique_identifier().unwrap().clone();
+    assert_eq!(uuid.scheme, "UUID");
+    assert_eq!(uuid.text.len(), 36);
+    assert_eq!(&uuid.text[14..15], "4");
+    // 再次输出使用同一个标识符
+    epub.output_bytes().unwrap();
+    assert_eq!(epub.unique_identifier(), Some(&uuid));
+
+    let book = Book::from_bytes(bytes).unwrap();
+    let metadata = book.metadata();
+    assert_eq!(book.package().unique_identifier(), "BookId");
+    assert_eq!(
+        metadata.identifier.as_ref().unwrap().text,
+        format!("urn:uuid:{}", uuid.text)
+    );
+    let others: Vec<&str> = metadata
+        .other_identifiers
+        .iter()
+        .map(|i| i.text.as_str())
+        .collect();
+    assert_eq!(
+        others,
+        vec!["urn:isbn:9780306406157", "urn:doi:10.1000/182"]
+    );
+
+    let mut epub = EpubBuilder::new("梦回大明春", EpubVersion::V20);
+    epub.set_unique_identifier(Identifier::isbn("isbn", "9780306406157").unwrap())
+        .unwrap();
+    assert!(epub
+        .add_section("<p>正文</p>", "第一章", None, None)
+        .is_ok());
+    let book = Book::from_bytes(epub.output_bytes().unwrap()).unwrap();
+    let identifier = book.metadata().identifier.clone().unwrap();
+    assert_eq!(identifier.scheme, "ISBN");
+    assert_eq!(identifier.text, "9780306406157");
+    assert_eq!(identifier.id, "BookId");
+    assert_eq!(
+        book.toc().unwrap().metadata("dtb:uid").as_deref(),
+        Some("9780306406157")
+    );
+
+    // 可复现模式下由内容计算标识符，书名和作者相同而正文不同时标识符不同
+    let output_id = |epub: &mut EpubBuilder| {
+        let book = Book::from_bytes(epub.output_bytes().unwrap()).unwrap();
+        book.metadata().identifier.clone().unwrap().text
+    };
+    let generated = |bodies: &[&str]| {
+        let mut epub = EpubBuilder::new("梦回大明春", EpubVersion::V30);
+        epub.set_reproducible(true).add_author("作者");
+        for body in bodies {
+            assert!(epub.add_section(*body, "第一章", None, None).is_ok());
+        }
+        output_id(&mut epub)
+    };
+    let first = generated(&["<p>正文</p>"]);
+    assert_eq!(&first[23..24], "8");
+    assert_eq!(first, generated(&["<p>正文</p>"]));
+    assert_ne!(first, generated(&["<p>另一本书的正文</p>"]));
+
+    // 校验或输出后继续修改，标识符随内容变化
+    let mut epub = EpubBuilder::new("梦回大明春", EpubVersion::V30);
+    epub.set_reproducible(true).add_author("作者");
+    assert!(epub
+        .add_section("<p>正文</p>", "第一章", None, None)
+        .is_ok());
+    epub.validate().unwrap();
+    assert!(epub.unique_identifier().is_none());
+    assert!(epub
+        .add_section("<p>第二章</p>", "第一章", None, None)
+        .is_ok());
+    assert_eq!(
+        output_id(&mut epub),
+        generated(&["<p>正文</p>", "<p>第二章</p>"])
+    );
+}
+
+#[test]
+fn test_unterminated_css_url() {
+    let mut epub = EpubBuilder::new("梦回大明春", EpubVersion::V30);
+    epub.add_stylesheet(
+        MediaSource::from("a{background:url(\"images/封面".as_bytes()),
+        Some(String::from("style.css")),
+    )
+    .unwrap();
+    epub.add_stylesheet(
+        MediaSource::from(&b"a{background:url(\""[..]),
+        Some(String::from("empty.css")),
+    )
+    .unwrap();
+    assert!(epub
+        .add_section("<p>正文</p>", "第一章", None, None)
+        .is_ok());
+    let report = epub.check_links();
+    assert!(report.of_kind(DiagnosticKind::BrokenLink).count() >= 1);
+
+    let bytes = epub.output_bytes().unwrap();
+    let book = Book::from_bytes(bytes).unwrap();
+    assert!(EpubBuilder::from_book(&book).is_ok());
+}
+
+/// 写出一个解压的 EPUB，包文件为 OEBPS/content.opf
+fn write_source(source: &std::path::Path, opf: &str, files: &[(&str, &str)]) {
+    let container = r#"<?xml version="1.0"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>"#;
+    let fixed = [
+        ("mimetype", "application/epub+zip"),
+        ("META-INF/container.xml", container),
+        ("OEBPS/content.opf", opf),
+    ];
+    for (name, content) in fixed.iter().chain(files) {
+        let path = source.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+}
+
+#[test]
+fn test_open_unterminated_attribute() {
+    let root = env::temp_dir().join(format!("rust-epub-attribute-{}", std::process::id()));
+    let opf = r#"<?xml version="1.0" encoding="utf-8"?>
+<package version="3.0" unique-identifier="uid" xmlns="http://www.idpf.org/2007/opf">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:title>Broken</dc:title>
+    <dc:identifier id="uid">broken-1</dc:identifier>
+  </metadata>
+  <manifest>
+    <item id="ch1" href="Text/ch1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="note1" href="Text/note1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="note2" href="Text/note2.xhtml" media-type="application/xhtml+xml"/>
+  </manifest>
+  <spine><itemref idref="ch1"/></spine>
+</package>"#;
+    let chapter = r#"<html xmlns="http://www.w3.org/1999/xhtml"><head><title>Ch1</title></head><body><p>正文</p></body></html>"#;
+    // 不在书脊中的文档，属性值缺少结束引号
+    write_source(
+        &root,
+        opf,
+        &[
+            ("OEBPS/Text/ch1.xhtml", chapter),
+            ("OEBPS/Text/note1.xhtml", "<p><a href=\"x>中"),
+            ("OEBPS/Text/note2.xhtml", "<p><a href=\""),
+        ],
+    );
+
+    let mut epub = EpubBuilder::open(&root).unwrap();
+    let book = Book::from_bytes(epub.output_bytes().unwrap()).unwrap();
+    assert_eq!(
+        book.read_href_to_string("Text/note1.xhtml").unwrap(),
+        "<p><a href=\"x>中"
+    );
+    assert_eq!(
+        book.read_href_to_string("Text/note2.xhtml").unwrap(),
+        "<p><a href=\""
+    );
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn test_open_keeps_metadata() {
+    let root = env::temp_dir().join(format!("rust-epub-metadata-{}", std::process::id()));
+    let opf = r##"<?xml version="1.0" encoding="utf-8"?>
+<package version="3.0" unique-identifier="uid" xmlns="http://www.idpf.org/2007/opf">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:title>Fixed</dc:title>
+    <dc:identifier id="uid">fixed-1</dc:identifier>
+    <dc:language>en</dc:language>
+    <dc:subject>A</dc:subject>
+    <dc:subject>B</dc:subject>
+    <meta property="dcterms:modified">2020-01-01T00:00:00Z</meta>
+    <meta property="rendition:layout">pre-paginated</meta>
+    <meta property="identifier-type" refines="#uid">01</meta>
+    <meta property="media:duration" refines="#overlay">0:00:10</meta>
+  </metadata>
+  <manifest>
+    <item id="ch1" href="Text/ch1.xhtml" media-type="application/xhtml+xml" media-overlay="overlay"/>
+    <item id="overlay" href="Audio/ch1.smil" media-type="application/smil+xml"/>
+  </manifest>
+  <spine><itemref idref="ch1"/></spine>
+</package>"##;
+    let chapter = r#"<html xmlns="http://www.w3.org/1999/xhtml"><head><title>Ch1</title></head><body><p>Text</p></body></html>"#;
+    write_source(
+        &root,
+        opf,
+        &[
+            ("OEBPS/Text/ch1.xhtml", chapter),
+            (
+                "OEBPS/Audio/ch1.smil",
+                r#"<smil xmlns="http://www.w3.org/ns/SMIL" version="3.0"/>"#,
+            ),
+        ],
+    );
+
+    let mut epub = EpubBuilder::open(&root).unwrap();
+    epub.set_modified(std::time::UNIX_EPOCH);
+    let book = Book::from_bytes(epub.output_bytes().unwrap()).unwrap();
+    assert_eq!(book.metadata().subject, vec!["A", "B"]);
+    let overlay = book.resource_by_href("Audio/ch1.smil").unwrap().id.clone();
+    let opf = book.read_file_to_string(book.package_path()).unwrap();
+    assert!(opf.contains("<meta property=\"rendition:layout\">pre-paginated</meta>"));
+    assert!(opf.contains("<meta property=\"identifier-type\" refines=\"#BookId\">01</meta>"));
+    assert!(opf.contains(&format!(
+        "<meta property=\"media:duration\" refines=\"#{}\">0:00:10</meta>",
+        overlay
+    )));
+    // 修改时间重新生成
+    assert_eq!(opf.matches("dcterms:modified").count(), 1);
+    assert!(opf.contains("<meta property=\"dcterms:modified\">1970-01-01T00:00:00Z</meta>"));
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn test_manifest_ids() {
+    let root = env::temp_dir().join(format!("rust-epub-ids-{}", std::process::id()));
+    let opf = r#"<?xml version="1.0" encoding="utf-8"?>
+<package version="3.0" unique-identifier="uid" xmlns="http://www.idpf.org/2007/opf">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:title>Ids</dc:title>
+    <dc:identifier id="uid">ids-1</dc:identifier>
+  </metadata>
+  <manifest>
+    <item id="ch1" href="Text/ch1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="root" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+  </manifest>
+  <spine><itemref idref="ch1"/></spine>
+</package>"#;
+    let chapter = r#"<html xmlns="http://www.w3.org/1999/xhtml"><head><title>Ch1</title></head><body><p>Text</p></body></html>"#;
+    write_source(
+        &root,
+        opf,
+        &[
+            ("OEBPS/Text/ch1.xhtml", chapter),
+            ("OEBPS/ch1.xhtml", chapter),
+        ],
+    );
+
+    // 文件名转换后相同，章节与包目录下的同名文件也不能共用 id
+    let mut epub = EpubBuilder::open(&root).unwrap();
+    epub.add_image(
+        MediaSource::from(&b"png"[..]),
+        Some(String::from("a.b.png")),
+    )
+    .unwrap();
+    epub.add_image(
+        MediaSource::from(&b"png"[..]),
+        Some(String::from("a_b.png")),
+    )
+    .unwrap();
+    let report = epub.validate().unwrap();
+    assert_eq!(report.of_kind(DiagnosticKind::DuplicateId).count(), 0);
+
+    let book = Book::from_bytes(epub.output_bytes().unwrap()).unwrap();
+    let mut ids: Vec<&str> = book
+        .package()
+        .manifest()
+        .iter()
+        .map(|m| m.id.as_str())
+        .collect();
+    let count = ids.len();
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), count);
+    assert!(ids.contains(&"images_a_b_png_2"));
+
+    let _ = fs::remove_dir_all(root);
+}
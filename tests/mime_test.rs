@@ -22,7 +22,7 @@ fn test_get_mime_type() {
     let ext_str = path.extension().and_then(OsStr::to_str);
     assert!(ext_str.is_some());
     assert_eq!(ext_str.unwrap(), "mp4");
-    let ext = get_mime_type(ext_str.unwrap().as_ref());
+    let ext = get_mime_type(ext_str.unwrap());
     assert!(ext.is_some());
 
     let ext_str = get_mime_type("mmmm");
@@ -1,6 +1,8 @@
-use rust_epub;
 use rust_epub::epub::EpubVersion;
-use rust_epub::opf::{BindingItem, GuideReference, ManifestItem, Metadata, SpineItemRef};
+use rust_epub::opf::{
+    BindingItem, Collection, Creator, Date, GuideReference, ManifestItem, Metadata, SpineItemRef,
+    Title,
+};
 
 #[test]
 fn test_encode_v2_xml() {
@@ -71,3 +73,260 @@ fn test_encode_v3_xml() {
     let ret = opf.encode_xml(EpubVersion::V30);
     println!("{}", ret.unwrap_or_default());
 }
+
+#[test]
+fn test_decode_xml() {
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="2.0" unique-identifier="uid">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf">
+    <dc:identifier opf:scheme="ISBN">9787115000000</dc:identifier>
+    <dc:title>北宋小厨师</dc:title>
+    <dc:creator opf:role="aut">南希北庆</dc:creator>
+    <meta name="cover" content="cover-image"/>
+    <dc:creator opf:role="edt">编辑</dc:creator>
+    <dc:identifier id="uid">urn:uuid:1234</dc:identifier>
+    <dc:language>zh</dc:language>
+    <dc:subject>历史</dc:subject>
+    <dc:subject>穿越</dc:subject>
+    <dc:date opf:event="publication">2010-05</dc:date>
+    <meta name="calibre:series" content="北宋"/>
+  </metadata>
+  <manifest>
+    <item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>
+    <item id="cover-image" href="Images/cover.jpg" media-type="image/jpeg"/>
+    <item id="chapter1" href="Text/chapter1.xhtml" media-type="application/xhtml+xml"/>
+  </manifest>
+  <spine toc="ncx">
+    <itemref idref="chapter1"/>
+  </spine>
+  <guide>
+    <reference type="text" title="正文" href="Text/chapter1.xhtml"/>
+  </guide>
+</package>"#;
+
+    let package = rust_epub::opf::Package::decode_xml(xml).unwrap();
+    assert_eq!(package.version(), Some(EpubVersion::V20));
+
+    let metadata = package.metadata();
+    assert_eq!(metadata.title, "北宋小厨师");
+    assert_eq!(
+        metadata.creator,
+        vec![Creator::new("南希北庆", "aut"), Creator::new("编辑", "edt")]
+    );
+    assert_eq!(metadata.subject, vec!["历史", "穿越"]);
+    assert_eq!(metadata.language, "zh");
+    assert_eq!(metadata.cover.as_deref(), Some("cover-image"));
+    assert_eq!(metadata.identifier.as_ref().unwrap().text, "urn:uuid:1234");
+    assert_eq!(metadata.date_published, Some(Date::month(2010, 5).unwrap()));
+    assert!(metadata.meta().is_empty());
+    assert_eq!(
+        metadata.collection,
+        vec![Collection::new("北宋", "series", "")]
+    );
+
+    assert_eq!(package.manifest().len(), 3);
+    assert_eq!(
+        package.manifest_item("chapter1").unwrap().href,
+        "Text/chapter1.xhtml"
+    );
+    assert_eq!(package.spine()[0].idref, "chapter1");
+    assert_eq!(package.guide()[0].href(), "Text/chapter1.xhtml");
+}
+
+fn translated_metadata() -> Metadata {
+    let mut metadata = Metadata::default();
+    metadata.set_title("魔戒");
+    metadata.add_creator(Creator {
+        file_as: String::from("Tolkien, J. R. R."),
+        ..Creator::new("J. R. R. Tolkien", "aut")
+    });
+    metadata.add_contributor(Creator::new("朱学恒", "trl"));
+    metadata.add_contributor(Creator::new("校对", ""));
+    metadata
+}
+
+#[test]
+fn test_encode_creators() {
+    let mut opf = rust_epub::opf::Package::new();
+    opf.set_metadata(translated_metadata());
+
+    let xml = opf.encode_xml(EpubVersion::V20).unwrap();
+    assert!(xml.contains(
+        "<dc:creator opf:role=\"aut\" opf:file-as=\"Tolkien, J. R. R.\">J. R. R. Tolkien</dc:creator>"
+    ));
+    assert!(xml.contains("<dc:contributor opf:role=\"trl\">朱学恒</dc:contributor>"));
+    assert!(xml.contains("<dc:contributor>校对</dc:contributor>"));
+    let decoded = rust_epub::opf::Package::decode_xml(&xml).unwrap();
+    assert_eq!(decoded.metadata().creator, translated_metadata().creator);
+    assert_eq!(
+        decoded.metadata().contributor,
+        translated_metadata().contributor
+    );
+
+    let xml = opf.encode_xml(EpubVersion::V30).unwrap();
+    assert!(xml.contains("<dc:creator id=\"creator-1\">J. R. R. Tolkien</dc:creator>"));
+    assert!(xml.contains(
+        "<meta property=\"role\" refines=\"#creator-1\" scheme=\"marc:relators\">aut</meta>"
+    ));
+    assert!(
+        xml.contains("<meta property=\"file-as\" refines=\"#creator-1\">Tolkien, J. R. R.</meta>")
+    );
+    assert!(xml.contains("<dc:contributor id=\"contributor-1\">朱学恒</dc:contributor>"));
+    assert!(xml.contains(
+        "<meta property=\"role\" refines=\"#contributor-1\" scheme=\"marc:relators\">trl</meta>"
+    ));
+    let decoded = rust_epub::opf::Package::decode_xml(&xml).unwrap();
+    assert_eq!(decoded.metadata().creator, translated_metadata().creator);
+    assert_eq!(
+        decoded.metadata().contributor,
+        translated_metadata().contributor
+    );
+    assert!(decoded
+        .metadata()
+        .meta()
+        .iter()
+        .all(|m| m.refines.is_empty()));
+
+    // set_contributor 替换已有的贡献者
+    let mut metadata = translated_metadata();
+    metadata.set_contributor("校对");
+    assert_eq!(metadata.contributor, vec![Creator::new("校对", "")]);
+}
+
+#[test]
+fn test_encode_collections() {
+    let mut metadata = Metadata::default();
+    metadata.set_title("梦回大明春 第二卷");
+    metadata.set_series("梦回大明", "1");
+    metadata.add_collection(Collection::new("历史穿越精选", "set", ""));
+    // 再次设置时替换原有系列
+    metadata.set_series("梦回大明春", "2");
+    assert_eq!(metadata.collection.len(), 2);
+    let mut opf = rust_epub::opf::Package::new();
+    opf.set_metadata(metadata);
+
+    let xml = opf.encode_xml(EpubVersion::V30).unwrap();
+    assert!(xml.contains(
+        "<meta property=\"belongs-to-collection\" id=\"collection-1\">梦回大明春</meta>"
+    ));
+    assert!(
+        xml.contains("<meta property=\"collection-type\" refines=\"#collection-1\">series</meta>")
+    );
+    assert!(xml.contains("<meta property=\"group-position\" refines=\"#collection-1\">2</meta>"));
+    assert!(xml.contains("<meta property=\"collection-type\" refines=\"#collection-2\">set</meta>"));
+    assert!(!xml.contains("<meta property=\"group-position\" refines=\"#collection-2\">"));
+    assert!(xml.contains("<meta name=\"calibre:series\" content=\"梦回大明春\"/>"));
+    assert!(xml.contains("<meta name=\"calibre:series_index\" content=\"2\"/>"));
+    let decoded = rust_epub::opf::Package::decode_xml(&xml).unwrap();
+    assert_eq!(decoded.metadata().collection, opf.metadata().collection);
+    assert!(decoded.metadata().meta().is_empty());
+
+    let xml = opf.encode_xml(EpubVersion::V20).unwrap();
+    assert!(!xml.contains("belongs-to-collection"));
+    assert!(xml.contains("<meta name=\"calibre:series_index\" content=\"2\"/>"));
+    let decoded = rust_epub::opf::Package::decode_xml(&xml).unwrap();
+    assert_eq!(
+        decoded.metadata().collection,
+        vec![Collection::new("梦回大明春", "series", "2")]
+    );
+}
+
+#[test]
+fn test_encode_titles() {
+    let mut main = Title::new("梦回大明春", "main");
+    main.lang = String::from("zh");
+    main.display_seq = Some(1);
+    main.add_alternate_script("zh-Latn-pinyin", "Meng Hui Da Ming Chun")
+        .add_alternate_script("en", "Dreaming of the Ming Spring");
+    let mut metadata = Metadata::default();
+    metadata.add_title(main);
+    metadata.add_title(Title::new("第二卷 蛮家子", "subtitle"));
+    let mut opf = rust_epub::opf::Package::new();
+    opf.set_metadata(metadata);
+
+    let xml = opf.encode_xml(EpubVersion::V30).unwrap();
+    assert!(xml.contains("<dc:title id=\"title-1\" xml:lang=\"zh\">梦回大明春</dc:title>"));
+    assert!(xml.contains("<meta property=\"title-type\" refines=\"#title-1\">main</meta>"));
+    assert!(xml.contains("<meta property=\"display-seq\" refines=\"#title-1\">1</meta>"));
+    assert!(xml.contains(
+        "<meta property=\"alternate-script\" refines=\"#title-1\" xml:lang=\"en\">Dreaming of the Ming Spring</meta>"
+    ));
+    assert!(xml.contains("<dc:title id=\"title-2\">第二卷 蛮家子</dc:title>"));
+    assert!(xml.contains("<meta property=\"title-type\" refines=\"#title-2\">subtitle</meta>"));
+    let decoded = rust_epub::opf::Package::decode_xml(&xml).unwrap();
+    assert_eq!(decoded.metadata().title, "梦回大明春");
+    assert_eq!(decoded.metadata().titles, opf.metadata().titles);
+    assert!(decoded.metadata().meta().is_empty());
+
+    let xml = opf.encode_xml(EpubVersion::V20).unwrap();
+    assert!(xml.contains("<dc:title xml:lang=\"zh\">梦回大明春</dc:title>"));
+    assert!(xml.contains("<dc:title>第二卷 蛮家子</dc:title>"));
+    assert!(!xml.contains("refines"));
+    let decoded = rust_epub::opf::Package::decode_xml(&xml).unwrap();
+    assert_eq!(decoded.metadata().title, "梦回大明春");
+    assert_eq!(decoded.metadata().titles.len(), 2);
+
+    // 重新写入时不重复主标题
+    let mut opf = rust_epub::opf::Package::new();
+    let mut metadata = Metadata::default();
+    metadata.set_title("梦回大明春");
+    metadata.titles = decoded.metadata().titles.clone();
+    opf.set_metadata(metadata);
+    let xml = opf.encode_xml(EpubVersion::V20).unwrap();
+    assert_eq!(xml.matches("<dc:title").count(), 2);
+}
+
+#[test]
+fn test_encode_dates() {
+    assert_eq!(Date::parse("2010"), Some(Date::year(2010).unwrap()));
+    assert_eq!(Date::parse("2010-13"), None);
+    assert_eq!(Date::parse("2010-02-30"), None);
+    // 超出 W3CDTF 范围的日期
+    assert!(Date::month(2010, 13).is_err());
+    assert!(Date::year(-5).is_err());
+    assert!(Date::year(10000).is_err());
+    assert!(Date::day(2010, 2, 30).is_err());
+    assert_eq!(Date::day(2010, 5, 1).unwrap().to_string(), "2010-05-01");
+    let ancient = chrono::NaiveDate::from_ymd_opt(-5, 3, 1).unwrap();
+    assert_eq!(Date::from(ancient).to_string(), "0000-01-01");
+    assert_eq!(
+        Date::parse("2010-05-01T08:00:00+08:00")
+            .unwrap()
+            .to_string(),
+        "2010-05-01T00:00:00Z"
+    );
+
+    let modified = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let mut metadata = Metadata::default();
+    metadata.set_title("北宋小厨师");
+    metadata.set_date_published(Date::month(2010, 5).unwrap());
+    metadata.set_date_created(chrono::NaiveDate::from_ymd_opt(2009, 12, 1).unwrap());
+    metadata.set_date_modified(modified);
+    let mut opf = rust_epub::opf::Package::new();
+    opf.set_metadata(metadata);
+
+    let xml = opf.encode_xml(EpubVersion::V30).unwrap();
+    assert!(xml.contains("<dc:date>2010-05</dc:date>"));
+    assert!(xml.contains("<meta property=\"dcterms:created\">2009-12-01</meta>"));
+    assert!(xml.contains("<meta property=\"dcterms:modified\">2023-11-14T22:13:20Z</meta>"));
+    let decoded = rust_epub::opf::Package::decode_xml(&xml).unwrap();
+    assert_eq!(
+        decoded.metadata().date_published,
+        Some(Date::month(2010, 5).unwrap())
+    );
+    assert_eq!(decoded.metadata().date_created, opf.metadata().date_created);
+    assert_eq!(decoded.metadata().date_modified, Some(modified));
+
+    let xml = opf.encode_xml(EpubVersion::V20).unwrap();
+    assert!(xml.contains("<dc:date opf:event=\"publication\">2010-05</dc:date>"));
+    assert!(xml.contains("<dc:date opf:event=\"creation\">2009-12-01</dc:date>"));
+    assert!(xml.contains("<dc:date opf:event=\"modification\">2023-11-14T22:13:20Z</dc:date>"));
+    assert!(!xml.contains("dcterms:modified"));
+    let decoded = rust_epub::opf::Package::decode_xml(&xml).unwrap();
+    assert_eq!(
+        decoded.metadata().date_published,
+        Some(Date::month(2010, 5).unwrap())
+    );
+    assert_eq!(decoded.metadata().date_created, opf.metadata().date_created);
+    assert_eq!(decoded.metadata().date_modified, Some(modified));
+}
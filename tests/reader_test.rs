@@ -0,0 +1,81 @@
+use rust_epub::epub::{EpubBuilder, EpubVersion};
+use rust_epub::reader::Book;
+use std::env;
+use std::fs;
+
+fn build_book(output_file: &std::path::Path, output_dir: &std::path::Path) {
+    let mut epub = EpubBuilder::new("梦回大明春", EpubVersion::V30);
+    let testdata = env::current_dir().unwrap().join("tests").join("testdata");
+    let image_path = testdata.join("cover.jpg").to_str().unwrap().to_string();
+
+    epub.set_id("urn:uuid:7d2c4ad7-1f2d-4c2a-8d1e-4b54e3f40f22")
+        .set_language("zh-CN")
+        .set_publisher("掌上书苑");
+    epub.set_cover(&image_path, None).unwrap();
+    let chapter = epub
+        .add_section("<p>第一卷内容</p>", "第一卷 蛮家子", None, None)
+        .unwrap();
+    epub.add_sub_section(Some(chapter), "<p>正文</p>", "第001章", None, None)
+        .unwrap();
+
+    epub.output_epub(output_file).unwrap();
+    epub.output(output_dir).unwrap();
+}
+
+#[test]
+fn test_open_book() {
+    let root = env::temp_dir().join(format!("rust-epub-reader-{}", std::process::id()));
+    let output_file = root.join("book.epub");
+    let output_dir = root.join("book");
+    build_book(&output_file, &output_dir);
+
+    for path in [&output_file, &output_dir] {
+        let book = Book::open(path).unwrap();
+        assert_eq!(book.version(), EpubVersion::V30);
+        assert_eq!(book.package_path(), "EPUB/content.opf");
+        assert_eq!(book.metadata().title, "梦回大明春");
+        assert_eq!(book.metadata().language, "zh-CN");
+        assert_eq!(book.metadata().publisher.as_deref(), Some("掌上书苑"));
+        assert_eq!(
+            book.metadata().identifier.as_ref().unwrap().text,
+            "urn:uuid:7d2c4ad7-1f2d-4c2a-8d1e-4b54e3f40f22"
+        );
+
+        let spine: Vec<&str> = book.spine_items().iter().map(|m| m.href.as_str()).collect();
+        assert_eq!(
+            spine,
+            vec![
+                "xhtml/cover.xhtml",
+                "xhtml/section_2.xhtml",
+                "xhtml/section_3.xhtml"
+            ]
+        );
+
+        let cover_id = book.metadata().cover.clone().unwrap();
+        let cover = book.read_id(&cover_id).unwrap();
+        assert_eq!(cover, fs::read("tests/testdata/cover.jpg").unwrap());
+
+        let section = book.resource_by_href("xhtml/section_2.xhtml#top").unwrap();
+        assert_eq!(section.media_type, "application/xhtml+xml");
+        let xhtml = book.read_href_to_string(&section.href).unwrap();
+        assert!(xhtml.contains("<p>第一卷内容</p>"));
+
+        let toc = book.toc().unwrap();
+        assert_eq!(toc.elements().len(), 2);
+        assert_eq!(toc.elements()[1].title, "第一卷 蛮家子");
+        assert_eq!(toc.elements()[1].childs[0].url, "xhtml/section_3.xhtml");
+
+        assert!(book.read_href("xhtml/missing.xhtml").is_err());
+        assert!(book
+            .file_names()
+            .contains(&String::from("META-INF/container.xml")));
+    }
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn test_open_missing_book() {
+    let ret = Book::open("tests/testdata/missing.epub");
+    assert!(ret.is_err());
+}
@@ -0,0 +1,78 @@
+use rust_epub::epub::{EpubBuilder, EpubVersion};
+use rust_epub::reader::Book;
+use rust_epub::text::TextImporter;
+
+const NOVEL: &str = "梦回大明春\n作者：某某\n\n\
+                     第一卷 蛮家子\n  卷首语\n\
+                     第001章 穿越\n\u{3000}\u{3000}第一段 A&B <C>\n\n\u{3000}\u{3000}第二段\n\
+                     第二章：落水\n第三段\n\
+                     第二卷 京城\n\
+                     第三章 进京\n第四段\n";
+
+#[test]
+fn test_parse_text() {
+    let sections = TextImporter::new().parse(NOVEL);
+    let titles: Vec<&str> = sections.iter().map(|s| s.title.as_str()).collect();
+    assert_eq!(titles, vec!["前言", "第一卷 蛮家子", "第二卷 京城"]);
+    assert_eq!(sections[0].paragraphs, vec!["梦回大明春", "作者：某某"]);
+    assert_eq!(sections[1].paragraphs, vec!["卷首语"]);
+    let chapters: Vec<&str> = sections[1]
+        .chapters
+        .iter()
+        .map(|s| s.title.as_str())
+        .collect();
+    assert_eq!(chapters, vec!["第001章 穿越", "第二章：落水"]);
+    assert_eq!(
+        sections[1].chapters[0].paragraphs,
+        vec!["第一段 A&B <C>", "第二段"]
+    );
+    assert_eq!(sections[2].chapters[0].title, "第三章 进京");
+
+    let mut importer = TextImporter::new();
+    importer
+        .set_volume_patterns(Vec::<&str>::new())
+        .unwrap()
+        .set_chapter_patterns([r"^Chapter \d+$"])
+        .unwrap();
+    let sections = importer.parse("Intro\nChapter 1\nOne\nChapter 2\nTwo\n");
+    let titles: Vec<&str> = sections.iter().map(|s| s.title.as_str()).collect();
+    assert_eq!(titles, vec!["前言", "Chapter 1", "Chapter 2"]);
+    assert!(importer.set_chapter_patterns(["("]).is_err());
+}
+
+#[test]
+fn test_decode_text() {
+    let importer = TextImporter::new();
+    let (gbk, _, _) = encoding_rs::GBK.encode(NOVEL);
+    assert_eq!(importer.decode(&gbk).unwrap(), NOVEL);
+    let mut bom = vec![0xEF, 0xBB, 0xBF];
+    bom.extend_from_slice(NOVEL.as_bytes());
+    assert_eq!(importer.decode(&bom).unwrap(), NOVEL);
+
+    let mut importer = TextImporter::new();
+    importer.set_encoding("gb18030").unwrap();
+    assert_eq!(importer.decode(&gbk).unwrap(), NOVEL);
+    assert!(importer.set_encoding("no-such-encoding").is_err());
+}
+
+#[test]
+fn test_import_text() {
+    let (gbk, _, _) = encoding_rs::GBK.encode(NOVEL);
+    let mut epub = EpubBuilder::new("梦回大明春", EpubVersion::V30);
+    let filenames = TextImporter::new().import(&mut epub, &gbk).unwrap();
+    assert_eq!(filenames.len(), 6);
+
+    let book = Book::from_bytes(epub.output_bytes().unwrap()).unwrap();
+    let toc = book.toc().unwrap();
+    let titles: Vec<&str> = toc.elements().iter().map(|e| e.title.as_str()).collect();
+    assert_eq!(titles, vec!["前言", "第一卷 蛮家子", "第二卷 京城"]);
+    assert_eq!(toc.elements()[1].childs.len(), 2);
+    assert_eq!(toc.elements()[1].childs[1].title, "第二章：落水");
+
+    let chapter = book
+        .read_href_to_string(&toc.elements()[1].childs[0].url)
+        .unwrap();
+    assert!(
+        chapter.contains("<h2>第001章 穿越</h2>\n<p>第一段 A&amp;B &lt;C&gt;</p>\n<p>第二段</p>")
+    );
+}
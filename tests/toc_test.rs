@@ -1,4 +1,3 @@
-use rust_epub;
 use rust_epub::epub::EpubVersion::*;
 use rust_epub::toc::*;
 
@@ -52,3 +51,209 @@ fn encode_nav_file() {
     assert!(ret.is_ok());
     println!("{}", ret.unwrap());
 }
+
+fn sample_nav() -> TocNav {
+    let mut nav = TocNav::new("梦回大明春", "zh");
+    nav.add_metadata("dtb:uid", "easypub-8a83e708");
+
+    let el1 = TocElement::new("Text/cover.xhtml", "封面");
+    let mut el2 = TocElement::new("Text/Chapter0002.xhtml", "第一卷 蛮家子");
+    el2.add_child(TocElement::new(
+        "Text/Chapter0003.xhtml#part1",
+        "第001章 工程狗是什么品种？",
+    ))
+    .add_child(TocElement::new(
+        "Text/Chapter0004.xhtml",
+        "第002章 土匪式拜师",
+    ));
+    nav.add_element(el1).add_element(el2);
+    nav
+}
+
+fn assert_sample_elements(toc: &TocNav) {
+    let elements = toc.elements();
+    assert_eq!(elements.len(), 2);
+    assert_eq!(elements[0].title, "封面");
+    assert_eq!(elements[0].url, "Text/cover.xhtml");
+    assert_eq!(elements[1].childs.len(), 2);
+    assert_eq!(elements[1].childs[0].url, "Text/Chapter0003.xhtml#part1");
+    assert_eq!(elements[1].childs[0].title, "第001章 工程狗是什么品种？");
+    assert_eq!(elements[1].childs[0].level, 2);
+}
+
+#[test]
+fn decode_ncx_file() {
+    let xml = sample_nav().encode_file(V20).unwrap();
+    let toc = TocNav::decode_ncx(&xml).unwrap();
+
+    assert_eq!(toc.title(), "梦回大明春");
+    assert_eq!(toc.lang(), "zh");
+    assert_eq!(toc.metadata("dtb:uid").as_deref(), Some("easypub-8a83e708"));
+    assert_sample_elements(&toc);
+}
+
+#[test]
+fn decode_nav_file() {
+    let xml = sample_nav().encode_file(V30).unwrap();
+    let toc = TocNav::decode_nav(&xml).unwrap();
+
+    assert_eq!(toc.title(), "梦回大明春");
+    assert_sample_elements(&toc);
+}
+
+#[test]
+fn decode_nav_file_with_landmarks() {
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" xml:lang="en">
+<head><title>Contents</title></head>
+<body>
+  <nav epub:type="landmarks"><ol><li><a epub:type="cover" href="cover.xhtml">Cover</a></li></ol></nav>
+  <nav epub:type="toc" id="toc">
+    <h2>Table of <em>Contents</em></h2>
+    <ol>
+      <li><span>Part I</span>
+        <ol>
+          <li><a href="ch1.xhtml"><span class="num">1.</span> Loomings &amp; more</a></li>
+          <li><a href="ch1.xhtml#sec2">The Carpet-Bag</a></li>
+        </ol>
+      </li>
+      <li><a href="ch2.xhtml">Chapter 2</a></li>
+    </ol>
+  </nav>
+</body>
+</html>"#;
+    let toc = TocNav::decode_nav(xml).unwrap();
+
+    assert_eq!(toc.title(), "Table of Contents");
+    assert_eq!(toc.lang(), "en");
+    let elements = toc.elements();
+    assert_eq!(elements.len(), 2);
+    assert_eq!(elements[0].title, "Part I");
+    assert_eq!(elements[0].url, "");
+    assert_eq!(elements[0].childs[0].title, "1. Loomings & more");
+    assert_eq!(elements[0].childs[0].url, "ch1.xhtml");
+    assert_eq!(elements[0].childs[1].url, "ch1.xhtml#sec2");
+    assert_eq!(elements[1].title, "Chapter 2");
+
+    assert!(
+        TocNav::decode_nav("<html><body><nav epub:type=\"landmarks\"/></body></html>").is_err()
+    );
+}
+
+#[test]
+fn encode_landmarks_and_page_list() {
+    let mut nav = TocNav::new("梦回大明春", "zh");
+    nav.add_element(TocElement::new("Text/Chapter0002.xhtml", "第一卷 蛮家子"))
+        .add_landmark(Landmark::new("cover", "Text/cover.xhtml", "封面"))
+        .add_landmark(Landmark::new(
+            "bodymatter",
+            "Text/Chapter0002.xhtml",
+            "正文",
+        ))
+        .add_page(PageTarget::new("iv", "Text/cover.xhtml#page_iv"))
+        .add_page(PageTarget::new("1", "Text/Chapter0002.xhtml#page_1"));
+
+    let xml = nav.encode_file(V30).unwrap();
+    assert!(xml.contains("<nav epub:type=\"landmarks\" id=\"landmarks\" hidden=\"\">"));
+    assert!(xml.contains("<a epub:type=\"cover\" href=\"Text/cover.xhtml\">封面</a>"));
+    assert!(xml.contains("<nav epub:type=\"page-list\" id=\"page-list\" hidden=\"\">"));
+    let decoded = TocNav::decode_nav(&xml).unwrap();
+    assert_eq!(decoded.elements().len(), 1);
+    assert_eq!(decoded.landmarks(), nav.landmarks());
+    assert_eq!(decoded.pages(), nav.pages());
+
+    let xml = nav.encode_file(V20).unwrap();
+    assert!(xml.contains("<pageTarget id=\"pageTarget-1\" type=\"front\" playOrder=\"2\">"));
+    assert!(xml
+        .contains("<pageTarget id=\"pageTarget-2\" type=\"normal\" value=\"1\" playOrder=\"3\">"));
+    let decoded = TocNav::decode_ncx(&xml).unwrap();
+    assert_eq!(decoded.pages(), nav.pages());
+    assert!(decoded.landmarks().is_empty());
+}
+
+#[test]
+fn encode_ncx_play_order() {
+    let mut part = TocElement::new("Text/part1.xhtml", "第一部分");
+    part.add_child(TocElement::new("Text/chapter1.xhtml", "第一章"))
+        .add_child(TocElement::new("Text/chapter1.xhtml#s1", "第一节"));
+    let mut nav = TocNav::new("梦回大明春", "zh");
+    nav.add_author("作者甲")
+        .add_element(part)
+        .add_element(TocElement::new("Text/chapter1.xhtml", "重复目标"))
+        .add_element(TocElement::new("Text/chapter2.xhtml", "第二章"))
+        .add_page(PageTarget::new("1", "Text/chapter2.xhtml"));
+
+    let xml = nav.encode_file(V20).unwrap();
+    assert!(xml.contains("<docAuthor>"));
+    assert!(xml.contains("<meta name=\"dtb:depth\" content=\"2\"/>"));
+    let order = |id: &str| {
+        let start = xml.find(&format!("id=\"{}\"", id)).unwrap();
+        let rest = &xml[start..];
+        let value = &rest[rest.find("playOrder=\"").unwrap() + 11..];
+        value[..value.find('"').unwrap()].parse::<i32>().unwrap()
+    };
+    assert_eq!(order("navPoint-1"), 1);
+    assert_eq!(order("navPoint-2"), 2);
+    assert_eq!(order("navPoint-3"), 3);
+    // 指向同一目标的节点共用 playOrder
+    assert_eq!(order("navPoint-4"), 2);
+    assert_eq!(order("navPoint-5"), 4);
+    assert_eq!(order("pageTarget-1"), 4);
+    assert!(!xml.contains("navPoint-6"));
+
+    let decoded = TocNav::decode_ncx(&xml).unwrap();
+    assert_eq!(decoded.authors(), nav.authors());
+    assert_eq!(decoded.elements().len(), 3);
+    assert_eq!(decoded.elements()[0].childs.len(), 2);
+    assert_eq!(decoded.elements()[0].childs[1].level, 2);
+}
+
+#[test]
+fn encode_ncx_page_order() {
+    let mut group = TocElement::new("", "附录");
+    group.add_child(TocElement::new("", "空白"));
+    let mut nav = TocNav::new("梦回大明春", "zh");
+    nav.add_element(TocElement::new("Text/chapter1.xhtml", "第一章"))
+        .add_element(TocElement::new("Text/chapter1.xhtml#s2", "第二节"))
+        .add_element(group)
+        .add_element(TocElement::new("Text/chapter2.xhtml", "第二章"))
+        .add_page(PageTarget::new("3", "Text/chapter2.xhtml#page_3"))
+        .add_page(PageTarget::new("1", "Text/chapter1.xhtml#page_1"))
+        .add_page(PageTarget::new("2", "Text/chapter1.xhtml#page_2"))
+        .add_page(PageTarget::new("4", "Text/missing.xhtml#page_4"))
+        .set_reading_order(
+            [
+                "Text/chapter1.xhtml",
+                "Text/chapter1.xhtml#page_1",
+                "Text/chapter1.xhtml#s2",
+                "Text/chapter1.xhtml#page_2",
+                "Text/chapter2.xhtml",
+                "Text/chapter2.xhtml#page_3",
+            ]
+            .map(String::from)
+            .to_vec(),
+        );
+
+    let xml = nav.encode_file(V20).unwrap();
+    let order = |src: &str| {
+        let end = xml.find(&format!("<content src=\"{}\"/>", src)).unwrap();
+        let start = xml[..end].rfind("playOrder=\"").unwrap() + 11;
+        xml[start..start + xml[start..].find('"').unwrap()]
+            .parse::<i32>()
+            .unwrap()
+    };
+    // 印刷页按阅读顺序插在目录节点之间
+    assert_eq!(order("Text/chapter1.xhtml"), 1);
+    assert_eq!(order("Text/chapter1.xhtml#page_1"), 2);
+    assert_eq!(order("Text/chapter1.xhtml#s2"), 3);
+    assert_eq!(order("Text/chapter1.xhtml#page_2"), 4);
+    assert_eq!(order("Text/chapter2.xhtml"), 5);
+    assert_eq!(order("Text/chapter2.xhtml#page_3"), 6);
+    assert_eq!(order("Text/missing.xhtml#page_4"), 7);
+    assert!(xml
+        .contains("<pageTarget id=\"pageTarget-1\" type=\"normal\" value=\"1\" playOrder=\"2\">"));
+    // 没有任何链接的分组不写入 NCX
+    assert!(!xml.contains("附录"));
+    assert!(!xml.contains("<content/>"));
+    assert!(!xml.contains("navPoint-4"));
+}
@@ -0,0 +1,137 @@
+use rust_epub::epub::{EpubBuilder, EpubVersion};
+use rust_epub::reader::Book;
+use rust_epub::validate::{validate_book, DiagnosticKind, Severity};
+use std::env;
+use std::fs;
+use std::io::{Cursor, Write};
+use std::time::{Duration, SystemTime};
+
+fn build_book() -> EpubBuilder {
+    let mut epub = EpubBuilder::new("大语言模型：辩论", EpubVersion::V30);
+    epub.set_id("urn:uuid:4f0b3b1e-8c4c-4c1e-9f5a-2d9b0c6e7a11")
+        .set_language("zh-CN")
+        .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+    epub.add_section("<p>测试章节内容</p>", "测试章节", None, None)
+        .unwrap();
+    epub
+}
+
+#[test]
+fn test_validate_builder() {
+    let report = build_book().validate().unwrap();
+    assert!(report.is_valid(), "{:?}", report.diagnostics());
+    assert_eq!(report.diagnostics().len(), 0);
+}
+
+#[test]
+fn test_validate_builder_metadata() {
+    let mut epub = EpubBuilder::new("", EpubVersion::V30);
+    epub.add_section("<p>正文</p>", "正文", None, None).unwrap();
+    let report = epub.validate().unwrap();
+
+    assert!(!report.is_valid());
+    let diagnostic = report.of_kind(DiagnosticKind::MissingTitle).next().unwrap();
+    assert_eq!(diagnostic.severity, Severity::Error);
+    assert_eq!(diagnostic.location.file, "EPUB/content.opf");
+    assert_eq!(report.of_kind(DiagnosticKind::MissingLanguage).count(), 0);
+    // 没有标识符时生成 UUID，没有修改时间时使用当前时间
+    assert_eq!(report.of_kind(DiagnosticKind::MissingIdentifier).count(), 0);
+    assert_eq!(report.of_kind(DiagnosticKind::MissingModified).count(), 0);
+}
+
+#[test]
+fn test_validate_broken_book() {
+    let root = env::temp_dir().join(format!("rust-epub-validate-{}", std::process::id()));
+    let files = [
+        ("mimetype", "application/zip"),
+        (
+            "META-INF/container.xml",
+            r#"<?xml version="1.0"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>"#,
+        ),
+        (
+            "OEBPS/content.opf",
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<package version="2.0" unique-identifier="uid" xmlns="http://www.idpf.org/2007/opf">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:title>Broken</dc:title>
+    <dc:language>en</dc:language>
+    <dc:identifier id="uid">broken-1</dc:identifier>
+  </metadata>
+  <manifest>
+    <item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="ch1" href="ch2.xhtml" media-type="application/xhtml+xml"/>
+    <item id="notes" href="notes.xhtml" media-type="application/xhtml+xml"/>
+    <item id="img" href="missing.png" media-type="image/png"/>
+  </manifest>
+  <spine>
+    <itemref idref="ch1"/>
+    <itemref idref="ch9"/>
+    <itemref idref="img"/>
+  </spine>
+</package>"#,
+        ),
+        ("OEBPS/ch1.xhtml", "<html/>"),
+        ("OEBPS/ch2.xhtml", "<html/>"),
+        ("OEBPS/notes.xhtml", "<html/>"),
+    ];
+    for (name, content) in files {
+        let path = root.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    let report = validate_book(&Book::open(&root).unwrap());
+    let kinds: Vec<DiagnosticKind> = report.diagnostics().iter().map(|d| d.kind).collect();
+    assert!(kinds.contains(&DiagnosticKind::InvalidMimetype));
+    assert!(kinds.contains(&DiagnosticKind::DuplicateId));
+    assert!(kinds.contains(&DiagnosticKind::UnknownSpineItem));
+    assert!(kinds.contains(&DiagnosticKind::MissingNavigation));
+    assert!(!kinds.contains(&DiagnosticKind::MissingModified));
+
+    let missing = report.of_kind(DiagnosticKind::MissingFile).next().unwrap();
+    assert_eq!(missing.location.file, "OEBPS/missing.png");
+    let warning = report
+        .of_kind(DiagnosticKind::NonContentSpineItem)
+        .next()
+        .unwrap();
+    assert_eq!(warning.severity, Severity::Warning);
+    let warning = report
+        .of_kind(DiagnosticKind::ContentNotInSpine)
+        .next()
+        .unwrap();
+    assert_eq!(warning.location.file, "OEBPS/notes.xhtml");
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn test_validate_mimetype_entry() {
+    let bytes = build_book().output_bytes().unwrap();
+    let mut source = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+
+    // 重新打包，mimetype 放在最后并压缩
+    let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = zip::write::SimpleFileOptions::default();
+    let mut names: Vec<String> = source.file_names().map(String::from).collect();
+    names.sort_by_key(|name| name == "mimetype");
+    for name in names {
+        let mut content = Vec::new();
+        std::io::copy(&mut source.by_name(&name).unwrap(), &mut content).unwrap();
+        writer.start_file(name, options).unwrap();
+        writer.write_all(&content).unwrap();
+    }
+    let bytes = writer.finish().unwrap().into_inner();
+
+    let report = validate_book(&Book::from_bytes(bytes).unwrap());
+    let diagnostic = report
+        .of_kind(DiagnosticKind::InvalidMimetype)
+        .next()
+        .unwrap();
+    assert_eq!(diagnostic.location.file, "mimetype");
+    assert!(diagnostic.message.contains("first entry"));
+}
@@ -0,0 +1,133 @@
+use rust_epub::epub::{EpubBuilder, EpubVersion};
+use rust_epub::reader::Book;
+use rust_epub::write::{MemoryStorage, Progress, Storage, ZipStorage};
+use std::env;
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+fn build_book() -> EpubBuilder {
+    let testdata = env::current_dir().unwrap().join("tests").join("testdata");
+    let css_path = testdata.join("style.css").to_str().unwrap().to_string();
+    let image_path = testdata.join("cover.jpg").to_str().unwrap().to_string();
+
+    let mut epub = EpubBuilder::new("大语言模型：辩论", EpubVersion::V30);
+    epub.add_section("<p>测试章节内容</p>", "测试章节", None, Some(css_path))
+        .unwrap();
+    epub.add_image(&image_path, None).unwrap();
+    epub
+}
+
+#[test]
+fn test_write_to_memory() {
+    let mut storage = MemoryStorage::new();
+    build_book().write_to(&mut storage).unwrap();
+
+    assert_eq!(
+        storage.file("mimetype"),
+        Some("application/epub+zip".as_bytes())
+    );
+    assert!(storage.file("META-INF/container.xml").is_some());
+    assert!(storage.file("EPUB/content.opf").is_some());
+    assert!(storage.file("EPUB/nav.xhtml").is_some());
+    assert!(storage.file("EPUB/css/style.css").is_some());
+    assert_eq!(
+        storage.file("EPUB/images/cover.jpg").unwrap(),
+        std::fs::read("tests/testdata/cover.jpg").unwrap()
+    );
+    let section = String::from_utf8(storage.file("EPUB/xhtml/section_1.xhtml").unwrap().to_vec());
+    assert!(section.unwrap().contains("<p>测试章节内容</p>"));
+    assert_eq!(storage.folders()[0], "META-INF");
+    assert!(storage.folders().contains(&String::from("EPUB/images")));
+}
+
+#[test]
+fn test_output_bytes() {
+    let bytes = build_book().output_bytes().unwrap();
+
+    let book = Book::from_bytes(bytes).unwrap();
+    assert_eq!(book.metadata().title, "大语言模型：辩论");
+    assert_eq!(book.spine_items()[0].href, "xhtml/section_1.xhtml");
+}
+
+#[test]
+fn test_zip_storage() {
+    let mut storage = ZipStorage::new(Cursor::new(Vec::new()));
+    storage
+        .write_file("mimetype", b"application/epub+zip")
+        .unwrap();
+    storage.add_folder("META-INF").unwrap();
+    storage
+        .write_file("META-INF/container.xml", b"<container/>")
+        .unwrap();
+    let bytes = storage.finish().unwrap().into_inner();
+
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+    let mimetype = archive.by_index(0).unwrap();
+    assert_eq!(mimetype.name(), "mimetype");
+    assert_eq!(mimetype.compression(), zip::CompressionMethod::Stored);
+    drop(mimetype);
+    let container = archive.by_name("META-INF/container.xml").unwrap();
+    assert_eq!(container.compression(), zip::CompressionMethod::Deflated);
+}
+
+#[test]
+fn test_reproducible_output() {
+    // 可复现模式下生成的 UUID 由书名计算
+    let output = || build_book().set_reproducible(true).output_bytes().unwrap();
+    assert_eq!(output(), output());
+
+    // 只有这个测试读取 SOURCE_DATE_EPOCH
+    env::set_var("SOURCE_DATE_EPOCH", "1700000000");
+    let mut epub = build_book();
+    epub.set_reproducible(true);
+    let bytes = epub.output_bytes().unwrap();
+    env::remove_var("SOURCE_DATE_EPOCH");
+
+    let book = Book::from_bytes(bytes.clone()).unwrap();
+    let modified = book.metadata().date_modified.unwrap();
+    assert_eq!(modified.timestamp(), 1_700_000_000);
+
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+    for index in 0..archive.len() {
+        let entry = archive.by_index(index).unwrap();
+        let time = entry.last_modified().unwrap();
+        assert_eq!((time.year(), time.month(), time.day()), (2023, 11, 14));
+    }
+}
+
+#[derive(Default)]
+struct Recorder {
+    events: Arc<Mutex<Vec<String>>>,
+}
+
+impl Progress for Recorder {
+    fn on_section(&mut self, filename: &str, index: usize, total: usize) {
+        let event = format!("section {} {}/{}", filename, index, total);
+        self.events.lock().unwrap().push(event);
+    }
+    fn on_media(&mut self, name: &str, index: usize, total: usize) {
+        let event = format!("media {} {}/{}", name, index, total);
+        self.events.lock().unwrap().push(event);
+    }
+    fn on_entry(&mut self, name: &str, _size: usize) {
+        self.events.lock().unwrap().push(format!("entry {}", name));
+    }
+}
+
+#[test]
+fn test_progress() {
+    let recorder = Recorder::default();
+    let events = recorder.events.clone();
+    let mut epub = build_book();
+    epub.set_progress(recorder);
+    epub.write_to(&mut MemoryStorage::new()).unwrap();
+
+    let events = events.lock().unwrap();
+    assert_eq!(events[0], "entry mimetype");
+    assert!(events.contains(&String::from("section section_1.xhtml 1/1")));
+    assert!(events.contains(&String::from("media EPUB/css/style.css 1/2")));
+    assert!(events.contains(&String::from("media EPUB/images/cover.jpg 2/2")));
+    let position = |event: &str| events.iter().position(|e| e == event).unwrap();
+    assert!(position("section section_1.xhtml 1/1") < position("entry EPUB/xhtml/section_1.xhtml"));
+    assert_eq!(events.last().unwrap(), "entry EPUB/images/cover.jpg");
+}
@@ -1,4 +1,7 @@
-use rust_epub::xhtml::{StyleContent, XHtmlLinkItem, XHtmlRoot};
+use rust_epub::validate::DiagnosticKind;
+use rust_epub::xhtml::{
+    check_body, repair_body, sanitize_html, Sanitizer, StyleContent, XHtmlLinkItem, XHtmlRoot,
+};
 #[test]
 fn test_encode_xml() {
     let mut html = XHtmlRoot::default();
@@ -7,7 +10,7 @@ fn test_encode_xml() {
     html.add_link(XHtmlLinkItem::new(
         "https://www.epubit.com/book/25106",
         "text/html",
-        "",
+        None,
     ));
 
     html.add_style_content("body {font-size: 14px;}");
@@ -20,3 +23,132 @@ fn test_encode_xml() {
 
     println!("ret: {:?}", ret);
 }
+
+#[test]
+fn test_decode_xml() {
+    let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xml:lang="en">
+<head>
+  <title>Chapter &amp; Verse</title>
+  <link href="../css/style.css" rel="stylesheet" type="text/css"/>
+</head>
+<body dir="ltr"><div><p>Hello&nbsp;<b>world</b></p></div><br/></body>
+</html>"#;
+    let root = XHtmlRoot::decode_xml(xml).unwrap();
+    assert_eq!(root.title(), "Chapter & Verse");
+    assert_eq!(root.links().len(), 1);
+    assert_eq!(root.links()[0].href(), "../css/style.css");
+    assert_eq!(
+        root.body(),
+        "<div><p>Hello&nbsp;<b>world</b></p></div><br/>"
+    );
+}
+
+#[test]
+fn test_check_body() {
+    assert!(check_body("<p>Hello&#160;<b>world</b></p>\n<br/>").is_empty());
+
+    let body = "<div>\n  <p>Hello&nbsp;world\n</div>\u{1}";
+    let diagnostics = check_body(body);
+    let found: Vec<_> = diagnostics
+        .iter()
+        .map(|d| (d.kind, d.location.line, d.location.column))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            (DiagnosticKind::MalformedMarkup, Some(2), Some(3)),
+            (DiagnosticKind::UndeclaredEntity, Some(2), Some(11)),
+            (DiagnosticKind::InvalidCharacter, Some(3), Some(7)),
+        ]
+    );
+
+    // 属性之间缺少空白、属性名不合法
+    let diagnostics = check_body("<p a=\"1\"b=\"2\">x</p>\n<p @click=\"x\">y</p>");
+    let found: Vec<_> = diagnostics
+        .iter()
+        .map(|d| (d.location.line, d.location.column, d.message.as_str()))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            (Some(1), Some(9), "Missing whitespace before attribute b"),
+            (Some(2), Some(4), "Invalid attribute name @click"),
+        ]
+    );
+}
+
+#[test]
+fn test_repair_body() {
+    let body = "<div>\n  <p class=note>Hello&nbsp;A&B<br>\n</div></span>\u{1}";
+    let repaired = repair_body(body);
+    assert_eq!(
+        repaired,
+        "<div>\n  <p class=\"note\">Hello&#160;A&amp;B<br/>\n</p></div>"
+    );
+    assert!(check_body(&repaired).is_empty());
+
+    // 脚本和样式的内容按原样保留，含有 `<` 时放入 CDATA
+    let repaired = repair_body("<script>if (a < b) { document.write('<p>x</p>') }</script><p>y");
+    assert_eq!(
+        repaired,
+        "<script><![CDATA[if (a < b) { document.write('<p>x</p>') }]]></script><p>y</p>"
+    );
+    assert!(check_body(&repaired).is_empty());
+    assert_eq!(repair_body("<style>p{}</style>"), "<style>p{}</style>");
+    assert_eq!(
+        repair_body("<p a=\"1\"b=\"2\" @click=\"x\">y</p>"),
+        "<p a=\"1\" b=\"2\">y</p>"
+    );
+
+    // 与 HTML 一样，新段落和列表项隐式结束前一个
+    assert_eq!(repair_body("<p>一<p>二"), "<p>一</p><p>二</p>");
+    assert_eq!(
+        repair_body("<UL><LI>a<Li>b</ul><P>c<div>d</div>"),
+        "<UL><LI>a</LI><Li>b</Li></UL><P>c</P><div>d</div>"
+    );
+}
+
+#[test]
+fn test_sanitize_html() {
+    let html = "<!DOCTYPE html><html><head><title>t</title><style>p{}</style></head>\n\
+                <BODY onload=\"init()\"><!-- ad --><script>if (a < b) { document.write('<p>x</p>') }</script>\n\
+                <P CLASS=lead style=\"color:red\">Tom&nbsp;&amp;&nbsp;Jerry <font color=red>&copy; 2024</font>\n\
+                <p>Second<br><img src=pic.jpg alt=Pic onerror=\"alert(1)\">\n\
+                <ul><li>One<li>Two</ul>\n\
+                <a href=\"javascript:alert(1)\" title=\"A &quot;quote&quot;\">link</a> & more\n\
+                </BODY></html>";
+    let body = sanitize_html(html);
+    assert_eq!(
+        body,
+        "\n\n<p class=\"lead\">Tom&#160;&amp;&#160;Jerry &#169; 2024\n</p>\
+         <p>Second<br/><img src=\"pic.jpg\" alt=\"Pic\"/>\n</p>\
+         <ul><li>One</li><li>Two</li></ul>\n\
+         <a title=\"A &quot;quote&quot;\">link</a> &amp; more\n"
+    );
+    assert!(check_body(&body).is_empty());
+
+    let mut sanitizer = Sanitizer::new();
+    sanitizer
+        .allow_element("font")
+        .allow_attribute("font", "color")
+        .remove_element("table")
+        .deny_attribute("*", "class");
+    assert_eq!(
+        sanitizer.sanitize("<p class=x><font color=red>red</font></p><table><tr><td>cell</table>"),
+        "<p><font color=\"red\">red</font></p>"
+    );
+
+    // script 的内容是原始文本，其中的标签不计入嵌套
+    assert_eq!(
+        sanitize_html(
+            "<p>a</p><script>document.write('<script src=x></scr'+'ipt>')</script><p>kept?</p>"
+        ),
+        "<p>a</p><p>kept?</p>"
+    );
+    assert_eq!(
+        sanitize_html("<STYLE>p::after{content:'</p><style>'}</Style ><p>b</p><script>x</scripts>"),
+        "<p>b</p>"
+    );
+}